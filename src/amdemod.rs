@@ -1,25 +1,291 @@
-pub struct SquaringAMDemodulator<'a> {
+//! Demodulators for the 2400Hz AM subcarrier carrying the image.
+
+use std::cell::RefCell;
+use std::f32::consts::PI;
+use std::fmt;
+use std::rc::Rc;
+use std::str::FromStr;
+
+use subcarrier::{SubcarrierEstimator, SubcarrierOffset};
+
+/// Frequency of the AM subcarrier in Hz.
+pub const SUBCARRIER_FREQUENCY: f32 = 2400.0;
+
+/// AM demodulator working on one sample at a time.
+pub trait AMDemodulator {
+    /// Demodulates the next sample and returns the envelope of the signal.
+    fn demodulate(&mut self, sample: f32) -> f32;
+}
+
+/// Available AM demodulators.
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub enum AMDemodulatorKind {
+    /// [`SquaringAMDemodulator`]
+    #[default]
+    Squaring,
+    /// [`HilbertAMDemodulator`]
+    Hilbert,
+    /// [`SynchronousAMDemodulator`]
+    Synchronous,
+    /// [`TrackingAMDemodulator`]
+    Tracking,
+}
+
+impl AMDemodulatorKind {
+    /// Creates the demodulator for signals sampled at `sample_rate`.
+    pub fn create(&self, sample_rate: u32) -> Box<dyn AMDemodulator> {
+        match self {
+            AMDemodulatorKind::Squaring => Box::new(SquaringAMDemodulator),
+            AMDemodulatorKind::Hilbert => Box::new(HilbertAMDemodulator::new(sample_rate)),
+            AMDemodulatorKind::Synchronous => Box::new(SynchronousAMDemodulator::new(sample_rate)),
+            AMDemodulatorKind::Tracking => Box::new(TrackingAMDemodulator::new(sample_rate)),
+        }
+    }
+}
+
+impl FromStr for AMDemodulatorKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "squaring" => Ok(AMDemodulatorKind::Squaring),
+            "hilbert" => Ok(AMDemodulatorKind::Hilbert),
+            "synchronous" => Ok(AMDemodulatorKind::Synchronous),
+            "tracking" => Ok(AMDemodulatorKind::Tracking),
+            _ => Err(format!("Unknown demodulator {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for AMDemodulatorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            AMDemodulatorKind::Squaring => "Squaring",
+            AMDemodulatorKind::Hilbert => "Hilbert envelope",
+            AMDemodulatorKind::Synchronous => "Synchronous",
+            AMDemodulatorKind::Tracking => "Frequency tracking",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Applies an [`AMDemodulator`] to every sample of a signal.
+pub struct AMDemodulation<'a> {
+    demodulator: Box<dyn AMDemodulator + 'a>,
     iterator: Box<dyn Iterator<Item = f32> + 'a>,
 }
 
-impl<'a> SquaringAMDemodulator<'a> {
-    pub fn from<I>(iterator1: I) -> SquaringAMDemodulator<'a>
+impl<'a> AMDemodulation<'a> {
+    pub fn from<I>(iterator: I, demodulator: Box<dyn AMDemodulator + 'a>) -> AMDemodulation<'a>
     where
         I: Iterator<Item = f32> + 'a,
     {
-        SquaringAMDemodulator {
-            iterator: Box::new(iterator1),
+        AMDemodulation {
+            demodulator,
+            iterator: Box::new(iterator),
         }
     }
 }
 
-impl<'a> Iterator for SquaringAMDemodulator<'a> {
+impl<'a> Iterator for AMDemodulation<'a> {
     type Item = f32;
 
     fn next(&mut self) -> Option<Self::Item> {
-        match self.iterator.next() {
-            Some(x) => Some((x * x).sqrt()),
-            None => None,
+        let sample = self.iterator.next()?;
+        Some(self.demodulator.demodulate(sample))
+    }
+}
+
+/// AM demodulator squaring the input and taking the square root of the result.
+///
+/// The output contains the original signal and a sideband at twice the carrier frequency,
+/// that has to be removed by a low pass afterwards.
+pub struct SquaringAMDemodulator;
+
+impl AMDemodulator for SquaringAMDemodulator {
+    fn demodulate(&mut self, sample: f32) -> f32 {
+        (sample * sample).sqrt()
+    }
+}
+
+// The Hilbert transformer has to reach down to the lower edge of the sidebands around 300Hz.
+const HILBERT_MIN_FREQUENCY: f32 = 150.0;
+
+/// AM demodulator taking the magnitude of the analytic signal.
+///
+/// The quadrature component is calculated by a windowed Hilbert transform FIR filter,
+/// the in phase component is the input delayed by the same amount as the filter.
+/// Unlike rectification this yields the envelope without components at twice the carrier frequency.
+pub struct HilbertAMDemodulator {
+    coeffs: Vec<f32>,
+    state: Vec<f32>,
+    pos: usize,
+}
+
+impl HilbertAMDemodulator {
+    pub fn new(sample_rate: u32) -> HilbertAMDemodulator {
+        // Odd number of taps, so the delay is a whole number of samples
+        let taps = (sample_rate as f32 / HILBERT_MIN_FREQUENCY) as usize | 1;
+        let center = (taps / 2) as i32;
+
+        let coeffs = (0..taps)
+            .map(|i| {
+                let n = i as i32 - center;
+                if n % 2 == 0 {
+                    0.0
+                } else {
+                    let window = 0.42 - 0.5 * (2.0 * PI * i as f32 / (taps - 1) as f32).cos()
+                        + 0.08 * (4.0 * PI * i as f32 / (taps - 1) as f32).cos();
+                    2.0 / (PI * n as f32) * window
+                }
+            })
+            .collect();
+
+        HilbertAMDemodulator {
+            coeffs,
+            state: vec![0.0; taps],
+            pos: 0,
         }
     }
 }
+
+impl AMDemodulator for HilbertAMDemodulator {
+    fn demodulate(&mut self, sample: f32) -> f32 {
+        let taps = self.coeffs.len();
+        self.state[self.pos] = sample;
+        self.pos = (self.pos + 1) % taps;
+
+        // state[pos] is the oldest sample now
+        let mut quadrature = 0.0;
+        for (i, coeff) in self.coeffs.iter().enumerate() {
+            if *coeff != 0.0 {
+                quadrature += coeff * self.state[(self.pos + taps - 1 - i) % taps];
+            }
+        }
+        let in_phase = self.state[(self.pos + taps / 2) % taps];
+
+        (in_phase * in_phase + quadrature * quadrature).sqrt()
+    }
+}
+
+// Natural frequency and damping of the carrier tracking loop.
+const PLL_BANDWIDTH: f32 = 10.0;
+const PLL_DAMPING: f32 = 0.707;
+// Cut off frequency of the low pass in front of the phase detector.
+const PLL_DETECTOR_CUTOFF: f32 = 500.0;
+// The subcarrier frequency is not tracked further than this from its nominal value.
+const PLL_MAX_OFFSET: f32 = 100.0;
+
+/// AM demodulator mixing the signal with a carrier recovered by a phase locked loop.
+///
+/// The in phase component of the mixed signal is the envelope,
+/// noise in the quadrature component is discarded instead of adding to the envelope.
+/// The output contains a sideband at twice the carrier frequency,
+/// that has to be removed by a low pass afterwards.
+pub struct SynchronousAMDemodulator {
+    sample_time: f32,
+    phase: f32,
+    frequency: f32,
+    nominal_frequency: f32,
+    max_offset: f32,
+    detector_alpha: f32,
+    in_phase: f32,
+    quadrature: f32,
+    proportional_gain: f32,
+    integral_gain: f32,
+}
+
+impl SynchronousAMDemodulator {
+    pub fn new(sample_rate: u32) -> SynchronousAMDemodulator {
+        let sample_time = 1.0 / sample_rate as f32;
+        let natural_frequency = 2.0 * PI * PLL_BANDWIDTH * sample_time;
+        let nominal_frequency = 2.0 * PI * SUBCARRIER_FREQUENCY * sample_time;
+
+        SynchronousAMDemodulator {
+            sample_time,
+            phase: 0.0,
+            frequency: nominal_frequency,
+            nominal_frequency,
+            max_offset: 2.0 * PI * PLL_MAX_OFFSET * sample_time,
+            detector_alpha: 1.0 - (-2.0 * PI * PLL_DETECTOR_CUTOFF * sample_time).exp(),
+            in_phase: 0.0,
+            quadrature: 0.0,
+            proportional_gain: 2.0 * PLL_DAMPING * natural_frequency,
+            integral_gain: natural_frequency * natural_frequency,
+        }
+    }
+}
+
+impl SynchronousAMDemodulator {
+    /// Moves the frequency range the carrier is tracked in to `frequency` in Hz.
+    pub fn set_carrier_frequency(&mut self, frequency: f32) {
+        self.nominal_frequency = 2.0 * PI * frequency * self.sample_time;
+        self.frequency = self.frequency.clamp(
+            self.nominal_frequency - self.max_offset,
+            self.nominal_frequency + self.max_offset,
+        );
+    }
+}
+
+impl AMDemodulator for SynchronousAMDemodulator {
+    fn demodulate(&mut self, sample: f32) -> f32 {
+        let (sin, cos) = self.phase.sin_cos();
+        let in_phase = 2.0 * sample * cos;
+        let quadrature = -2.0 * sample * sin;
+
+        self.in_phase += self.detector_alpha * (in_phase - self.in_phase);
+        self.quadrature += self.detector_alpha * (quadrature - self.quadrature);
+        let error = self.quadrature.atan2(self.in_phase);
+
+        self.frequency = (self.frequency + self.integral_gain * error).clamp(
+            self.nominal_frequency - self.max_offset,
+            self.nominal_frequency + self.max_offset,
+        );
+        self.phase += self.frequency + self.proportional_gain * error;
+        if self.phase > PI {
+            self.phase -= 2.0 * PI;
+        } else if self.phase < -PI {
+            self.phase += 2.0 * PI;
+        }
+
+        in_phase
+    }
+}
+
+/// Synchronous AM demodulator following the subcarrier frequency measured by a [`SubcarrierEstimator`].
+///
+/// The estimator steers the range the phase locked loop searches the carrier in,
+/// so recordings with the subcarrier far from 2400Hz can still be demodulated.
+/// The measured offsets are collected and can be read using [`TrackingAMDemodulator::offsets`].
+pub struct TrackingAMDemodulator {
+    estimator: SubcarrierEstimator,
+    demodulator: SynchronousAMDemodulator,
+    offsets: Rc<RefCell<Vec<SubcarrierOffset>>>,
+}
+
+impl TrackingAMDemodulator {
+    pub fn new(sample_rate: u32) -> TrackingAMDemodulator {
+        TrackingAMDemodulator {
+            estimator: SubcarrierEstimator::new(sample_rate),
+            demodulator: SynchronousAMDemodulator::new(sample_rate),
+            offsets: Rc::new(RefCell::new(Vec::new())),
+        }
+    }
+
+    /// Shared list of the subcarrier offsets measured so far.
+    pub fn offsets(&self) -> Rc<RefCell<Vec<SubcarrierOffset>>> {
+        self.offsets.clone()
+    }
+}
+
+impl AMDemodulator for TrackingAMDemodulator {
+    fn demodulate(&mut self, sample: f32) -> f32 {
+        if let Some(offset) = self.estimator.push(sample) {
+            self.demodulator
+                .set_carrier_frequency(SUBCARRIER_FREQUENCY + offset.offset);
+            self.offsets.borrow_mut().push(offset);
+        }
+
+        self.demodulator.demodulate(sample)
+    }
+}
@@ -1,3 +1,5 @@
+use std::collections::VecDeque;
+
 const SYNC_LENGHT: usize = 40;
 const SYNCA_SEQ: [bool; 40] = [
     false, false, false, false, true, true, false, false, // Pulse 1
@@ -16,98 +18,313 @@ const SYNCB_SEQ: [bool; 40] = [
     true, true, true, false, false, true, true, true, false, false, false,
 ];
 
+const LINE_LENGTH: u64 = 2080;
+
+// A sync has to be the best match within this many samples in both directions.
+const PEAK_DISTANCE: usize = 20;
+// Once locked, the next sync is expected one line later, give or take this many samples.
+const SEARCH_WINDOW: u64 = 5;
+// Correlation needed to accept a sync anywhere, respectively inside the search window.
+const ACQUIRE_THRESHOLD: f32 = 0.7;
+const TRACK_THRESHOLD: f32 = 0.3;
+// Number of lines without a sync, before the lock is dropped.
+const MAX_MISSED_LINES: u32 = 8;
+
+/// Pixel emitted by [`APTSyncer`], tagged with the sync marker it belongs to.
 pub enum SyncedSample {
+    /// Regular pixel
     Sample(f32),
-    SyncA(f32),
-    SyncB(f32),
+    /// First pixel of the sync A marker, start of channel A
+    SyncA {
+        sample: f32,
+        /// Normalized correlation with the sync pattern (0.0 - 1.0)
+        confidence: f32,
+    },
+    /// First pixel of the sync B marker, start of channel B
+    SyncB {
+        sample: f32,
+        /// Normalized correlation with the sync pattern (0.0 - 1.0)
+        confidence: f32,
+    },
+}
+
+// Zero mean template of a sync pattern with unit energy.
+fn template(seq: &[bool; SYNC_LENGHT]) -> [f32; SYNC_LENGHT] {
+    let mean = seq.iter().filter(|x| **x).count() as f32 / SYNC_LENGHT as f32;
+    let mut template = [0.0; SYNC_LENGHT];
+    for (t, x) in template.iter_mut().zip(seq.iter()) {
+        *t = if *x { 1.0 } else { 0.0 } - mean;
+    }
+    let norm = template.iter().map(|t| t * t).sum::<f32>().sqrt();
+    for t in template.iter_mut() {
+        *t /= norm;
+    }
+    template
 }
 
+// Tracks the position of one of the sync markers from line to line.
+struct SyncTracker {
+    expected: Option<u64>,
+    missed: u32,
+}
+
+impl SyncTracker {
+    fn new() -> SyncTracker {
+        SyncTracker {
+            expected: None,
+            missed: 0,
+        }
+    }
+
+    // Decides if there is a sync at `pos`, given the correlation and whether it is a local peak.
+    fn update(&mut self, pos: u64, correlation: f32, is_peak: bool) -> bool {
+        let in_window = match self.expected {
+            Some(expected) => pos + SEARCH_WINDOW >= expected && pos <= expected + SEARCH_WINDOW,
+            None => false,
+        };
+
+        let threshold = if in_window {
+            TRACK_THRESHOLD
+        } else {
+            ACQUIRE_THRESHOLD
+        };
+
+        if is_peak && correlation >= threshold {
+            self.expected = Some(pos + LINE_LENGTH);
+            self.missed = 0;
+            return true;
+        }
+
+        // No sync found in the window, expect it one line later
+        if let Some(expected) = self.expected {
+            if pos == expected + SEARCH_WINDOW {
+                self.missed += 1;
+                self.expected = if self.missed > MAX_MISSED_LINES {
+                    None
+                } else {
+                    Some(expected + LINE_LENGTH)
+                };
+            }
+        }
+
+        false
+    }
+}
+
+/// Detects the sync A and sync B markers in a signal at the pixel rate of 4160Hz.
+///
+/// The signal is cross correlated with both sync patterns,
+/// a sync is detected where the normalized correlation peaks above a threshold.
+/// After the first sync, the next one is searched for one line later with a lower threshold,
+/// which keeps weak signals locked.
+/// The output is delayed by the length of the sync pattern and the peak search.
 pub struct APTSyncer<'a> {
-    state: [f32; SYNC_LENGHT],
-    pos: usize,
-    nones_read: usize,
-    avg_level: f32,
+    template_a: [f32; SYNC_LENGHT],
+    template_b: [f32; SYNC_LENGHT],
+    samples: VecDeque<f32>,
+    correlations_a: VecDeque<f32>,
+    correlations_b: VecDeque<f32>,
+    pos: u64,
+    tracker_a: SyncTracker,
+    tracker_b: SyncTracker,
     iterator: Box<dyn Iterator<Item = f32> + 'a>,
 }
 
 impl<'a> APTSyncer<'a> {
-    pub fn from<I>(mut iterator: I) -> APTSyncer<'a>
+    pub fn from<I>(iterator: I) -> APTSyncer<'a>
     where
         I: Iterator<Item = f32> + 'a,
     {
-        let mut state = [0.0; SYNC_LENGHT];
-        let mut avg_level = 0.5;
-        for i in 0..SYNC_LENGHT {
-            match iterator.next() {
-                Some(x) => {
-                    state[i] = x;
-                    avg_level = 0.25 * x + avg_level * 0.75;
-                }
-                None => panic!("Could not retrieve enough samples to prime syncer"),
-            }
-        }
-
         APTSyncer {
-            state,
+            template_a: template(&SYNCA_SEQ),
+            template_b: template(&SYNCB_SEQ),
+            samples: VecDeque::with_capacity(SYNC_LENGHT + PEAK_DISTANCE + 1),
+            // The correlations start PEAK_DISTANCE samples before the sample emitted next
+            correlations_a: VecDeque::from(vec![0.0; PEAK_DISTANCE]),
+            correlations_b: VecDeque::from(vec![0.0; PEAK_DISTANCE]),
             pos: 0,
-            nones_read: 0,
-            avg_level,
+            tracker_a: SyncTracker::new(),
+            tracker_b: SyncTracker::new(),
             iterator: Box::new(iterator),
         }
     }
 
-    fn is_marker(&mut self) -> (bool, bool) {
-        let mut count_a = 0;
-        let mut count_b = 0;
+    // Normalized correlation of the window starting at `start` with both templates.
+    fn correlate(&self, start: usize) -> (f32, f32) {
+        let window = self.samples.range(start..start + SYNC_LENGHT);
+        let mean = window.clone().sum::<f32>() / SYNC_LENGHT as f32;
+        let energy = window
+            .clone()
+            .map(|x| (x - mean) * (x - mean))
+            .sum::<f32>()
+            .sqrt();
+        if energy == 0.0 {
+            return (0.0, 0.0);
+        }
 
-        for i in 0..SYNC_LENGHT {
-            let sync_pos = (self.pos + i) % SYNC_LENGHT;
-            let sample = self.state[sync_pos] / (self.avg_level * 2.0);
-            if (sample > 0.5 && SYNCA_SEQ[i]) || (sample <= 0.5 && !SYNCA_SEQ[i]) {
-                count_a += 1;
-            }
-            if (sample > 0.5 && SYNCB_SEQ[i]) || (sample <= 0.5 && !SYNCB_SEQ[i]) {
-                count_b += 1;
+        let mut correlation_a = 0.0;
+        let mut correlation_b = 0.0;
+        for (i, x) in window.enumerate() {
+            correlation_a += (x - mean) * self.template_a[i];
+            correlation_b += (x - mean) * self.template_b[i];
+        }
+
+        (correlation_a / energy, correlation_b / energy)
+    }
+
+    // Fills the buffers until the peak search for the oldest sample can be done.
+    fn fill(&mut self) {
+        while self.samples.len() < SYNC_LENGHT + PEAK_DISTANCE {
+            match self.iterator.next() {
+                Some(x) => self.samples.push_back(x),
+                None => break,
             }
 
-            /*
-            if !count_a && !count_b {
-                break;
+            if self.samples.len() >= SYNC_LENGHT {
+                let (correlation_a, correlation_b) =
+                    self.correlate(self.samples.len() - SYNC_LENGHT);
+                self.correlations_a.push_back(correlation_a);
+                self.correlations_b.push_back(correlation_b);
             }
-            */
         }
-
-        return (count_a > 35, count_b > 35);
     }
 }
 
+fn is_peak(correlations: &VecDeque<f32>) -> bool {
+    let center = correlations[PEAK_DISTANCE];
+    correlations.iter().all(|c| *c <= center)
+}
+
 impl<'a> Iterator for APTSyncer<'a> {
     type Item = SyncedSample;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let (is_a, is_b) = self.is_marker();
+        self.fill();
 
-        let sample = self.state[self.pos];
-        match self.iterator.next() {
-            Some(x) => {
-                self.state[self.pos] = x;
-                self.avg_level = 0.25 * x + self.avg_level * 0.75;
-            }
-            None => self.nones_read += 1,
+        let sample = self.samples.pop_front()?;
+
+        // Close to the end there are no correlations left, the remaining samples can not be syncs
+        let (correlation_a, correlation_b) = match (
+            self.correlations_a.get(PEAK_DISTANCE),
+            self.correlations_b.get(PEAK_DISTANCE),
+        ) {
+            (Some(a), Some(b)) => (*a, *b),
+            _ => (0.0, 0.0),
         };
+        let peak_a = correlation_a > 0.0 && is_peak(&self.correlations_a);
+        let peak_b = correlation_b > 0.0 && is_peak(&self.correlations_b);
 
-        if self.nones_read >= SYNC_LENGHT {
-            return None;
-        }
+        let is_a = self.tracker_a.update(self.pos, correlation_a, peak_a);
+        let is_b = self.tracker_b.update(self.pos, correlation_b, peak_b);
 
-        self.pos = (self.pos + 1) % SYNC_LENGHT;
+        self.correlations_a.pop_front();
+        self.correlations_b.pop_front();
+        self.pos += 1;
 
         if is_a {
-            return Some(SyncedSample::SyncA(sample));
+            Some(SyncedSample::SyncA {
+                sample,
+                confidence: correlation_a,
+            })
         } else if is_b {
-            return Some(SyncedSample::SyncB(sample));
+            Some(SyncedSample::SyncB {
+                sample,
+                confidence: correlation_b,
+            })
         } else {
-            return Some(SyncedSample::Sample(sample));
+            Some(SyncedSample::Sample(sample))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::PI;
+
+    // Feeds the tracker every position up to `end`, with the given correlation peaks,
+    // returns the positions accepted as syncs.
+    fn track(tracker: &mut SyncTracker, end: u64, peaks: &[(u64, f32)]) -> Vec<u64> {
+        (0..end)
+            .filter(|pos| {
+                let peak = peaks.iter().find(|(p, _)| p == pos);
+                let correlation = peak.map_or(0.0, |(_, c)| *c);
+                tracker.update(*pos, correlation, peak.is_some())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn lock_holds_across_missed_line() {
+        let mut tracker = SyncTracker::new();
+        // A weak sync is only accepted close to where it is expected
+        let peaks = [
+            (100, 0.9),
+            (2180, 0.4),
+            (4260, 0.4),
+            (6342, 0.4),
+            (9000, 0.4),
+        ];
+        let syncs = track(&mut tracker, 12000, &peaks);
+        assert_eq!(syncs, vec![100, 2180, 4260, 6342]);
+
+        // Line 2 is missed, line 3 is still searched for around 100 + 3 * 2080
+        let mut tracker = SyncTracker::new();
+        let syncs = track(&mut tracker, 8000, &[(100, 0.9), (6340, 0.4)]);
+        assert_eq!(syncs, vec![100, 6340]);
+    }
+
+    #[test]
+    fn lock_is_dropped_after_missed_lines() {
+        let mut tracker = SyncTracker::new();
+        let late = 100 + (MAX_MISSED_LINES as u64 + 2) * LINE_LENGTH;
+        let syncs = track(&mut tracker, late + 10, &[(100, 0.9), (late, 0.4)]);
+        assert_eq!(syncs, vec![100]);
+        assert_eq!(tracker.expected, None);
+    }
+
+    // Deterministic noise in -amplitude..amplitude.
+    fn noise(state: &mut u32, amplitude: f32) -> f32 {
+        *state = state.wrapping_mul(1664525).wrapping_add(1013904223);
+        ((*state >> 8) as f32 / (1 << 24) as f32 * 2.0 - 1.0) * amplitude
+    }
+
+    #[test]
+    fn detects_syncs_in_noise() {
+        let lines = 20;
+        // The start of this line is lost in a dropout
+        let missing = 7;
+        let mut state = 1;
+        let signal: Vec<f32> = (0..lines * LINE_LENGTH as usize)
+            .map(|i| {
+                let (line, x) = (i / LINE_LENGTH as usize, i % LINE_LENGTH as usize);
+                let level = match x {
+                    0..=99 if line == missing => return 0.0,
+                    0..=39 => SYNCA_SEQ[x] as u8 as f32,
+                    1040..=1079 => SYNCB_SEQ[x - 1040] as u8 as f32,
+                    _ => 0.5 + 0.3 * (x as f32 / 30.0 * PI).sin(),
+                };
+                level + noise(&mut state, 0.2)
+            })
+            .collect();
+
+        let mut syncs_a = Vec::new();
+        let mut syncs_b = Vec::new();
+        for (pos, sample) in APTSyncer::from(signal.into_iter()).enumerate() {
+            match sample {
+                SyncedSample::SyncA { .. } => syncs_a.push(pos),
+                SyncedSample::SyncB { .. } => syncs_b.push(pos),
+                SyncedSample::Sample(_) => {}
+            }
         }
+
+        let line_starts = (0..lines).map(|line| line * LINE_LENGTH as usize);
+        let expected_a: Vec<usize> = line_starts
+            .clone()
+            .filter(|start| *start != missing * LINE_LENGTH as usize)
+            .collect();
+        let expected_b: Vec<usize> = line_starts.map(|start| start + 1040).collect();
+        assert_eq!(syncs_a, expected_a);
+        assert_eq!(syncs_b, expected_b);
     }
 }
@@ -0,0 +1,555 @@
+//! Radiometric calibration of the image channels.
+//!
+//! The telemetry frames contain everything needed to turn the transmitted counts
+//! into physical values: the grey scale in wedges 1 - 9 maps the received levels back to counts,
+//! wedges 10 - 13 contain the temperatures of the internal black body measured by four PRTs
+//! and wedge 15 contains the counts of the black body as seen by the thermal channels.
+//! The space view columns next to the sync pattern contain the counts of cold space.
+//!
+//! Thermal channels are calibrated to brightness temperatures in Kelvin,
+//! visible channels to albedo in percent, using the coefficients from the NOAA KLM User's Guide.
+
+// Coefficients are kept exactly as published
+#![allow(clippy::excessive_precision)]
+
+use std::fmt;
+use std::str::FromStr;
+
+use decoder::{IMAGE_A_START, IMAGE_B_START, IMAGE_WIDTH};
+use telemetry::{AVHRRChannel, Telemetry, TelemetryFrame, LINES_PER_FRAME, WEDGES};
+
+/// First column of the space view of channel A.
+pub const SPACE_A_START: usize = 39;
+/// First column of the space view of channel B.
+pub const SPACE_B_START: usize = 1079;
+/// Width of the space view.
+pub const SPACE_WIDTH: usize = 47;
+
+// Counts of wedges 1 - 9 after the 8 bit quantization on board.
+const WEDGE_COUNTS: [f32; 9] = [31.0, 63.0, 95.0, 127.0, 159.0, 191.0, 223.0, 255.0, 0.0];
+
+const SPACE_MARGIN: usize = 5;
+
+// Radiation constants in mW/(m^2 sr cm^-4) and cm K
+const C1: f32 = 1.1910427e-5;
+const C2: f32 = 1.4387752;
+
+/// Satellites with known calibration coefficients.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Satellite {
+    Noaa15,
+    Noaa18,
+    Noaa19,
+}
+
+impl FromStr for Satellite {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().replace(['-', ' ', '_'], "").as_str() {
+            "noaa15" => Ok(Satellite::Noaa15),
+            "noaa18" => Ok(Satellite::Noaa18),
+            "noaa19" => Ok(Satellite::Noaa19),
+            _ => Err(format!("Unknown satellite {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for Satellite {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Satellite::Noaa15 => "NOAA-15",
+            Satellite::Noaa18 => "NOAA-18",
+            Satellite::Noaa19 => "NOAA-19",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// Coefficients of the visible channels, dual gain with a low and high range.
+struct VisibleCoeffs {
+    slope_low: f32,
+    intercept_low: f32,
+    slope_high: f32,
+    intercept_high: f32,
+    cutoff: f32,
+}
+
+// Coefficients of the thermal channels.
+struct ThermalCoeffs {
+    // Central wave number in cm^-1
+    vc: f32,
+    // Band correction of the black body temperature
+    a: f32,
+    b: f32,
+    // Radiance of space and the non linear correction
+    ns: f32,
+    b0: f32,
+    b1: f32,
+    b2: f32,
+}
+
+struct SatelliteCoeffs {
+    prt: [[f32; 3]; 4],
+    ch1: VisibleCoeffs,
+    ch2: VisibleCoeffs,
+    ch3a: VisibleCoeffs,
+    ch3b: ThermalCoeffs,
+    ch4: ThermalCoeffs,
+    ch5: ThermalCoeffs,
+}
+
+const NOAA15: SatelliteCoeffs = SatelliteCoeffs {
+    prt: [
+        [276.60157, 0.051045, 1.36328e-6],
+        [276.62531, 0.050909, 1.47266e-6],
+        [276.67413, 0.050907, 1.47656e-6],
+        [276.59258, 0.050966, 1.47656e-6],
+    ],
+    ch1: VisibleCoeffs {
+        slope_low: 0.0568,
+        intercept_low: -2.1874,
+        slope_high: 0.1633,
+        intercept_high: -54.9928,
+        cutoff: 496.0,
+    },
+    ch2: VisibleCoeffs {
+        slope_low: 0.0596,
+        intercept_low: -2.4096,
+        slope_high: 0.1629,
+        intercept_high: -55.2436,
+        cutoff: 511.0,
+    },
+    ch3a: VisibleCoeffs {
+        slope_low: 0.1018,
+        intercept_low: -4.0477,
+        slope_high: 0.2634,
+        intercept_high: -52.0036,
+        cutoff: 511.0,
+    },
+    ch3b: ThermalCoeffs {
+        vc: 2695.9743,
+        a: 1.624481,
+        b: 0.998824,
+        ns: 0.0,
+        b0: 0.0,
+        b1: 0.0,
+        b2: 0.0,
+    },
+    ch4: ThermalCoeffs {
+        vc: 925.4075,
+        a: 0.338243,
+        b: 0.998873,
+        ns: -4.50,
+        b0: 4.76,
+        b1: -0.0932,
+        b2: 0.0004524,
+    },
+    ch5: ThermalCoeffs {
+        vc: 839.8979,
+        a: 0.304856,
+        b: 0.999120,
+        ns: -3.61,
+        b0: 3.83,
+        b1: -0.0659,
+        b2: 0.0002811,
+    },
+};
+
+const NOAA18: SatelliteCoeffs = SatelliteCoeffs {
+    prt: [
+        [276.601, 0.05090, 1.657e-6],
+        [276.683, 0.05101, 1.482e-6],
+        [276.565, 0.05117, 1.313e-6],
+        [276.615, 0.05103, 1.484e-6],
+    ],
+    ch1: VisibleCoeffs {
+        slope_low: 0.0546,
+        intercept_low: -2.1390,
+        slope_high: 0.1623,
+        intercept_high: -57.5034,
+        cutoff: 501.0,
+    },
+    ch2: VisibleCoeffs {
+        slope_low: 0.0586,
+        intercept_low: -2.3154,
+        slope_high: 0.1822,
+        intercept_high: -64.5656,
+        cutoff: 500.0,
+    },
+    ch3a: VisibleCoeffs {
+        slope_low: 0.0279,
+        intercept_low: -1.0987,
+        slope_high: 0.1801,
+        intercept_high: -77.9014,
+        cutoff: 500.0,
+    },
+    ch3b: ThermalCoeffs {
+        vc: 2659.7952,
+        a: 1.698704,
+        b: 0.996960,
+        ns: 0.0,
+        b0: 0.0,
+        b1: 0.0,
+        b2: 0.0,
+    },
+    ch4: ThermalCoeffs {
+        vc: 928.1460,
+        a: 0.436645,
+        b: 0.998607,
+        ns: -5.53,
+        b0: 5.82,
+        b1: -0.11069,
+        b2: 0.00052337,
+    },
+    ch5: ThermalCoeffs {
+        vc: 833.2532,
+        a: 0.253179,
+        b: 0.999057,
+        ns: -2.22,
+        b0: 2.67,
+        b1: -0.04360,
+        b2: 0.00017715,
+    },
+};
+
+const NOAA19: SatelliteCoeffs = SatelliteCoeffs {
+    prt: [
+        [276.6067, 0.051111, 1.405783e-6],
+        [276.6119, 0.051090, 1.496037e-6],
+        [276.6311, 0.051033, 1.496990e-6],
+        [276.6268, 0.051058, 1.493110e-6],
+    ],
+    ch1: VisibleCoeffs {
+        slope_low: 0.055091,
+        intercept_low: -2.1415,
+        slope_high: 0.16253,
+        intercept_high: -55.863,
+        cutoff: 496.43,
+    },
+    ch2: VisibleCoeffs {
+        slope_low: 0.061195,
+        intercept_low: -2.4309,
+        slope_high: 0.18269,
+        intercept_high: -64.596,
+        cutoff: 511.0,
+    },
+    ch3a: VisibleCoeffs {
+        slope_low: 0.027102,
+        intercept_low: -1.0701,
+        slope_high: 0.18290,
+        intercept_high: -80.091,
+        cutoff: 500.0,
+    },
+    ch3b: ThermalCoeffs {
+        vc: 2670.2425,
+        a: 1.6863857,
+        b: 0.9974112,
+        ns: 0.0,
+        b0: 0.0,
+        b1: 0.0,
+        b2: 0.0,
+    },
+    ch4: ThermalCoeffs {
+        vc: 927.92374,
+        a: 0.39419031,
+        b: 0.9986574,
+        ns: -5.49,
+        b0: 5.70,
+        b1: -0.11187,
+        b2: 0.00054668,
+    },
+    ch5: ThermalCoeffs {
+        vc: 831.28619,
+        a: 0.2636462,
+        b: 0.9990811,
+        ns: -3.39,
+        b0: 3.58,
+        b1: -0.05991,
+        b2: 0.00024985,
+    },
+};
+
+impl Satellite {
+    /// Frequency of the APT transmitter in Hz.
+    pub fn frequency(&self) -> f64 {
+        match self {
+            Satellite::Noaa15 => 137.62e6,
+            Satellite::Noaa18 => 137.9125e6,
+            Satellite::Noaa19 => 137.1e6,
+        }
+    }
+
+    fn coeffs(&self) -> &'static SatelliteCoeffs {
+        match self {
+            Satellite::Noaa15 => &NOAA15,
+            Satellite::Noaa18 => &NOAA18,
+            Satellite::Noaa19 => &NOAA19,
+        }
+    }
+}
+
+/// Physical unit of a calibrated channel.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CalibrationUnit {
+    /// Brightness temperature in Kelvin
+    Kelvin,
+    /// Albedo in percent
+    Albedo,
+}
+
+impl fmt::Display for CalibrationUnit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CalibrationUnit::Kelvin => write!(f, "K"),
+            CalibrationUnit::Albedo => write!(f, "%"),
+        }
+    }
+}
+
+/// Calibrated image of a single channel.
+#[derive(Clone, Debug)]
+pub struct CalibratedChannel {
+    pub channel: AVHRRChannel,
+    pub unit: CalibrationUnit,
+    /// Width of the grid, always [`IMAGE_WIDTH`]
+    pub width: usize,
+    /// Height of the grid, one row per decoded line
+    pub height: usize,
+    /// Calibrated values row by row, NaN where no calibration was possible
+    pub values: Vec<f32>,
+}
+
+/// Calibrated images of both channels.
+#[derive(Clone, Debug, Default)]
+pub struct Calibration {
+    pub channel_a: Option<CalibratedChannel>,
+    pub channel_b: Option<CalibratedChannel>,
+}
+
+impl Calibration {
+    /// Calibrates both channels using the telemetry extracted from `lines`.
+    ///
+    /// Channels are left out, if the telemetry does not identify them.
+    pub fn from_lines(
+        lines: &[Vec<f32>],
+        telemetry: &Telemetry,
+        satellite: Satellite,
+    ) -> Calibration {
+        let coeffs = satellite.coeffs();
+
+        Calibration {
+            channel_a: telemetry
+                .channel_a
+                .map(|channel| calibrate_channel(lines, telemetry, coeffs, channel, Side::A)),
+            channel_b: telemetry
+                .channel_b
+                .map(|channel| calibrate_channel(lines, telemetry, coeffs, channel, Side::B)),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Side {
+    A,
+    B,
+}
+
+impl Side {
+    fn wedges<'a>(&self, frame: &'a TelemetryFrame) -> &'a [f32; WEDGES] {
+        match self {
+            Side::A => &frame.wedges_a,
+            Side::B => &frame.wedges_b,
+        }
+    }
+
+    fn channel(&self, frame: &TelemetryFrame) -> Option<AVHRRChannel> {
+        match self {
+            Side::A => frame.channel_a,
+            Side::B => frame.channel_b,
+        }
+    }
+
+    fn space_start(&self) -> usize {
+        match self {
+            Side::A => SPACE_A_START,
+            Side::B => SPACE_B_START,
+        }
+    }
+
+    fn image_start(&self) -> usize {
+        match self {
+            Side::A => IMAGE_A_START,
+            Side::B => IMAGE_B_START,
+        }
+    }
+}
+
+// Calibration of a single frame, mapping the received levels to physical values.
+struct FrameCalibration {
+    // Linear mapping from received levels to 10 bit counts
+    gain: f32,
+    offset: f32,
+    // Counts of space and the black body for the thermal channels
+    space_count: f32,
+    blackbody_count: f32,
+    blackbody_radiance: f32,
+}
+
+impl FrameCalibration {
+    fn new(
+        lines: &[Vec<f32>],
+        frame: &TelemetryFrame,
+        side: Side,
+        thermal: Option<&ThermalCoeffs>,
+        coeffs: &SatelliteCoeffs,
+    ) -> FrameCalibration {
+        let wedges = side.wedges(frame);
+        let (gain, offset) = fit_linear(&wedges[0..WEDGE_COUNTS.len()], &WEDGE_COUNTS);
+        // APT transmits the upper 8 bits of the 10 bit AVHRR counts
+        let gain = gain * 4.0;
+        let offset = offset * 4.0;
+        let to_count = |level: f32| level * gain + offset;
+
+        let frame_lines = &lines[frame.start_line..frame.start_line + LINES_PER_FRAME];
+        let space_start = side.space_start() + SPACE_MARGIN;
+        let space_end = side.space_start() + SPACE_WIDTH - SPACE_MARGIN;
+        let space_level = frame_lines
+            .iter()
+            .map(|line| line[space_start..space_end].iter().sum::<f32>())
+            .sum::<f32>()
+            / (frame_lines.len() * (space_end - space_start)) as f32;
+        let space_count = to_count(space_level);
+
+        // Wedges 10 - 13 contain the readings of the four PRTs,
+        // their average is the temperature of the internal black body.
+        let prt_temp = (0..4)
+            .map(|i| {
+                let count = to_count(wedges[9 + i]);
+                let d = coeffs.prt[i];
+                d[0] + d[1] * count + d[2] * count * count
+            })
+            .sum::<f32>()
+            / 4.0;
+
+        let blackbody_count = to_count(wedges[14]);
+        let blackbody_radiance = match thermal {
+            Some(thermal) => planck(thermal.vc, thermal.a + thermal.b * prt_temp),
+            None => 0.0,
+        };
+
+        FrameCalibration {
+            gain,
+            offset,
+            space_count,
+            blackbody_count,
+            blackbody_radiance,
+        }
+    }
+
+    fn thermal(&self, level: f32, coeffs: &ThermalCoeffs) -> f32 {
+        let count = level * self.gain + self.offset;
+        let linear = coeffs.ns
+            + (self.blackbody_radiance - coeffs.ns) * (self.space_count - count)
+                / (self.space_count - self.blackbody_count);
+        let radiance = linear + coeffs.b0 + coeffs.b1 * linear + coeffs.b2 * linear * linear;
+        if radiance <= 0.0 {
+            return f32::NAN;
+        }
+
+        let temp = C2 * coeffs.vc / (1.0 + C1 * coeffs.vc.powi(3) / radiance).ln();
+        (temp - coeffs.a) / coeffs.b
+    }
+
+    fn visible(&self, level: f32, coeffs: &VisibleCoeffs) -> f32 {
+        let count = level * self.gain + self.offset;
+        if count <= coeffs.cutoff {
+            coeffs.slope_low * count + coeffs.intercept_low
+        } else {
+            coeffs.slope_high * count + coeffs.intercept_high
+        }
+    }
+}
+
+fn calibrate_channel(
+    lines: &[Vec<f32>],
+    telemetry: &Telemetry,
+    coeffs: &SatelliteCoeffs,
+    channel: AVHRRChannel,
+    side: Side,
+) -> CalibratedChannel {
+    let (thermal, visible) = match channel {
+        AVHRRChannel::Ch1 => (None, Some(&coeffs.ch1)),
+        AVHRRChannel::Ch2 => (None, Some(&coeffs.ch2)),
+        AVHRRChannel::Ch3A => (None, Some(&coeffs.ch3a)),
+        AVHRRChannel::Ch3B => (Some(&coeffs.ch3b), None),
+        AVHRRChannel::Ch4 => (Some(&coeffs.ch4), None),
+        AVHRRChannel::Ch5 => (Some(&coeffs.ch5), None),
+    };
+
+    let frames: Vec<(usize, Option<FrameCalibration>)> = telemetry
+        .frames
+        .iter()
+        .map(|frame| {
+            let calibration = if side.channel(frame) == Some(channel) {
+                Some(FrameCalibration::new(lines, frame, side, thermal, coeffs))
+            } else {
+                None
+            };
+            (frame.start_line, calibration)
+        })
+        .collect();
+
+    let mut values = Vec::with_capacity(lines.len() * IMAGE_WIDTH);
+    for (y, line) in lines.iter().enumerate() {
+        // Lines outside of complete frames use the closest frame
+        let frame = frames
+            .iter()
+            .min_by_key(|(start, _)| (start + LINES_PER_FRAME / 2).abs_diff(y))
+            .and_then(|(_, calibration)| calibration.as_ref());
+
+        let pixels = &line[side.image_start()..side.image_start() + IMAGE_WIDTH];
+        for level in pixels {
+            let value = match (frame, thermal, visible) {
+                (Some(frame), Some(thermal), _) => frame.thermal(*level, thermal),
+                (Some(frame), None, Some(visible)) => frame.visible(*level, visible),
+                _ => f32::NAN,
+            };
+            values.push(value);
+        }
+    }
+
+    CalibratedChannel {
+        channel,
+        unit: if thermal.is_some() {
+            CalibrationUnit::Kelvin
+        } else {
+            CalibrationUnit::Albedo
+        },
+        width: IMAGE_WIDTH,
+        height: lines.len(),
+        values,
+    }
+}
+
+// Radiance of a black body at the wave number `vc` and the temperature `temp`.
+fn planck(vc: f32, temp: f32) -> f32 {
+    C1 * vc.powi(3) / ((C2 * vc / temp).exp() - 1.0)
+}
+
+// Least squares fit of y = gain * x + offset.
+fn fit_linear(x: &[f32], y: &[f32]) -> (f32, f32) {
+    let n = x.len() as f32;
+    let mean_x = x.iter().sum::<f32>() / n;
+    let mean_y = y.iter().sum::<f32>() / n;
+
+    let mut cov = 0.0;
+    let mut var = 0.0;
+    for (x, y) in x.iter().zip(y.iter()) {
+        cov += (x - mean_x) * (y - mean_y);
+        var += (x - mean_x) * (x - mean_x);
+    }
+
+    let gain = if var > 0.0 { cov / var } else { 0.0 };
+    (gain, mean_y - gain * mean_x)
+}
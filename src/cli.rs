@@ -1,27 +1,706 @@
+use clap::{arg, value_parser, ArgMatches, Command};
 use indicatif::{ProgressBar, ProgressStyle};
 
-use decoder;
+use std::fmt::Display;
+use std::ops::{Bound, RangeBounds};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use apt_decoder::calibration::{CalibratedChannel, Satellite};
+use apt_decoder::composite::Palette;
+use apt_decoder::decoder::{
+    self, DecoderOptions, InputFormat, IMAGE_WIDTH, LINES_PER_SECOND, PIXEL_RATE,
+};
+use apt_decoder::direction::Rotation;
+use apt_decoder::doppler::DopplerModel;
+use apt_decoder::enhance::{Enhancement, Equalization};
+use apt_decoder::georef::{Georeferencing, GroundControlPoint};
+use apt_decoder::mosaic::{mosaic, MapPass};
+use apt_decoder::normalization::{Normalization, MAX_FIT_DEGREE};
+use apt_decoder::orbit::{format_time, parse_time, Observer, Tracker};
+use apt_decoder::overlay::{
+    MapLayer, Overlay, BORDER_COLOR, COASTLINE_COLOR, MIN_GRATICULE_SPACING,
+};
+use apt_decoder::reproject::MapGrid;
+use apt_decoder::sgp4::{Sgp4, Tle};
+use apt_decoder::shapes::read_shapes;
+use apt_decoder::subcarrier::SubcarrierOffset;
+use apt_decoder::telemetry::{AVHRRChannel, Telemetry};
+use apt_decoder::utils::{wav_recording_time, IQFormat, RawFormat};
+use apt_decoder::DecoderError;
 
 const STEPS: u64 = 100;
+const SUBCARRIER_REPORT_INTERVAL: f32 = 10.0;
+// Seconds between the printed values of the Doppler curve.
+const DOPPLER_REPORT_INTERVAL: f64 = 30.0;
+
+pub fn decoder_args(command: Command) -> Command {
+    command
+        .arg(
+            arg!(-f --format <FORMAT> "Format of the input file")
+                .value_parser(["wav", "f32", "s16", "cf32", "cu8", "cs16"])
+                .default_value("wav"),
+        )
+        .arg(
+            arg!(-c --channel <CHANNEL> "Channel of stereo WAV files, iq decodes left and right as I/Q")
+                .value_parser(["left", "right", "mix", "iq"])
+                .default_value("mix"),
+        )
+        .arg(
+            arg!(-r --samplerate <RATE> "Sampling rate of raw audio and IQ files in Hz")
+                .value_parser(value_parser!(u32)),
+        )
+        .arg(
+            arg!(--bandwidth <HZ> "Bandwidth of the channel filter for raw IQ files in Hz")
+                .value_parser(float_in((Bound::Excluded(0.0f32), Bound::Unbounded), "above 0"))
+                .default_value("40000"),
+        )
+        .arg(
+            arg!(--deemphasis <MICROSECONDS> "Enable de-emphasis for raw IQ files with the given time constant")
+                .value_parser(float_in((Bound::Excluded(0.0f32), Bound::Unbounded), "above 0")),
+        )
+        .arg(
+            arg!(-d --demodulator <DEMODULATOR> "Demodulator for the AM subcarrier")
+                .value_parser(["squaring", "hilbert", "synchronous", "tracking"])
+                .default_value("squaring"),
+        )
+        .arg(
+            arg!(-s --satellite <SATELLITE> "Satellite the recording is from, enables calibration")
+                .value_parser(["noaa15", "noaa18", "noaa19"]),
+        )
+        .arg(
+            arg!(--calibrate "Write the calibrated channels as .npy files next to the output image")
+                .requires("satellite"),
+        )
+        .arg(
+            arg!(--normalize <MODE> "Scale the grey levels to the running maximum, to percentiles of each channel or to the telemetry wedges")
+                .value_parser(["running", "percentile", "wedges", "wedge-fit"])
+                .default_value("running"),
+        )
+        .arg(
+            arg!(--"fit-degree" <DEGREE> "Degree of the polynomial fitted to the wedges by --normalize wedge-fit")
+                .value_parser(value_parser!(u8).range(1..=MAX_FIT_DEGREE as i64))
+                .default_value("1"),
+        )
+        .arg(
+            arg!(--clip <PERCENT> "Share of the darkest and brightest pixels clipped by --normalize percentile")
+                .value_parser(float_in(0.0f32..50.0, "at least 0 and below 50"))
+                .default_value("0.5"),
+        )
+        .arg(
+            arg!(--median <RADIUS> "Remove noise from both channels with a median filter of the given radius")
+                .value_parser(value_parser!(u32).range(0..=5))
+                .default_value("0"),
+        )
+        .arg(
+            arg!(--equalize <MODE> "Equalize the histogram of each channel, either as a whole or in tiles (CLAHE)")
+                .value_parser(["none", "histogram", "clahe"])
+                .default_value("none"),
+        )
+        .arg(
+            arg!(--"clahe-clip" <LIMIT> "Contrast limit of --equalize clahe, in multiples of the mean histogram bin")
+                .value_parser(float_in(1.0f32.., "at least 1"))
+                .default_value("2"),
+        )
+        .arg(
+            arg!(--gamma <GAMMA> "Gamma correction of both channels, values above 1 brighten the mid tones")
+                .value_parser(float_in((Bound::Excluded(0.0f32), Bound::Unbounded), "above 0"))
+                .default_value("1"),
+        )
+        .arg(
+            arg!(--sharpen <AMOUNT> "Sharpen both channels with an unsharp mask of the given strength")
+                .value_parser(float_in(0.0f32.., "at least 0"))
+                .default_value("0"),
+        )
+        .arg(
+            arg!(--layout <LAYOUT> "Write the full frame, separate images of both channels or both")
+                .value_parser(["full", "channels", "both"])
+                .default_value("full"),
+        )
+        .arg(arg!(--crop "Crop the channel images to the image, without sync, space view and telemetry"))
+        .arg(
+            arg!(--"channel-names" <TEMPLATE> "Names of the channel images next to the output file, {stem} is the name of the output file, {channel} is A or B")
+                .default_value(decoder::DEFAULT_CHANNEL_NAMES),
+        )
+        .arg(
+            arg!(--"high-depth" <FORMAT> "Also write the image with 16 bits per pixel as PNG or TIFF")
+                .value_parser(["png", "tiff"]),
+        )
+        .arg(arg!(--"raw-lines" "Write the unscaled, synced lines as float32 .npy file next to the output image"))
+        .arg(arg!(--metadata "Write the metadata and the sync quality of every line as .json file next to the output image"))
+        .arg(arg!(--tle <FILE> "TLE file with the orbit of the satellite, for Doppler correction and georeferencing"))
+        .arg(
+            arg!(--location <LOCATION> "Receiver location as latitude,longitude[,altitude in m], corrects the Doppler shift of IQ input")
+                .requires("tle"),
+        )
+        .arg(arg!(--start <TIME> "Start of the recording in UTC, e.g. 2024-05-01T12:34:56Z, read from WAV files if not given"))
+        .arg(
+            arg!(--frequency <MHZ> "Transmit frequency of the satellite in MHz, if no satellite is given")
+                .value_parser(value_parser!(f64)),
+        )
+        .arg(
+            arg!(--georef <FORMAT> "Georeference the image using a GeoTIFF or a world file, with a table of ground control points")
+                .value_parser(["geotiff", "worldfile"])
+                .requires("tle"),
+        )
+        .arg(arg!(--coastlines <FILE> "Draw coastlines from a shapefile or GeoJSON file over the image").requires("tle"))
+        .arg(arg!(--borders <FILE> "Draw borders from a shapefile or GeoJSON file over the image").requires("tle"))
+        .arg(
+            arg!(--graticule <DEGREES> "Draw a latitude / longitude grid with the given spacing over the image")
+                .value_parser(float_in(MIN_GRATICULE_SPACING.., "at least 0.1"))
+                .requires("tle"),
+        )
+        .arg(
+            arg!(--overlay <MODE> "Draw the map over a copy of the image or write it as transparent layer")
+                .value_parser(["burn-in", "layer"])
+                .default_value("burn-in"),
+        )
+        .arg(
+            arg!(--project <PROJECTION> "Reproject both channels onto a map")
+                .value_parser(["equirectangular", "mercator"])
+                .requires("tle"),
+        )
+        .arg(arg!(--bounds <BOUNDS> "Area of the map as west,south,east,north in degrees, the whole pass if not given").requires("project"))
+        .arg(
+            arg!(--resolution <DEGREES> "Size of the map pixels in degrees of longitude")
+                .value_parser(float_in((Bound::Excluded(0.0f64), Bound::Unbounded), "above 0"))
+                .default_value("0.04"),
+        )
+        .arg(
+            arg!(--rotate <MODE> "Rotate the image by 180°, for northbound passes or always")
+                .value_parser(["never", "north-up", "always"])
+                .default_value("never"),
+        )
+        .arg(arg!(-p --palette <PALETTE> "Write a false colour composite using a palette PNG file or 'false-color'"))
+}
+
+pub fn mosaic_args(command: Command) -> Command {
+    command
+        .about("Combines the maps of several passes decoded with --project")
+        .arg(
+            arg!(-c --channel <CHANNEL> "Channel of the passes to combine")
+                .value_parser(["a", "b"])
+                .default_value("a"),
+        )
+        .arg(
+            arg!(-m --mode <MODE> "Use the pass closest to nadir or with the highest sun, or blend all passes")
+                .value_parser(["nadir", "sun", "feather"])
+                .default_value("nadir"),
+        )
+        .arg(
+            arg!(--feather <PIXELS> "Width of the blended seams between passes")
+                .value_parser(value_parser!(f32))
+                .default_value("20"),
+        )
+        .arg(arg!(<output> "Output png file"))
+        .arg(arg!(<passes> ... "Output png files of the decoded passes"))
+}
+
+pub fn mosaic_passes(matches: &ArgMatches) {
+    let channel = matches
+        .get_one::<String>("channel")
+        .expect("No channel given")
+        .parse()
+        .expect("Unknown channel");
+    let mode = matches
+        .get_one::<String>("mode")
+        .expect("No mode given")
+        .parse()
+        .expect("Unknown mosaic mode");
+    let feather = *matches
+        .get_one::<f32>("feather")
+        .expect("No feather width given");
+    let output_path = matches
+        .get_one::<String>("output")
+        .expect("No output file given");
+
+    let mut passes = Vec::new();
+    for path in matches
+        .get_many::<String>("passes")
+        .expect("No passes given")
+    {
+        match MapPass::open(path, channel) {
+            Ok(pass) => {
+                println!(
+                    "{}: {} map from {}",
+                    path,
+                    pass.projection,
+                    format_time(pass.time)
+                );
+                passes.push(pass);
+            }
+            Err(error) => {
+                println!("Unable to read pass: {}", error);
+                return;
+            }
+        }
+    }
+
+    println!("Combining {} passes: {}", passes.len(), mode);
+    match mosaic(&passes, mode, feather).and_then(|mosaic| {
+        mosaic.save(output_path)?;
+        Ok(mosaic)
+    }) {
+        Ok(mosaic) => println!(
+            "Mosaic written to {}: {} x {} pixels",
+            output_path,
+            mosaic.image.width(),
+            mosaic.image.height()
+        ),
+        Err(error) => println!("Unable to combine passes: {}", error),
+    }
+}
+
+pub fn decoder_options(matches: &ArgMatches) -> Result<DecoderOptions, DecoderError> {
+    let input_format = match matches.get_one::<String>("format").map(|f| f.as_str()) {
+        Some("f32") => InputFormat::Raw(RawFormat::F32),
+        Some("s16") => InputFormat::Raw(RawFormat::S16),
+        Some("cf32") => InputFormat::IQ(IQFormat::ComplexF32),
+        Some("cu8") => InputFormat::IQ(IQFormat::ComplexU8),
+        Some("cs16") => InputFormat::IQ(IQFormat::ComplexS16),
+        _ => InputFormat::Wav,
+    };
+
+    let palette = match matches.get_one::<String>("palette").map(|p| p.as_str()) {
+        Some("false-color") => Some(Palette::false_color()),
+        Some(path) => Some(Palette::from_file(Path::new(path))?),
+        None => None,
+    };
+
+    let satellite: Option<Satellite> = matches
+        .get_one::<String>("satellite")
+        .map(|name| name.parse().expect("Unknown satellite"));
+
+    let overlay = overlay_options(matches)?;
+    let map = match matches.get_one::<String>("project") {
+        Some(projection) => Some(MapGrid {
+            projection: projection.parse().expect("Unknown projection"),
+            bounds: match matches.get_one::<String>("bounds") {
+                Some(bounds) => Some(bounds.parse()?),
+                None => None,
+            },
+            resolution: *matches
+                .get_one::<f64>("resolution")
+                .expect("No resolution given"),
+        }),
+        None => None,
+    };
+    let rotation: Rotation = matches
+        .get_one::<String>("rotate")
+        .expect("No rotation given")
+        .parse()
+        .expect("Unknown rotation");
+    let (doppler, georeference) = match matches.get_one::<String>("tle") {
+        Some(path) => orbit_options(
+            matches,
+            Path::new(path),
+            satellite,
+            input_format,
+            // The orbit tells the direction of the pass better than the image
+            overlay.is_some() || map.is_some() || rotation == Rotation::NorthUp,
+        )?,
+        None => (None, None),
+    };
+
+    let normalization = match matches
+        .get_one::<String>("normalize")
+        .expect("No normalization given")
+        .parse()
+        .expect("Unknown normalization")
+    {
+        Normalization::Percentile { .. } => Normalization::Percentile {
+            clip: *matches.get_one::<f32>("clip").expect("No clip given"),
+        },
+        Normalization::WedgeFit { .. } => Normalization::WedgeFit {
+            degree: *matches
+                .get_one::<u8>("fit-degree")
+                .expect("No degree given") as usize,
+        },
+        normalization => normalization,
+    };
+
+    let equalization = match matches
+        .get_one::<String>("equalize")
+        .expect("No equalization given")
+        .parse()
+        .expect("Unknown equalization")
+    {
+        Equalization::Clahe { .. } => Equalization::Clahe {
+            clip_limit: *matches
+                .get_one::<f32>("clahe-clip")
+                .expect("No clip limit given"),
+        },
+        equalization => equalization,
+    };
+    let enhancement = Enhancement {
+        median: *matches.get_one::<u32>("median").expect("No median given"),
+        equalization,
+        gamma: *matches.get_one::<f32>("gamma").expect("No gamma given"),
+        sharpen: *matches
+            .get_one::<f32>("sharpen")
+            .expect("No sharpening given"),
+    };
+
+    Ok(DecoderOptions {
+        input_format,
+        wav_channel: matches
+            .get_one::<String>("channel")
+            .map(|name| name.parse().expect("Unknown channel"))
+            .unwrap_or_default(),
+        sample_rate: matches.get_one::<u32>("samplerate").copied(),
+        channel_bandwidth: *matches
+            .get_one::<f32>("bandwidth")
+            .expect("No bandwidth given"),
+        demodulator: matches
+            .get_one::<String>("demodulator")
+            .map(|name| name.parse().expect("Unknown demodulator"))
+            .unwrap_or_default(),
+        deemphasis: matches
+            .get_one::<f32>("deemphasis")
+            .map(|tau| tau / 1_000_000.0),
+        doppler,
+        satellite,
+        normalization,
+        enhancement,
+        layout: matches
+            .get_one::<String>("layout")
+            .expect("No layout given")
+            .parse()
+            .expect("Unknown layout"),
+        crop_channels: matches.get_flag("crop"),
+        channel_names: matches
+            .get_one::<String>("channel-names")
+            .expect("No channel names given")
+            .to_string(),
+        high_depth: matches
+            .get_one::<String>("high-depth")
+            .map(|format| format.parse().expect("Unknown image format")),
+        write_lines: matches.get_flag("raw-lines"),
+        write_calibration: matches.get_flag("calibrate"),
+        write_metadata: matches.get_flag("metadata"),
+        palette,
+        georeference,
+        overlay,
+        map,
+        rotation,
+        stop: None,
+    })
+}
 
-pub fn decode(input_path: &str, output_path: &str) {
+fn orbit_options(
+    matches: &ArgMatches,
+    tle_path: &Path,
+    satellite: Option<Satellite>,
+    input_format: InputFormat,
+    needs_georeference: bool,
+) -> Result<(Option<DopplerModel>, Option<Georeferencing>), DecoderError> {
+    let location = matches.get_one::<String>("location");
+    let georef_format = matches.get_one::<String>("georef");
+    if location.is_none() && georef_format.is_none() && !needs_georeference {
+        return Ok((None, None));
+    }
+
+    let tles = Tle::from_file(tle_path)?;
+    // Pick the element set of the satellite, if the file contains several
+    let tle = match satellite {
+        Some(satellite) => tles
+            .iter()
+            .find(|tle| tle.matches(&satellite.to_string()))
+            .or_else(|| tles.first()),
+        None => tles.first(),
+    }
+    .ok_or_else(|| DecoderError::InvalidTLE(format!("No element set in {}", tle_path.display())))?;
+    let sgp4 = Sgp4::new(tle)?;
+    let start = recording_start(matches, input_format)?;
+
+    let doppler = match location {
+        Some(location) => {
+            let observer: Observer = location.parse()?;
+            let frequency = match matches.get_one::<f64>("frequency") {
+                Some(mhz) => mhz * 1e6,
+                None => satellite
+                    .map(|satellite| satellite.frequency())
+                    .ok_or(DecoderError::MissingFrequency)?,
+            };
+            Some(DopplerModel {
+                tracker: Tracker::new(sgp4.clone(), observer),
+                frequency,
+                start,
+            })
+        }
+        None => None,
+    };
+
+    // Overlays, maps and the pass direction need the georeferencing, even without writing its files
+    let georeference = if georef_format.is_some() || needs_georeference {
+        Some(Georeferencing {
+            sgp4,
+            start,
+            format: georef_format
+                .map(|format| format.parse().expect("Unknown georeferencing format")),
+        })
+    } else {
+        None
+    };
+
+    Ok((doppler, georeference))
+}
+
+fn overlay_options(matches: &ArgMatches) -> Result<Option<Overlay>, DecoderError> {
+    let mut layers = Vec::new();
+    for (name, color) in [("coastlines", COASTLINE_COLOR), ("borders", BORDER_COLOR)] {
+        if let Some(path) = matches.get_one::<String>(name) {
+            layers.push(MapLayer {
+                lines: read_shapes(Path::new(path))?,
+                color,
+            });
+        }
+    }
+    let graticule = matches.get_one::<f64>("graticule").copied();
+
+    if layers.is_empty() && graticule.is_none() {
+        return Ok(None);
+    }
+    Ok(Some(Overlay {
+        layers,
+        graticule,
+        mode: matches
+            .get_one::<String>("overlay")
+            .map(|mode| mode.parse().expect("Unknown overlay mode"))
+            .unwrap_or_default(),
+    }))
+}
+
+// Parses floating point arguments, which have to lie within `range`, described by `expected`.
+fn float_in<T, R>(range: R, expected: &'static str) -> impl Fn(&str) -> Result<T, String> + Clone
+where
+    T: FromStr + PartialOrd + Display,
+    T::Err: Display,
+    R: RangeBounds<T> + Clone + Send + Sync + 'static,
+{
+    move |value| {
+        let value: T = value.parse().map_err(|err: T::Err| err.to_string())?;
+        if range.contains(&value) {
+            Ok(value)
+        } else {
+            Err(format!("{} is not {}", value, expected))
+        }
+    }
+}
+
+// The start time can be given or taken from the metadata of WAV files.
+fn recording_start(matches: &ArgMatches, input_format: InputFormat) -> Result<f64, DecoderError> {
+    if let Some(start) = matches.get_one::<String>("start") {
+        return parse_time(start);
+    }
+
+    match (input_format, matches.get_one::<String>("inputfile")) {
+        (InputFormat::Wav, Some(path)) if path != "-" => wav_recording_time(Path::new(path)),
+        _ => None,
+    }
+    .ok_or(DecoderError::MissingStartTime)
+}
+
+pub fn decode(input_path: &str, output_path: &str, options: &DecoderOptions) {
     println!("Decoding {} to {}", input_path, output_path);
 
+    // Ctrl+C ends the input, so everything decoded so far is still written
+    let stop = Arc::new(AtomicBool::new(false));
+    let handler_stop = stop.clone();
+    if let Err(error) = ctrlc::set_handler(move || handler_stop.store(true, Ordering::Relaxed)) {
+        println!("Unable to install Ctrl+C handler: {}", error);
+    }
+    let options = DecoderOptions {
+        stop: Some(stop),
+        ..options.clone()
+    };
+
+    if let Some(model) = &options.doppler {
+        print_doppler(model);
+    }
+    if let Some(georeference) = &options.georeference {
+        match georeference.format {
+            Some(format) => println!(
+                "Georeferencing as {} from {}",
+                format,
+                format_time(georeference.start)
+            ),
+            None => println!("Georeferencing from {}", format_time(georeference.start)),
+        }
+    }
+
     let bar = ProgressBar::new(STEPS).with_style(
         ProgressStyle::default_bar()
-            .template("{spinner:.green} [{wide_bar}] {percent}% ({eta})")
+            .template("{spinner:.green} [{wide_bar}] {percent}% ({eta}) {msg}")
             .unwrap()
             .progress_chars("=> "),
     );
-    let res = decoder::decode(input_path, output_path, |progress, _| {
+    let res = decoder::decode(input_path, output_path, &options, |progress, image| {
         bar.set_position((progress * STEPS as f32) as u64);
+        bar.set_message(format!("{} lines", image.height()));
         (true, STEPS as u32)
     });
     bar.finish();
 
-    if let Err(error) = res {
-        println!("Unable to decode file: {}", error);
+    match res {
+        Err(error) => println!("Unable to decode file: {}", error),
+        Ok(decoded) => {
+            if let Some(decoded) = decoded {
+                print_sync_quality(&decoded.sync_quality);
+                print_subcarrier_offsets(&decoded.subcarrier_offsets);
+                if let Some(model) = &options.doppler {
+                    let duration = decoded.lines.len() as f64 / LINES_PER_SECOND as f64;
+                    print_doppler_curve(model, duration);
+                }
+                if decoded.pixel_rate != PIXEL_RATE as f64 {
+                    println!(
+                        "Pixel rate approximated as {:.3} Hz instead of {} Hz",
+                        decoded.pixel_rate, PIXEL_RATE
+                    );
+                }
+                if let Some(drift) = decoded.drift_ppm {
+                    println!("Sample clock drift: {:+.1} ppm", drift);
+                }
+                match &decoded.direction {
+                    Some(direction) => println!("Pass direction: {}", direction),
+                    None => println!("Pass direction: Unknown"),
+                }
+                if decoded.rotated {
+                    println!("Rotated by 180°");
+                }
+                println!("Normalization: {}", decoded.normalization);
+                if !options.enhancement.is_identity() {
+                    println!("Enhancement: {}", options.enhancement);
+                }
+                print_ground_track(&decoded.ground_control_points);
+                if let Some(map) = &decoded.map {
+                    println!(
+                        "{} map: {} x {} pixels, {:.2}, {:.2} to {:.2}, {:.2}",
+                        map.projection,
+                        map.channel_a.width(),
+                        map.channel_a.height(),
+                        map.bounds.west,
+                        map.bounds.south,
+                        map.bounds.east,
+                        map.bounds.north
+                    );
+                }
+                print_telemetry(&decoded.telemetry);
+                if let Some(calibration) = &decoded.calibration {
+                    print_calibration("A", &calibration.channel_a);
+                    print_calibration("B", &calibration.channel_b);
+                }
+            }
+            println!("Done!")
+        }
+    }
+}
+
+fn print_doppler(model: &DopplerModel) {
+    let name = &model.tracker.sgp4.tle.name;
+    match model.shift(0.0) {
+        Ok(shift) => println!(
+            "Doppler correction for {}: {:+.0} Hz at the start",
+            if name.is_empty() { "satellite" } else { name },
+            shift
+        ),
+        Err(error) => println!("Doppler correction not possible: {}", error),
+    }
+}
+
+fn print_doppler_curve(model: &DopplerModel, duration: f64) {
+    match model.curve(duration, DOPPLER_REPORT_INTERVAL) {
+        Ok(curve) => {
+            println!("Doppler curve of the pass:");
+            for (time, shift) in curve {
+                println!("  {:6.1}s {:+7.0} Hz", time, shift);
+            }
+        }
+        Err(error) => println!("Doppler curve not available: {}", error),
+    }
+}
+
+fn print_ground_track(points: &[GroundControlPoint]) {
+    // Centers of the first and last line
+    let center = IMAGE_WIDTH as u32 / 2;
+    let mut centers = points.iter().filter(|p| p.column == center);
+    if let (Some(first), Some(last)) = (centers.next(), centers.next_back()) {
+        println!(
+            "Ground track: {:.2}, {:.2} to {:.2}, {:.2}",
+            first.latitude, first.longitude, last.latitude, last.longitude
+        );
+    }
+}
+
+fn print_sync_quality(sync_quality: &[f32]) {
+    let synced: Vec<f32> = sync_quality.iter().cloned().filter(|q| *q > 0.0).collect();
+    let mean = if synced.is_empty() {
+        0.0
     } else {
-        println!("Done!")
+        synced.iter().sum::<f32>() / synced.len() as f32
+    };
+    println!(
+        "Synced lines: {} of {} (mean confidence {:.2})",
+        synced.len(),
+        sync_quality.len(),
+        mean
+    );
+}
+
+fn print_subcarrier_offsets(offsets: &[SubcarrierOffset]) {
+    if offsets.is_empty() {
+        return;
+    }
+
+    let (min, max) = offsets.iter().fold((f32::MAX, f32::MIN), |(min, max), o| {
+        (min.min(o.offset), max.max(o.offset))
+    });
+    println!("Subcarrier offset: {:+.1} Hz - {:+.1} Hz", min, max);
+
+    // One value every 10 seconds is enough to see the trend
+    let mut next_time = 0.0;
+    for offset in offsets {
+        if offset.time >= next_time {
+            println!("  {:6.1}s {:+7.1} Hz", offset.time, offset.offset);
+            next_time = offset.time + SUBCARRIER_REPORT_INTERVAL;
+        }
+    }
+}
+
+fn print_telemetry(telemetry: &Telemetry) {
+    println!("Telemetry frames: {}", telemetry.frames.len());
+    println!("Channel A: {}", channel_name(telemetry.channel_a));
+    println!("Channel B: {}", channel_name(telemetry.channel_b));
+}
+
+fn print_calibration(name: &str, channel: &Option<CalibratedChannel>) {
+    match channel {
+        Some(channel) => {
+            let (min, max) = channel
+                .values
+                .iter()
+                .filter(|value| value.is_finite())
+                .fold((f32::MAX, f32::MIN), |(min, max), value| {
+                    (min.min(*value), max.max(*value))
+                });
+            if min <= max {
+                println!(
+                    "Channel {} calibrated: {:.1}{} - {:.1}{}",
+                    name, min, channel.unit, max, channel.unit
+                );
+            } else {
+                println!("Channel {} calibration failed", name);
+            }
+        }
+        None => println!("Channel {} not calibrated, no telemetry found", name),
+    }
+}
+
+pub fn channel_name(channel: Option<AVHRRChannel>) -> String {
+    match channel {
+        Some(channel) => channel.to_string(),
+        None => "unknown".to_string(),
     }
 }
@@ -0,0 +1,105 @@
+//! False colour composites built from both image channels.
+//!
+//! A palette is a 2D lookup table, the visible channel selects the column
+//! and the infrared channel selects the row of the colour used for each pixel.
+//! Palettes can be loaded from PNG files as used by other APT tools,
+//! the image is scaled to 256 x 256 if it has a different size.
+
+use std::path::Path;
+
+use image::{imageops, GrayImage, Rgb, RgbImage};
+
+use decoder::{IMAGE_A_START, IMAGE_B_START, IMAGE_WIDTH};
+use errors::DecoderError;
+use telemetry::Telemetry;
+
+const PALETTE_SIZE: u32 = 256;
+
+/// 2D colour lookup table indexed by the visible and infrared pixel values.
+#[derive(Clone, Debug)]
+pub struct Palette {
+    colors: RgbImage,
+}
+
+impl Palette {
+    /// Loads a palette from an image file, x is the visible and y the infrared value.
+    pub fn from_file(path: &Path) -> Result<Palette, DecoderError> {
+        let colors = image::open(path)
+            .map_err(DecoderError::PaletteError)?
+            .to_rgb8();
+
+        let colors = if colors.dimensions() != (PALETTE_SIZE, PALETTE_SIZE) {
+            imageops::resize(
+                &colors,
+                PALETTE_SIZE,
+                PALETTE_SIZE,
+                imageops::FilterType::Triangle,
+            )
+        } else {
+            colors
+        };
+
+        Ok(Palette { colors })
+    }
+
+    /// Simple false colour palette, that needs no external files.
+    ///
+    /// Cold and bright pixels are rendered as white clouds,
+    /// dark pixels as blue water and everything in between as green or brown land.
+    pub fn false_color() -> Palette {
+        let colors = RgbImage::from_fn(PALETTE_SIZE, PALETTE_SIZE, |x, y| {
+            let visible = x as f32 / (PALETTE_SIZE - 1) as f32;
+            // Cold is bright in the infrared channels
+            let cold = y as f32 / (PALETTE_SIZE - 1) as f32;
+
+            let water = [0.05, 0.12, 0.35];
+            let land = [
+                0.15 + 0.5 * visible,
+                0.3 + 0.3 * visible,
+                0.1 + 0.2 * visible,
+            ];
+            let ground_mix = smoothstep(0.1, 0.25, visible);
+            let cloud_mix = smoothstep(0.35, 0.75, cold) * smoothstep(0.15, 0.5, visible);
+
+            let mut rgb = [0; 3];
+            for (i, channel) in rgb.iter_mut().enumerate() {
+                let ground = water[i] * (1.0 - ground_mix) + land[i] * ground_mix;
+                let value = ground * (1.0 - cloud_mix) + visible * cloud_mix;
+                *channel = (value.clamp(0.0, 1.0) * 255.0) as u8;
+            }
+            Rgb(rgb)
+        });
+
+        Palette { colors }
+    }
+
+    /// Returns the colour for a pair of visible and infrared pixel values.
+    pub fn lookup(&self, visible: u8, infrared: u8) -> Rgb<u8> {
+        *self.colors.get_pixel(visible as u32, infrared as u32)
+    }
+}
+
+/// Builds a colour composite from the channels of a decoded image.
+///
+/// Channel A is expected to be the visible and channel B the infrared channel,
+/// unless the telemetry says otherwise.
+pub fn composite(image: &GrayImage, telemetry: &Telemetry, palette: &Palette) -> RgbImage {
+    let swapped = telemetry.channel_a.map(|c| c.is_thermal()) == Some(true)
+        && telemetry.channel_b.map(|c| c.is_thermal()) == Some(false);
+    let (visible_start, infrared_start) = if swapped {
+        (IMAGE_B_START, IMAGE_A_START)
+    } else {
+        (IMAGE_A_START, IMAGE_B_START)
+    };
+
+    RgbImage::from_fn(IMAGE_WIDTH as u32, image.height(), |x, y| {
+        let visible = image.get_pixel(visible_start as u32 + x, y)[0];
+        let infrared = image.get_pixel(infrared_start as u32 + x, y)[0];
+        palette.lookup(visible, infrared)
+    })
+}
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
@@ -1,116 +1,941 @@
-use std::path::Path;
+//! File based entry points and the glue code wiring up the signal processing chain.
 
-use amdemod::SquaringAMDemodulator;
+use std::cell::RefCell;
+use std::fmt;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use image::{ImageBuffer, Luma};
+use num_complex::Complex;
+use tiff::encoder::{colortype, TiffEncoder};
+
+use amdemod::{AMDemodulation, AMDemodulator, AMDemodulatorKind, TrackingAMDemodulator};
 use aptsyncer::{APTSyncer, SyncedSample};
+use calibration::{CalibratedChannel, Calibration, Satellite};
+use composite::{composite, Palette};
+use direction::{detect_direction, rotate_channels, DetectedDirection, Rotation};
+use doppler::{DopplerCorrection, DopplerModel};
+use enhance::Enhancement;
 use errors::DecoderError;
-use firfilter::FIRFilter;
-use resamplers::{Downsampler, Upsampler};
-use utils::float_sample_iterator;
-
-const LINES_PER_SECOND: u32 = 2;
-const PIXELS_PER_LINE: u32 = 2080;
-
-const LOWPASS_COEFFS: [f32; 63] = [
-    -7.383784e-03,
-    -3.183046e-03,
-    2.255039e-03,
-    7.461166e-03,
-    1.091908e-02,
-    1.149109e-02,
-    8.769802e-03,
-    3.252932e-03,
-    -3.720606e-03,
-    -1.027446e-02,
-    -1.447403e-02,
-    -1.486427e-02,
-    -1.092423e-02,
-    -3.307958e-03,
-    6.212477e-03,
-    1.511364e-02,
-    2.072873e-02,
-    2.096037e-02,
-    1.492345e-02,
-    3.347624e-03,
-    -1.138407e-02,
-    -2.560252e-02,
-    -3.507114e-02,
-    -3.591225e-02,
-    -2.553830e-02,
-    -3.371569e-03,
-    2.882645e-02,
-    6.711368e-02,
-    1.060042e-01,
-    1.394643e-01,
-    1.620650e-01,
-    1.700462e-01,
-    1.620650e-01,
-    1.394643e-01,
-    1.060042e-01,
-    6.711368e-02,
-    2.882645e-02,
-    -3.371569e-03,
-    -2.553830e-02,
-    -3.591225e-02,
-    -3.507114e-02,
-    -2.560252e-02,
-    -1.138407e-02,
-    3.347624e-03,
-    1.492345e-02,
-    2.096037e-02,
-    2.072873e-02,
-    1.511364e-02,
-    6.212477e-03,
-    -3.307958e-03,
-    -1.092423e-02,
-    -1.486427e-02,
-    -1.447403e-02,
-    -1.027446e-02,
-    -3.720606e-03,
-    3.252932e-03,
-    8.769802e-03,
-    1.149109e-02,
-    1.091908e-02,
-    7.461166e-03,
-    2.255039e-03,
-    -3.183046e-03,
-    -7.383784e-03,
-];
+use firfilter::{lowpass_coeffs, FIRFilter};
+use fmdemod::{DeEmphasis, QuadratureDemodulator, APT_DEVIATION};
+use georef::{
+    tie_points, write_gcp_table, write_qgis_points, write_world_file, GeoFormat, Georeferencing,
+    GroundControlPoint, GCP_LINE_STEP,
+};
+use geotiff;
+use metadata::Metadata;
+use normalization::{normalize, Normalization};
+use npy;
+use overlay::{burn_in, Overlay, OverlayMode};
+use reproject::{map_suffix, reproject, MapGrid, MapImage};
+use resamplers::{resampled_rate, RationalResampler};
+use subcarrier::SubcarrierOffset;
+use telemetry::Telemetry;
+use timing::TimingRecovery;
+use utils::{
+    complex_sample_iterator, complex_wav_iterator, float_sample_iterator, open_input,
+    raw_sample_iterator, ChannelSelection, IQFormat, RawFormat,
+};
+
+/// Number of lines transmitted per second.
+pub const LINES_PER_SECOND: u32 = 2;
+/// Number of pixels in a line, including sync, space view and telemetry for both channels.
+pub const PIXELS_PER_LINE: u32 = 2080;
+
+/// First column of the image of channel A.
+pub const IMAGE_A_START: usize = 86;
+/// First column of the image of channel B.
+pub const IMAGE_B_START: usize = 1126;
+/// Width of the image of each channel.
+pub const IMAGE_WIDTH: usize = 909;
+
+/// Rate of the pixels in the demodulated signal in Hz.
+pub const PIXEL_RATE: u32 = LINES_PER_SECOND * PIXELS_PER_LINE;
+
+/// The AM signal spans 2400Hz +/- 2080Hz, so anything below twice its upper edge can not work.
+pub const MIN_SAMPLE_RATE: u32 = 9000;
+
+const LOWPASS_CUTOFF: f32 = 4000.0;
+const LOWPASS_TAPS: usize = 63;
+
+/// Default names of the channel images, relative to the output file.
+pub const DEFAULT_CHANNEL_NAMES: &str = "{stem}-{channel}.png";
+
+// IQ recordings are resampled to this rate before the FM demodulator.
+const CHANNEL_RATE: u32 = 48000;
+const CHANNEL_TAPS: usize = 63;
+
+/// Format of the input file.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum InputFormat {
+    /// WAV file containing the FM demodulated signal
+    Wav,
+    /// Headerless stream of the FM demodulated signal, e.g. from `rtl_fm`
+    Raw(RawFormat),
+    /// Raw interleaved IQ recording, that still needs to be FM demodulated
+    IQ(IQFormat),
+}
+
+/// One of the two image channels of an APT line.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImageChannel {
+    A,
+    B,
+}
+
+impl ImageChannel {
+    /// First column of the image of the channel.
+    pub fn image_start(&self) -> usize {
+        match self {
+            ImageChannel::A => IMAGE_A_START,
+            ImageChannel::B => IMAGE_B_START,
+        }
+    }
+
+    /// First column of the half line of the channel, starting with its sync marker.
+    pub fn frame_start(&self) -> u32 {
+        match self {
+            ImageChannel::A => 0,
+            ImageChannel::B => PIXELS_PER_LINE / 2,
+        }
+    }
+}
+
+impl FromStr for ImageChannel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "a" => Ok(ImageChannel::A),
+            "b" => Ok(ImageChannel::B),
+            _ => Err(format!("Unknown channel {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for ImageChannel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ImageChannel::A => write!(f, "A"),
+            ImageChannel::B => write!(f, "B"),
+        }
+    }
+}
+
+/// Images written by [`decode`].
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub enum ImageLayout {
+    /// Complete lines with sync, space view, both channels and telemetry
+    #[default]
+    FullFrame,
+    /// A separate image for each channel
+    Channels,
+    /// The full frame and the separate channel images
+    Both,
+}
+
+impl ImageLayout {
+    fn has_full_frame(&self) -> bool {
+        *self != ImageLayout::Channels
+    }
+
+    fn has_channels(&self) -> bool {
+        *self != ImageLayout::FullFrame
+    }
+}
+
+impl FromStr for ImageLayout {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "full" => Ok(ImageLayout::FullFrame),
+            "channels" => Ok(ImageLayout::Channels),
+            "both" => Ok(ImageLayout::Both),
+            _ => Err(format!("Unknown image layout {}", s)),
+        }
+    }
+}
+
+/// File format of the 16 bit copy of the image.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HighDepthFormat {
+    Png,
+    Tiff,
+}
+
+impl HighDepthFormat {
+    fn suffix(&self) -> &'static str {
+        match self {
+            HighDepthFormat::Png => "-16bit.png",
+            HighDepthFormat::Tiff => "-16bit.tif",
+        }
+    }
+}
+
+impl FromStr for HighDepthFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "png" => Ok(HighDepthFormat::Png),
+            "tiff" => Ok(HighDepthFormat::Tiff),
+            _ => Err(format!("Unknown image format {}", s)),
+        }
+    }
+}
+
+/// Settings for decoding a recording.
+#[derive(Clone, Debug)]
+pub struct DecoderOptions {
+    pub input_format: InputFormat,
+    /// Channel of stereo and multi channel WAV files to decode.
+    pub wav_channel: ChannelSelection,
+    /// Sampling rate of raw audio and IQ recordings, WAV files contain their own.
+    pub sample_rate: Option<u32>,
+    /// Bandwidth of the channel filter in front of the FM demodulator in Hz.
+    pub channel_bandwidth: f32,
+    /// Demodulator for the AM subcarrier.
+    pub demodulator: AMDemodulatorKind,
+    /// Time constant of the optional de-emphasis filter behind the FM demodulator in seconds.
+    pub deemphasis: Option<f32>,
+    /// Doppler shift to remove from IQ recordings before the FM demodulator.
+    pub doppler: Option<DopplerModel>,
+    /// Satellite the recording is from, needed for the radiometric calibration.
+    pub satellite: Option<Satellite>,
+    /// Scaling of the pixel values to grey levels.
+    pub normalization: Normalization,
+    /// Enhancement of both channels, applied before the image is written and reprojected.
+    pub enhancement: Enhancement,
+    /// Images written to the output files.
+    pub layout: ImageLayout,
+    /// Crop the channel images to the 909 pixels of the image, without sync, space view and telemetry.
+    pub crop_channels: bool,
+    /// Names of the channel images relative to the output file,
+    /// `{stem}` is replaced by the name of the output file without extension and `{channel}` by `A` or `B`.
+    pub channel_names: String,
+    /// Also write the full frame with 16 bit per pixel, scaled to the brightest synced pixel.
+    pub high_depth: Option<HighDepthFormat>,
+    /// Write the unscaled, synced lines as float32 `.npy` file next to the output image.
+    pub write_lines: bool,
+    /// Write the calibrated channels as `.npy` files next to the output image.
+    pub write_calibration: bool,
+    /// Write the metadata with the sync quality of every line as `.json` file next to the output image.
+    pub write_metadata: bool,
+    /// Palette for a false colour composite, written next to the output image.
+    pub palette: Option<Palette>,
+    /// Orbit and start time of the recording, to georeference the output image.
+    pub georeference: Option<Georeferencing>,
+    /// Map data drawn over the image, needs [`DecoderOptions::georeference`].
+    pub overlay: Option<Overlay>,
+    /// Map grid both channels are reprojected to, needs [`DecoderOptions::georeference`].
+    pub map: Option<MapGrid>,
+    /// When to rotate the images by 180°, depending on the direction of the pass.
+    pub rotation: Rotation,
+    /// Setting this flag ends decoding as if the input ended, e.g. from a signal handler.
+    pub stop: Option<Arc<AtomicBool>>,
+}
+
+/// Result of decoding a recording.
+///
+/// If the images were rotated, `lines`, `sync_quality` and `telemetry` stay in the order of reception.
+pub struct DecodedImage {
+    /// Unscaled pixel values of each line, as they came out of the demodulator
+    pub lines: Vec<Vec<f32>>,
+    /// Confidence of the sync A marker starting each line, 0.0 if none was found
+    pub sync_quality: Vec<f32>,
+    /// Measured offsets of the subcarrier from 2400Hz, if the subcarrier was tracked
+    pub subcarrier_offsets: Vec<SubcarrierOffset>,
+    /// Sampling rate of the input in Hz, before any resampling
+    pub sample_rate: u32,
+    /// Rate of the resampled pixels in Hz, differs from `PIXEL_RATE` if the ratio of the
+    /// sampling rates had to be approximated
+    pub pixel_rate: f64,
+    /// Estimated deviation of the sample clock from its nominal rate in ppm, if any sync was found
+    pub drift_ppm: Option<f32>,
+    /// Image scaled to 8 bit, as it is written to the output file
+    pub image: image::GrayImage,
+    /// Image before the enhancement, if one was applied
+    pub unenhanced: Option<image::GrayImage>,
+    /// Scaling of the image, differs from the requested one if it was not possible
+    pub normalization: Normalization,
+    /// Telemetry extracted from the telemetry strips next to both channels
+    pub telemetry: Telemetry,
+    /// Calibrated channels, if the satellite was known
+    pub calibration: Option<Calibration>,
+    /// False colour composite, if a palette was given
+    pub composite: Option<image::RgbImage>,
+    /// Location of a few pixels on every line, if the image was georeferenced.
+    /// Both channels are scanned at the same time, so they apply to the columns of either channel.
+    pub ground_control_points: Vec<GroundControlPoint>,
+    /// Transparent image with the map overlay, if one was requested
+    pub overlay: Option<image::RgbaImage>,
+    /// Both channels reprojected onto a map, if a map grid was given
+    pub map: Option<MapImage>,
+    /// Direction of the pass, if it could be detected
+    pub direction: Option<DetectedDirection>,
+    /// True if the images, calibrated channels and ground control points were rotated by 180°
+    pub rotated: bool,
+}
+
+impl Default for DecoderOptions {
+    fn default() -> Self {
+        Self {
+            input_format: InputFormat::Wav,
+            wav_channel: ChannelSelection::default(),
+            sample_rate: None,
+            channel_bandwidth: 40000.0,
+            demodulator: AMDemodulatorKind::default(),
+            deemphasis: None,
+            doppler: None,
+            satellite: None,
+            normalization: Normalization::default(),
+            enhancement: Enhancement::default(),
+            layout: ImageLayout::default(),
+            crop_channels: false,
+            channel_names: DEFAULT_CHANNEL_NAMES.to_string(),
+            high_depth: None,
+            write_lines: false,
+            write_calibration: false,
+            write_metadata: false,
+            palette: None,
+            georeference: None,
+            overlay: None,
+            map: None,
+            rotation: Rotation::default(),
+            stop: None,
+        }
+    }
+}
 
+/// Decodes the recording at `input_file` and writes the resulting image as PNG to `output_file`.
+///
+/// Depending on [`DecoderOptions::layout`] the channels are written as separate images instead or as well,
+/// named by [`DecoderOptions::channel_names`].
+/// Georeferencing files always refer to the full frame.
+/// `progress_update` is called periodically with the progress (0.0 - 1.0) and a preview of the image.
+/// It returns whether decoding should continue and into how many steps the progress updates should be split.
+/// If decoding is canceled no output file is written and `None` is returned.
+/// Information about the recording and the decoded pass is stored as PNG text chunks,
+/// see [`Metadata`].
+/// With [`DecoderOptions::write_metadata`] it is also written to `<output_file stem>.json`,
+/// with the sync quality of every line.
+///
+/// With [`DecoderOptions::high_depth`] the full frame is also written with 16 bit per pixel
+/// to `<output_file stem>-16bit.png` or `<output_file stem>-16bit.tif`, see [`high_depth_image`].
+/// With [`DecoderOptions::write_lines`] the unscaled lines are written to `<output_file stem>-lines.npy`,
+/// in the order of reception with 2080 values per line.
+/// With [`DecoderOptions::write_calibration`] the calibrated channels are written
+/// to `<output_file stem>-A.npy` and `<output_file stem>-B.npy`.
+/// With [`DecoderOptions::palette`] the composite is written to `<output_file stem>-composite.png`.
+/// With [`DecoderOptions::georeference`] the ground control points are written to `<output_file stem>-gcp.csv`,
+/// depending on the format either with a GeoTIFF copy of the image as `<output_file stem>.tif`
+/// or with a world file `<output_file stem>.pgw` and the QGIS georeferencer points `<output_file>.points`.
+/// The GeoTIFF and QGIS files locate channel A.
+/// With [`DecoderOptions::overlay`] the map overlay is written to `<output_file stem>-overlay.png`,
+/// either drawn over the image or as transparent layer.
+/// With [`DecoderOptions::map`] the reprojected channels are written to `<output_file stem>-A-map.png`
+/// and `<output_file stem>-B-map.png`, each with a world file,
+/// the projection and time of the pass are written to `<output_file stem>-map.txt`.
 pub fn decode<T>(
     input_file: &str,
     output_file: &str,
+    options: &DecoderOptions,
     progress_update: T,
-) -> Result<(), DecoderError>
+) -> Result<Option<DecodedImage>, DecoderError>
 where
     T: Fn(f32, image::RgbaImage) -> (bool, u32),
 {
-    let mut reader =
-        hound::WavReader::open(input_file).map_err(|err| DecoderError::InputFileError(err))?;
+    // Rejects a broken channel name template before the recording is decoded
+    if options.layout.has_channels() {
+        channel_path(output_file, &options.channel_names, ImageChannel::A)?;
+    }
+    let decoded = decode_image(input_file, options, progress_update)?;
+
+    if let Some(decoded) = &decoded {
+        let metadata = Metadata::new(input_file, decoded, options);
+        save_images(output_file, &decoded.image, &metadata, options)?;
+        if let Some(format) = options.high_depth {
+            let path = sibling_path(output_file, format.suffix());
+            let image = high_depth_image(decoded);
+            match format {
+                HighDepthFormat::Png => {
+                    // PNG stores 16 bit samples big endian
+                    let bytes: Vec<u8> = image.iter().flat_map(|v| v.to_be_bytes()).collect();
+                    write_image(
+                        &path,
+                        image.width(),
+                        image.height(),
+                        png::BitDepth::Sixteen,
+                        &bytes,
+                        &metadata,
+                    )?;
+                }
+                HighDepthFormat::Tiff => write_tiff16(&path, &image)?,
+            }
+        }
+        if options.write_lines {
+            let values: Vec<f32> = decoded.lines.concat();
+            npy::write_f32(
+                &sibling_path(output_file, "-lines.npy"),
+                decoded.lines.len(),
+                PIXELS_PER_LINE as usize,
+                &values,
+            )
+            .map_err(DecoderError::OutputIOError)?;
+        }
+        if options.write_metadata {
+            metadata
+                .write_json(&sibling_path(output_file, ".json"), &decoded.sync_quality)
+                .map_err(DecoderError::OutputIOError)?;
+        }
+
+        if let Some(composite) = &decoded.composite {
+            composite
+                .save_with_format(
+                    sibling_path(output_file, "-composite.png"),
+                    image::ImageFormat::Png,
+                )
+                .map_err(DecoderError::OutputFileError)?;
+        }
+
+        if let Some(format) = options.georeference.as_ref().and_then(|g| g.format) {
+            write_georeference(output_file, decoded, format)?;
+        }
+
+        if let (Some(overlay), Some(layer)) = (&options.overlay, &decoded.overlay) {
+            let path = sibling_path(output_file, "-overlay.png");
+            match overlay.mode {
+                OverlayMode::BurnIn => {
+                    burn_in(&decoded.image, layer).save_with_format(path, image::ImageFormat::Png)
+                }
+                OverlayMode::Layer => layer.save_with_format(path, image::ImageFormat::Png),
+            }
+            .map_err(DecoderError::OutputFileError)?;
+        }
+
+        if let Some(map) = &decoded.map {
+            write_map(output_file, map)?;
+        }
+
+        if let (true, Some(calibration)) = (options.write_calibration, &decoded.calibration) {
+            if let Some(channel) = &calibration.channel_a {
+                write_calibrated_channel(channel, &sibling_path(output_file, "-A.npy"))?;
+            }
+            if let Some(channel) = &calibration.channel_b {
+                write_calibrated_channel(channel, &sibling_path(output_file, "-B.npy"))?;
+            }
+        }
+    }
+
+    Ok(decoded)
+}
+
+/// Writes the full frame `image` and its channels to `output_file` as [`decode`] does,
+/// depending on [`DecoderOptions::layout`], e.g. after enhancing a decoded image again.
+pub fn save_images(
+    output_file: &str,
+    image: &image::GrayImage,
+    metadata: &Metadata,
+    options: &DecoderOptions,
+) -> Result<(), DecoderError> {
+    if options.layout.has_full_frame() {
+        save_image(Path::new(output_file), image, metadata)?;
+    }
+    if options.layout.has_channels() {
+        for channel in [ImageChannel::A, ImageChannel::B] {
+            let path = channel_path(output_file, &options.channel_names, channel)?;
+            let channel = channel_image(image, channel, options.crop_channels);
+            save_image(&path, &channel, metadata)?;
+        }
+    }
+    Ok(())
+}
+
+/// Writes an 8 bit image as PNG with the metadata as text chunks.
+pub fn save_image(
+    path: &Path,
+    image: &image::GrayImage,
+    metadata: &Metadata,
+) -> Result<(), DecoderError> {
+    write_image(
+        path,
+        image.width(),
+        image.height(),
+        png::BitDepth::Eight,
+        image.as_raw(),
+        metadata,
+    )
+}
+
+// Writes a greyscale image with the metadata as PNG text chunks.
+fn write_image(
+    path: &Path,
+    width: u32,
+    height: u32,
+    depth: png::BitDepth,
+    data: &[u8],
+    metadata: &Metadata,
+) -> Result<(), DecoderError> {
+    let file = File::create(path).map_err(DecoderError::OutputIOError)?;
+    let mut encoder = png::Encoder::new(BufWriter::new(file), width, height);
+    encoder.set_color(png::ColorType::Grayscale);
+    encoder.set_depth(depth);
+
+    for (keyword, text) in metadata.text_chunks() {
+        // tEXt chunks are Latin-1, file names may need the UTF-8 of iTXt
+        if text.is_ascii() {
+            encoder.add_text_chunk(keyword, text)?;
+        } else {
+            encoder.add_itxt_chunk(keyword, text)?;
+        }
+    }
+
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(data)?;
+    writer.finish()?;
+    Ok(())
+}
+
+fn write_tiff16(path: &Path, image: &ImageBuffer<Luma<u16>, Vec<u16>>) -> Result<(), DecoderError> {
+    let file = File::create(path).map_err(DecoderError::OutputIOError)?;
+    TiffEncoder::new(BufWriter::new(file))
+        .and_then(|mut encoder| {
+            encoder.write_image::<colortype::Gray16>(image.width(), image.height(), image.as_raw())
+        })
+        .map_err(DecoderError::TiffError)
+}
+
+/// Scales the unscaled lines of `decoded` to 16 bit, rotated like the 8 bit image.
+///
+/// Unlike the 8 bit image, which follows the running maximum while decoding,
+/// all lines share one scale, set by the brightest pixel on a line with a sync marker.
+/// Without any sync the brightest pixel of the whole recording is used.
+pub fn high_depth_image(decoded: &DecodedImage) -> ImageBuffer<Luma<u16>, Vec<u16>> {
+    let brightest = |synced_only: bool| {
+        decoded
+            .lines
+            .iter()
+            .zip(&decoded.sync_quality)
+            .filter(|(_, quality)| !synced_only || **quality > 0.0)
+            .flat_map(|(line, _)| line.iter().cloned())
+            .fold(0.0, f32::max)
+    };
+    let mut max_level = brightest(true);
+    if max_level <= 0.0 {
+        max_level = brightest(false);
+    }
+
+    let values: Vec<u16> = decoded
+        .lines
+        .iter()
+        .flatten()
+        .map(|sample| {
+            if max_level > 0.0 {
+                (sample / max_level * 65535.0).round().clamp(0.0, 65535.0) as u16
+            } else {
+                0
+            }
+        })
+        .collect();
+    let mut image = ImageBuffer::from_raw(PIXELS_PER_LINE, decoded.lines.len() as u32, values)
+        .expect("Pixel buffer does not match the image size");
+    if decoded.rotated {
+        rotate_channels(&mut image);
+    }
+    image
+}
 
-    if reader.spec().channels != 1 {
-        panic!("Expected a mono file");
+/// Cuts the half line of `channel` out of a full frame `image`,
+/// or only the image of the channel if `cropped` is set.
+pub fn channel_image(
+    image: &image::GrayImage,
+    channel: ImageChannel,
+    cropped: bool,
+) -> image::GrayImage {
+    let (start, width) = if cropped {
+        (channel.image_start() as u32, IMAGE_WIDTH as u32)
+    } else {
+        (channel.frame_start(), PIXELS_PER_LINE / 2)
+    };
+    image::imageops::crop_imm(image, start, 0, width, image.height()).to_image()
+}
+
+/// Expands the file name template `names` for the image of `channel`, next to `output_file`.
+///
+/// The template has to contain `{channel}`, otherwise both channels end up in the same file.
+pub fn channel_path(
+    output_file: &str,
+    names: &str,
+    channel: ImageChannel,
+) -> Result<PathBuf, DecoderError> {
+    if !names.contains("{channel}") {
+        return Err(DecoderError::InvalidTemplate(names.to_string()));
     }
+    let path = Path::new(output_file);
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let name = names
+        .replace("{stem}", &stem)
+        .replace("{channel}", &channel.to_string());
+    Ok(path.with_file_name(name))
+}
+
+// Builds a path next to `path` by replacing its extension with `suffix`.
+pub(crate) fn sibling_path(path: &str, suffix: &str) -> PathBuf {
+    let path = Path::new(path);
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default();
+    path.with_file_name(stem + suffix)
+}
+
+fn write_georeference(
+    output_file: &str,
+    decoded: &DecodedImage,
+    format: GeoFormat,
+) -> Result<(), DecoderError> {
+    let points = &decoded.ground_control_points;
+    write_gcp_table(&sibling_path(output_file, "-gcp.csv"), points)
+        .map_err(DecoderError::OutputIOError)?;
+
+    // A GCP on every line only slows down warping
+    let last_line = points.last().map(|p| p.line).unwrap_or(0);
+    let sparse: Vec<GroundControlPoint> = points
+        .iter()
+        .filter(|p| p.line % GCP_LINE_STEP == 0 || p.line == last_line)
+        .cloned()
+        .collect();
+
+    match format {
+        GeoFormat::GeoTiff => geotiff::write_gray(
+            &sibling_path(output_file, ".tif"),
+            &decoded.image,
+            &tie_points(&sparse, IMAGE_A_START),
+        )
+        .map_err(DecoderError::GeoTiffError),
+        GeoFormat::WorldFile => {
+            write_world_file(&sibling_path(output_file, ".pgw"), &sparse, IMAGE_A_START)
+                .map_err(DecoderError::OutputIOError)?;
+            write_qgis_points(
+                Path::new(&format!("{}.points", output_file)),
+                &sparse,
+                IMAGE_A_START,
+            )
+            .map_err(DecoderError::OutputIOError)
+        }
+    }
+}
+
+fn write_map(output_file: &str, map: &MapImage) -> Result<(), DecoderError> {
+    let world_file = map.world_file();
+    for (channel, image) in [
+        (ImageChannel::A, &map.channel_a),
+        (ImageChannel::B, &map.channel_b),
+    ] {
+        image
+            .save_with_format(
+                sibling_path(output_file, &format!("{}.png", map_suffix(channel))),
+                image::ImageFormat::Png,
+            )
+            .map_err(DecoderError::OutputFileError)?;
+        world_file
+            .write(&sibling_path(
+                output_file,
+                &format!("{}.pgw", map_suffix(channel)),
+            ))
+            .map_err(DecoderError::OutputIOError)?;
+    }
+    map.write_info(&sibling_path(output_file, "-map.txt"))
+        .map_err(DecoderError::OutputIOError)
+}
+
+fn write_calibrated_channel(channel: &CalibratedChannel, path: &Path) -> Result<(), DecoderError> {
+    npy::write_f32(path, channel.height, channel.width, &channel.values)
+        .map_err(DecoderError::OutputIOError)
+}
+
+/// Decodes the recording at `input_file` into a greyscale image.
+///
+/// `input_file` can be `-` to read from stdin or a named pipe.
+/// If the length of the input is unknown the image grows until the input ends.
+/// Returns `None` if decoding was canceled from `progress_update`, see [`decode`].
+pub fn decode_image<T>(
+    input_file: &str,
+    options: &DecoderOptions,
+    progress_update: T,
+) -> Result<Option<DecodedImage>, DecoderError>
+where
+    T: Fn(f32, image::RgbaImage) -> (bool, u32),
+{
+    let is_iq = match options.input_format {
+        InputFormat::Wav => options.wav_channel == ChannelSelection::IQ,
+        InputFormat::Raw(_) => false,
+        InputFormat::IQ(_) => true,
+    };
+    if options.doppler.is_some() && !is_iq {
+        return Err(DecoderError::DopplerNeedsIQ);
+    }
+    let needs_georeference = options.overlay.is_some() || options.map.is_some();
+    if needs_georeference && options.georeference.is_none() {
+        return Err(DecoderError::MissingGeoreference);
+    }
+
+    match options.input_format {
+        InputFormat::Wav => decode_wav(input_file, options, progress_update),
+        InputFormat::Raw(format) => decode_raw(input_file, format, options, progress_update),
+        InputFormat::IQ(format) => decode_iq(input_file, format, options, progress_update),
+    }
+}
+
+fn decode_wav<T>(
+    input_file: &str,
+    options: &DecoderOptions,
+    progress_update: T,
+) -> Result<Option<DecodedImage>, DecoderError>
+where
+    T: Fn(f32, image::RgbaImage) -> (bool, u32),
+{
+    let (input, length) = open_input(input_file)?;
+    let mut reader = hound::WavReader::new(input).map_err(DecoderError::InputFileError)?;
 
     let sample_rate = reader.spec().sample_rate;
-    if sample_rate != 48000 {
+    // Streams usually have a bogus length in their header
+    let sample_count = length.map(|_| reader.duration());
+    let is_stream = length.is_none();
+
+    let read_error = RefCell::new(None);
+    let decoded = if options.wav_channel == ChannelSelection::IQ {
+        if sample_rate < MIN_SAMPLE_RATE {
+            return Err(DecoderError::UnexpectedSamplingRate(sample_rate));
+        }
+        let audio_count = sample_count
+            .map(|count| (count as u64 * CHANNEL_RATE as u64 / sample_rate as u64) as u32);
+        let coeffs = channel_coeffs(options)?;
+        let samples = stop_on_error(complex_wav_iterator(&mut reader)?, &read_error);
+        let audio = fm_demodulate(samples, sample_rate, &coeffs, options);
+        decode_samples(audio, CHANNEL_RATE, audio_count, options, progress_update)?
+            .map(|decoded| from_channel_rate(decoded, sample_rate))
+    } else {
+        let samples = stop_on_error(
+            float_sample_iterator(&mut reader, options.wav_channel)?,
+            &read_error,
+        );
+        decode_samples(samples, sample_rate, sample_count, options, progress_update)?
+    };
+
+    match read_error.into_inner() {
+        None => Ok(decoded),
+        // hound reports running out of data as a generic IO error
+        Some(hound::Error::IoError(_)) => {
+            // Streams end wherever the recording was stopped
+            if is_stream {
+                Ok(decoded)
+            } else {
+                Err(DecoderError::TruncatedInput)
+            }
+        }
+        Some(error) => Err(DecoderError::CorruptInput(error)),
+    }
+}
+
+// Ends the samples at the first read error, which is kept in `error`.
+fn stop_on_error<'a, I, S, E>(
+    samples: I,
+    error: &'a RefCell<Option<E>>,
+) -> impl Iterator<Item = S> + 'a
+where
+    I: Iterator<Item = Result<S, E>> + 'a,
+    E: 'a,
+{
+    samples.map_while(move |sample| match sample {
+        Ok(sample) => Some(sample),
+        Err(read_error) => {
+            *error.borrow_mut() = Some(read_error);
+            None
+        }
+    })
+}
+
+fn decode_raw<T>(
+    input_file: &str,
+    format: RawFormat,
+    options: &DecoderOptions,
+    progress_update: T,
+) -> Result<Option<DecodedImage>, DecoderError>
+where
+    T: Fn(f32, image::RgbaImage) -> (bool, u32),
+{
+    let sample_rate = options
+        .sample_rate
+        .ok_or(DecoderError::MissingSamplingRate)?;
+
+    let (input, length) = open_input(input_file)?;
+    let sample_count = length.map(|length| (length / format.sample_size() as u64) as u32);
+    let read_error = RefCell::new(None);
+    let samples = stop_on_error(raw_sample_iterator(input, format), &read_error);
+    let decoded = decode_samples(samples, sample_rate, sample_count, options, progress_update);
+
+    // A read error also cuts the recording short, so it takes precedence
+    match read_error.into_inner() {
+        None => decoded,
+        Some(error) => Err(DecoderError::InputIOError(error)),
+    }
+}
+
+fn decode_iq<T>(
+    input_file: &str,
+    format: IQFormat,
+    options: &DecoderOptions,
+    progress_update: T,
+) -> Result<Option<DecodedImage>, DecoderError>
+where
+    T: Fn(f32, image::RgbaImage) -> (bool, u32),
+{
+    let sample_rate = options
+        .sample_rate
+        .ok_or(DecoderError::MissingSamplingRate)?;
+    if sample_rate < MIN_SAMPLE_RATE {
         return Err(DecoderError::UnexpectedSamplingRate(sample_rate));
     }
 
-    let sample_count = reader.len();
-    let seconds = (sample_count as f32) / (sample_rate as f32);
-    let lines = (seconds.ceil() as u32) * LINES_PER_SECOND;
+    let (input, length) = open_input(input_file)?;
+    let audio_count = length.map(|length| {
+        let sample_count = length / format.sample_size() as u64;
+        (sample_count * CHANNEL_RATE as u64 / sample_rate as u64) as u32
+    });
 
-    let mut img = image::DynamicImage::ImageLuma8(image::ImageBuffer::new(PIXELS_PER_LINE, lines));
+    let coeffs = channel_coeffs(options)?;
+    let read_error = RefCell::new(None);
+    let samples = stop_on_error(complex_sample_iterator(input, format), &read_error);
+    let audio = fm_demodulate(samples, sample_rate, &coeffs, options);
+    let decoded = decode_samples(audio, CHANNEL_RATE, audio_count, options, progress_update);
 
-    let coeffs = &LOWPASS_COEFFS;
+    // A read error also cuts the recording short, so it takes precedence
+    match read_error.into_inner() {
+        None => Ok(decoded?.map(|decoded| from_channel_rate(decoded, sample_rate))),
+        Some(error) => Err(DecoderError::InputIOError(error)),
+    }
+}
 
-    let samples = float_sample_iterator(&mut reader);
+// Restores the rate of the complex samples, the audio was decoded as if at exactly `CHANNEL_RATE`.
+fn from_channel_rate(decoded: DecodedImage, sample_rate: u32) -> DecodedImage {
+    let channel_rate = resampled_rate(sample_rate, CHANNEL_RATE);
+    DecodedImage {
+        sample_rate,
+        pixel_rate: decoded.pixel_rate * channel_rate / CHANNEL_RATE as f64,
+        ..decoded
+    }
+}
 
-    let demod = SquaringAMDemodulator::from(samples);
-    let filter = FIRFilter::from(demod, coeffs);
-    let upsampler = Upsampler::from(filter, 13);
-    let downsampler = Downsampler::from(upsampler, 150);
-    let syncer = APTSyncer::from(downsampler);
+// Coefficients of the channel filter in front of the FM demodulator,
+// also checks the de-emphasis behind it.
+fn channel_coeffs(options: &DecoderOptions) -> Result<Vec<f32>, DecoderError> {
+    if options.channel_bandwidth.is_nan() || options.channel_bandwidth <= 0.0 {
+        return Err(DecoderError::InvalidFilter(format!(
+            "channel bandwidth {}Hz",
+            options.channel_bandwidth
+        )));
+    }
+    if let Some(tau) = options.deemphasis.filter(|tau| tau.is_nan() || *tau <= 0.0) {
+        return Err(DecoderError::InvalidFilter(format!(
+            "de-emphasis time constant {}s",
+            tau
+        )));
+    }
+    let cutoff = f32::min(options.channel_bandwidth / 2.0 / CHANNEL_RATE as f32, 0.5);
+    Ok(lowpass_coeffs(cutoff, CHANNEL_TAPS))
+}
+
+// FM demodulates complex baseband samples to audio at `CHANNEL_RATE`.
+fn fm_demodulate<'a, I>(
+    samples: I,
+    sample_rate: u32,
+    coeffs: &'a [f32],
+    options: &DecoderOptions,
+) -> Box<dyn Iterator<Item = f32> + 'a>
+where
+    I: Iterator<Item = Complex<f32>> + 'a,
+{
+    let samples: Box<dyn Iterator<Item = Complex<f32>>> = match &options.doppler {
+        Some(model) => Box::new(DopplerCorrection::from(samples, sample_rate, model.clone())),
+        None => Box::new(samples),
+    };
+
+    let channel = RationalResampler::from(samples, sample_rate, CHANNEL_RATE);
+    let filter = FIRFilter::from(channel, coeffs);
+    let fm_demod = QuadratureDemodulator::from(filter, CHANNEL_RATE, APT_DEVIATION);
+
+    match options.deemphasis {
+        Some(tau) => Box::new(DeEmphasis::from(fm_demod, CHANNEL_RATE, tau)),
+        None => Box::new(fm_demod),
+    }
+}
+
+/// Decodes an already FM demodulated signal sampled at `sample_rate`.
+///
+/// `sample_count` is the expected number of samples and determines the height of the image.
+/// If it is `None` the image grows line by line until the samples run out,
+/// the progress reported to `progress_update` stays at 0.0 in that case.
+/// Returns `None` if decoding was canceled from `progress_update`, see [`decode`].
+pub fn decode_samples<'a, I, T>(
+    samples: I,
+    sample_rate: u32,
+    sample_count: Option<u32>,
+    options: &DecoderOptions,
+    progress_update: T,
+) -> Result<Option<DecodedImage>, DecoderError>
+where
+    I: Iterator<Item = f32> + 'a,
+    T: Fn(f32, image::RgbaImage) -> (bool, u32),
+{
+    if sample_rate < MIN_SAMPLE_RATE {
+        return Err(DecoderError::UnexpectedSamplingRate(sample_rate));
+    }
+
+    let height = sample_count.map(|sample_count| {
+        let seconds = (sample_count as f32) / (sample_rate as f32);
+        (seconds.ceil() as u32) * LINES_PER_SECOND
+    });
+
+    let mut pixels = vec![0; (PIXELS_PER_LINE * height.unwrap_or(0)) as usize];
+    let mut lines = vec![vec![0.0; PIXELS_PER_LINE as usize]; height.unwrap_or(0) as usize];
+    let mut sync_quality = vec![0.0; height.unwrap_or(0) as usize];
+
+    let coeffs = lowpass_coeffs(LOWPASS_CUTOFF / sample_rate as f32, LOWPASS_TAPS);
+
+    let stop = options.stop.clone();
+    let samples = samples.take_while(move |_| match &stop {
+        Some(stop) => !stop.load(Ordering::Relaxed),
+        None => true,
+    });
+
+    // The subcarrier offsets are only measured when tracking the subcarrier
+    let (demodulator, subcarrier_offsets) = match options.demodulator {
+        AMDemodulatorKind::Tracking => {
+            let demodulator = TrackingAMDemodulator::new(sample_rate);
+            let offsets = demodulator.offsets();
+            (
+                Box::new(demodulator) as Box<dyn AMDemodulator>,
+                Some(offsets),
+            )
+        }
+        kind => (kind.create(sample_rate), None),
+    };
+
+    let demod = AMDemodulation::from(samples, demodulator);
+    let filter = FIRFilter::from(demod, &coeffs);
+    let resampler = RationalResampler::from(filter, sample_rate, PIXEL_RATE);
+    let syncer = APTSyncer::from(resampler);
+    let mut timing = TimingRecovery::from(syncer);
 
     let mut x = 0;
     let mut y = 0;
@@ -118,32 +943,45 @@ where
     let mut has_sync = false;
 
     let mut progress = 0;
-    let pixel_count = sample_count * 13 / 150;
+    let pixel_count = sample_count.map(|sample_count| {
+        ((sample_count as u64) * (PIXEL_RATE as u64) / (sample_rate as u64)) as u32
+    });
     let mut update_step = 10;
 
     let mut previous_sample = 0.0;
 
-    for synced_sample in syncer {
+    for synced_sample in timing.by_ref() {
         progress += 1;
 
+        // Streams without a known length grow as needed
+        if height.is_none() && y as usize >= lines.len() {
+            pixels.resize(pixels.len() + PIXELS_PER_LINE as usize, 0);
+            lines.push(vec![0.0; PIXELS_PER_LINE as usize]);
+            sync_quality.push(0.0);
+        }
+
         let sample = match synced_sample {
             SyncedSample::Sample(s) => s,
-            SyncedSample::SyncA(s) => {
+            SyncedSample::SyncA { sample, confidence } => {
                 if !has_sync {
                     max_level = 0.0;
                     has_sync = true;
                 }
                 x = 0;
-                s
+                if (y as usize) < sync_quality.len() {
+                    sync_quality[y as usize] = confidence;
+                }
+                sample
             }
-            SyncedSample::SyncB(s) => {
+            SyncedSample::SyncB { sample, .. } => {
                 if x < (PIXELS_PER_LINE / 2) {
                     let skip_distance = (PIXELS_PER_LINE / 2) - x;
                     let color = (previous_sample / max_level * 255.0) as u8;
-                    for i in 0..skip_distance {
-                        img.as_mut_luma8()
-                            .unwrap()
-                            .put_pixel(x + i, y, image::Luma([color]));
+                    if (y as usize) < lines.len() {
+                        for i in 0..skip_distance {
+                            pixels[(y * PIXELS_PER_LINE + x + i) as usize] = color;
+                            lines[y as usize][(x + i) as usize] = previous_sample;
+                        }
                     }
                 }
                 if !has_sync {
@@ -151,17 +989,16 @@ where
                     has_sync = true;
                 }
                 x = PIXELS_PER_LINE / 2;
-                s
+                sample
             }
         };
 
         max_level = f32::max(sample, max_level);
         let color = (sample / max_level * 255.0) as u8;
 
-        if y < lines {
-            img.as_mut_luma8()
-                .unwrap()
-                .put_pixel(x, y, image::Luma([color]));
+        if (y as usize) < lines.len() {
+            pixels[(y * PIXELS_PER_LINE + x) as usize] = color;
+            lines[y as usize][x as usize] = sample;
         }
 
         x += 1;
@@ -173,13 +1010,16 @@ where
         previous_sample = sample;
 
         if progress % (PIXELS_PER_LINE * update_step) == 0 {
-            let (cont, update_steps) =
-                progress_update((progress as f32) / (pixel_count as f32), img.to_rgba8());
+            let fraction = match pixel_count {
+                Some(pixel_count) => (progress as f32) / (pixel_count as f32),
+                None => 0.0,
+            };
+            let (cont, update_steps) = progress_update(fraction, preview(&pixels));
             if !cont {
-                return Ok(());
+                return Ok(None);
             }
 
-            let line_count = pixel_count / PIXELS_PER_LINE;
+            let line_count = pixel_count.unwrap_or(0) / PIXELS_PER_LINE;
             update_step = if line_count / update_steps > 4 {
                 line_count / update_steps
             } else {
@@ -188,10 +1028,183 @@ where
         }
     }
 
-    progress_update(1.0, img.to_rgba8());
+    if progress < PIXELS_PER_LINE {
+        return Err(DecoderError::RecordingTooShort);
+    }
 
-    img.save_with_format(&Path::new(output_file), image::ImageFormat::Png)
-        .map_err(|err| DecoderError::OutputFileError(err))?;
+    let telemetry = Telemetry::from_lines(&lines);
+    let normalization = match normalize(&lines, &sync_quality, &telemetry, options.normalization) {
+        Some((normalized, normalization)) => {
+            pixels = normalized;
+            normalization
+        }
+        None => Normalization::Running,
+    };
 
-    Ok(())
+    progress_update(1.0, preview(&pixels));
+
+    let calibration = options
+        .satellite
+        .map(|satellite| Calibration::from_lines(&lines, &telemetry, satellite));
+
+    let image = image::GrayImage::from_raw(PIXELS_PER_LINE, lines.len() as u32, pixels)
+        .expect("Pixel buffer does not match the image size");
+    let composite = options
+        .palette
+        .as_ref()
+        .map(|palette| composite(&image, &telemetry, palette));
+    let ground_control_points = match &options.georeference {
+        Some(georeference) => georeference.ground_control_points(lines.len() as u32, 1)?,
+        None => Vec::new(),
+    };
+    let overlay = match (&options.overlay, &options.georeference) {
+        (Some(overlay), Some(georeference)) => {
+            Some(overlay.render(georeference, lines.len() as u32)?)
+        }
+        _ => None,
+    };
+    let direction = detect_direction(&image, &telemetry, options.georeference.as_ref())?;
+
+    // The composite and the direction need the levels as received, the map shows the enhanced image
+    let (image, unenhanced) = if options.enhancement.is_identity() {
+        (image, None)
+    } else {
+        (options.enhancement.apply(&image), Some(image))
+    };
+    let map = match (&options.map, &options.georeference) {
+        (Some(grid), Some(georeference)) => Some(reproject(
+            &image,
+            georeference,
+            grid,
+            &ground_control_points,
+        )?),
+        _ => None,
+    };
+
+    let mut decoded = DecodedImage {
+        lines,
+        sync_quality,
+        drift_ppm: timing.drift_ppm(),
+        sample_rate,
+        pixel_rate: resampled_rate(sample_rate, PIXEL_RATE),
+        subcarrier_offsets: subcarrier_offsets
+            .map(|offsets| offsets.borrow().clone())
+            .unwrap_or_default(),
+        image,
+        unenhanced,
+        normalization,
+        telemetry,
+        calibration,
+        composite,
+        ground_control_points,
+        overlay,
+        map,
+        direction,
+        rotated: false,
+    };
+    if options.rotation.applies(direction.as_ref()) {
+        rotate(&mut decoded);
+    }
+
+    Ok(Some(decoded))
+}
+
+// Rotates everything in image coordinates by 180°, the map is already north up.
+fn rotate(decoded: &mut DecodedImage) {
+    let height = decoded.image.height();
+    rotate_channels(&mut decoded.image);
+    if let Some(unenhanced) = &mut decoded.unenhanced {
+        rotate_channels(unenhanced);
+    }
+    if let Some(overlay) = &mut decoded.overlay {
+        rotate_channels(overlay);
+    }
+    if let Some(composite) = &mut decoded.composite {
+        image::imageops::rotate180_in_place(composite);
+    }
+    if let Some(calibration) = &mut decoded.calibration {
+        for channel in calibration
+            .channel_a
+            .iter_mut()
+            .chain(calibration.channel_b.iter_mut())
+        {
+            // Row by row in a single buffer, so reversing it rotates the grid
+            channel.values.reverse();
+        }
+    }
+    for point in &mut decoded.ground_control_points {
+        point.line = height - 1 - point.line;
+        point.column = IMAGE_WIDTH as u32 - 1 - point.column;
+    }
+    decoded.ground_control_points.reverse();
+    decoded.rotated = true;
+}
+
+fn preview(pixels: &[u8]) -> image::RgbaImage {
+    let height = pixels.len() as u32 / PIXELS_PER_LINE;
+    let image = image::GrayImage::from_raw(PIXELS_PER_LINE, height, pixels.to_vec())
+        .expect("Pixel buffer does not match the image size");
+    image::DynamicImage::ImageLuma8(image).to_rgba8()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Full frame with the column number, divided by 10 to fit, as grey level of each pixel.
+    fn frame() -> image::GrayImage {
+        image::GrayImage::from_fn(PIXELS_PER_LINE, 3, |x, _| image::Luma([(x / 10) as u8]))
+    }
+
+    #[test]
+    fn cuts_out_channels() {
+        let image = frame();
+        let half = PIXELS_PER_LINE / 2;
+
+        let a = channel_image(&image, ImageChannel::A, false);
+        assert_eq!(a.dimensions(), (half, 3));
+        assert_eq!(a.get_pixel(0, 0)[0], 0);
+        let b = channel_image(&image, ImageChannel::B, false);
+        assert_eq!(b.dimensions(), (half, 3));
+        assert_eq!(b.get_pixel(0, 0)[0], (half / 10) as u8);
+
+        for channel in [ImageChannel::A, ImageChannel::B] {
+            let cropped = channel_image(&image, channel, true);
+            let start = channel.image_start() as u32;
+            assert_eq!(cropped.dimensions(), (IMAGE_WIDTH as u32, 3));
+            assert_eq!(cropped.get_pixel(0, 2)[0], (start / 10) as u8);
+            let last = start + IMAGE_WIDTH as u32 - 1;
+            assert_eq!(
+                cropped.get_pixel(IMAGE_WIDTH as u32 - 1, 2)[0],
+                (last / 10) as u8
+            );
+        }
+    }
+
+    #[test]
+    fn expands_channel_names() {
+        let path = |names: &str, channel| channel_path("out/pass.png", names, channel).unwrap();
+        assert_eq!(
+            path(DEFAULT_CHANNEL_NAMES, ImageChannel::A),
+            Path::new("out/pass-A.png")
+        );
+        assert_eq!(
+            path(DEFAULT_CHANNEL_NAMES, ImageChannel::B),
+            Path::new("out/pass-B.png")
+        );
+        assert_eq!(
+            path("channel-{channel}-{stem}.png", ImageChannel::B),
+            Path::new("out/channel-B-pass.png")
+        );
+    }
+
+    #[test]
+    fn rejects_names_without_channel() {
+        for names in ["{stem}.png", "channel.png", "{Channel}.png"] {
+            match channel_path("pass.png", names, ImageChannel::A) {
+                Err(DecoderError::InvalidTemplate(template)) => assert_eq!(template, names),
+                other => panic!("{} gave {:?}", names, other),
+            }
+        }
+    }
 }
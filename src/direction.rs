@@ -0,0 +1,269 @@
+//! Direction of a pass and rotation of the image so north is up.
+//!
+//! The lines are received in the order the satellite scans them,
+//! so southbound passes come out with north up and northbound passes upside down.
+//! Rotating both channels by 180° turns northbound passes the right way up.
+
+use std::fmt;
+use std::str::FromStr;
+
+use image::{imageops, GrayImage, ImageBuffer, Pixel};
+
+use decoder::{IMAGE_A_START, IMAGE_B_START, IMAGE_WIDTH};
+use errors::DecoderError;
+use georef::Georeferencing;
+use telemetry::Telemetry;
+
+// Smallest change of the thermal channel over the pass in grey levels, that is taken as a trend.
+const MIN_THERMAL_TREND: f64 = 8.0;
+
+/// Direction the satellite moves in during the pass.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PassDirection {
+    Northbound,
+    Southbound,
+}
+
+impl fmt::Display for PassDirection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            PassDirection::Northbound => "Northbound",
+            PassDirection::Southbound => "Southbound",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// How the direction of a pass was found.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DirectionSource {
+    /// Velocity of the satellite from its orbit
+    Orbit,
+    /// Temperature trend of the thermal channel, assuming a receiver on the northern hemisphere
+    Thermal,
+}
+
+impl fmt::Display for DirectionSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            DirectionSource::Orbit => "from the orbit",
+            DirectionSource::Thermal => "guessed from the thermal channel",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Direction of a pass and how it was found.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DetectedDirection {
+    pub direction: PassDirection,
+    pub source: DirectionSource,
+}
+
+impl fmt::Display for DetectedDirection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} ({})", self.direction, self.source)
+    }
+}
+
+/// When decoded images are rotated by 180°.
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub enum Rotation {
+    /// Lines are kept in the order they were received
+    #[default]
+    Never,
+    /// Northbound passes are rotated, so north is up
+    NorthUp,
+    /// Every pass is rotated, e.g. if the direction can't be detected
+    Always,
+}
+
+impl Rotation {
+    /// Returns true if a pass in `direction` has to be rotated.
+    pub fn applies(&self, direction: Option<&DetectedDirection>) -> bool {
+        match self {
+            Rotation::Never => false,
+            Rotation::NorthUp => direction.map(|d| d.direction) == Some(PassDirection::Northbound),
+            Rotation::Always => true,
+        }
+    }
+}
+
+impl FromStr for Rotation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "never" => Ok(Rotation::Never),
+            "north-up" => Ok(Rotation::NorthUp),
+            "always" => Ok(Rotation::Always),
+            _ => Err(format!("Unknown rotation {}", s)),
+        }
+    }
+}
+
+/// Detects the direction of a pass from the orbit if known, from the thermal channel otherwise.
+///
+/// Returns `None` if the thermal channel shows no clear trend.
+pub fn detect_direction(
+    image: &GrayImage,
+    telemetry: &Telemetry,
+    georeference: Option<&Georeferencing>,
+) -> Result<Option<DetectedDirection>, DecoderError> {
+    let detected = match georeference {
+        Some(georeference) => Some(DetectedDirection {
+            direction: direction_from_orbit(georeference, image.height())?,
+            source: DirectionSource::Orbit,
+        }),
+        None => direction_from_thermal(image, telemetry).map(|direction| DetectedDirection {
+            direction,
+            source: DirectionSource::Thermal,
+        }),
+    };
+    Ok(detected)
+}
+
+/// Direction of the satellite in the middle of a pass of `lines` lines.
+pub fn direction_from_orbit(
+    georeference: &Georeferencing,
+    lines: u32,
+) -> Result<PassDirection, DecoderError> {
+    let time = georeference.line_time(lines as f64 / 2.0);
+    // The z axis of the TEME frame points north
+    let state = georeference.sgp4.propagate_to(time)?;
+    if state.velocity[2] > 0.0 {
+        Ok(PassDirection::Northbound)
+    } else {
+        Ok(PassDirection::Southbound)
+    }
+}
+
+/// Guesses the direction of a pass from the temperature trend of the thermal channel.
+///
+/// The earth gets colder towards the pole, on the northern hemisphere that means
+/// colder towards the end for northbound passes.
+/// Cold is bright in the thermal channels of APT.
+pub fn direction_from_thermal(image: &GrayImage, telemetry: &Telemetry) -> Option<PassDirection> {
+    // Channel B is thermal on all NOAA satellites, unless the telemetry says otherwise
+    let start = match (telemetry.channel_a, telemetry.channel_b) {
+        (Some(a), Some(b)) if a.is_thermal() && !b.is_thermal() => IMAGE_A_START,
+        _ => IMAGE_B_START,
+    };
+
+    let means: Vec<f64> = image
+        .rows()
+        .map(|row| {
+            let sum: u64 = row.skip(start).take(IMAGE_WIDTH).map(|p| p[0] as u64).sum();
+            sum as f64 / IMAGE_WIDTH as f64
+        })
+        .collect();
+    if means.len() < 2 {
+        return None;
+    }
+
+    // Least squares fit of a line through the mean of each line
+    let n = means.len() as f64;
+    let mean_x = (n - 1.0) / 2.0;
+    let mean_y = means.iter().sum::<f64>() / n;
+    let (covariance, variance) =
+        means
+            .iter()
+            .enumerate()
+            .fold((0.0, 0.0), |(covariance, variance), (x, y)| {
+                let dx = x as f64 - mean_x;
+                (covariance + dx * (y - mean_y), variance + dx * dx)
+            });
+    let change = covariance / variance * (n - 1.0);
+
+    if change > MIN_THERMAL_TREND {
+        Some(PassDirection::Northbound)
+    } else if change < -MIN_THERMAL_TREND {
+        Some(PassDirection::Southbound)
+    } else {
+        None
+    }
+}
+
+/// Rotates both channels of a full APT image by 180°.
+///
+/// The lines are reversed and each channel is mirrored in place,
+/// so channel A stays on the left and the sync and telemetry columns keep their positions.
+pub fn rotate_channels<P>(image: &mut ImageBuffer<P, Vec<P::Subpixel>>)
+where
+    P: Pixel,
+{
+    imageops::flip_vertical_in_place(image);
+    for y in 0..image.height() {
+        for start in [IMAGE_A_START, IMAGE_B_START] {
+            for x in 0..IMAGE_WIDTH / 2 {
+                let left = (start + x) as u32;
+                let right = (start + IMAGE_WIDTH - 1 - x) as u32;
+                let pixel = *image.get_pixel(left, y);
+                image.put_pixel(left, y, *image.get_pixel(right, y));
+                image.put_pixel(right, y, pixel);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use decoder::PIXELS_PER_LINE;
+    use image::Luma;
+
+    const HEIGHT: u32 = 5;
+
+    // Every pixel holds its own position, so its origin can be told after the rotation.
+    fn numbered() -> ImageBuffer<Luma<u32>, Vec<u32>> {
+        ImageBuffer::from_fn(PIXELS_PER_LINE, HEIGHT, |x, y| {
+            Luma([y * PIXELS_PER_LINE + x])
+        })
+    }
+
+    // Column and line a pixel came from.
+    fn origin(image: &ImageBuffer<Luma<u32>, Vec<u32>>, x: u32, y: u32) -> (u32, u32) {
+        let value = image.get_pixel(x, y)[0];
+        (value % PIXELS_PER_LINE, value / PIXELS_PER_LINE)
+    }
+
+    #[test]
+    fn rotates_northbound_pass() {
+        let northbound = DetectedDirection {
+            direction: PassDirection::Northbound,
+            source: DirectionSource::Orbit,
+        };
+        let southbound = DetectedDirection {
+            direction: PassDirection::Southbound,
+            ..northbound
+        };
+        assert!(Rotation::NorthUp.applies(Some(&northbound)));
+        assert!(!Rotation::NorthUp.applies(Some(&southbound)));
+        assert!(!Rotation::NorthUp.applies(None));
+        assert!(!Rotation::Never.applies(Some(&northbound)));
+        assert!(Rotation::Always.applies(Some(&southbound)));
+    }
+
+    #[test]
+    fn rotates_channels_in_place() {
+        let mut image = numbered();
+        rotate_channels(&mut image);
+
+        for y in 0..HEIGHT {
+            for x in 0..PIXELS_PER_LINE {
+                let column = x as usize;
+                let expected = [IMAGE_A_START, IMAGE_B_START]
+                    .iter()
+                    .find(|start| (**start..**start + IMAGE_WIDTH).contains(&column))
+                    .map_or(column, |start| 2 * start + IMAGE_WIDTH - 1 - column);
+                assert_eq!(
+                    origin(&image, x, y),
+                    (expected as u32, HEIGHT - 1 - y),
+                    "Pixel {}, {}",
+                    x,
+                    y
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,145 @@
+//! Doppler correction of IQ recordings made with a fixed receive frequency.
+
+use std::f64::consts::PI;
+
+use num_complex::Complex;
+
+use errors::DecoderError;
+use orbit::Tracker;
+
+// The Doppler shift is updated this often, it changes by less than 100Hz per second.
+const UPDATES_PER_SECOND: u32 = 100;
+
+/// Predicts the Doppler shift of a pass from the orbit of the satellite.
+#[derive(Clone, Debug)]
+pub struct DopplerModel {
+    /// Orbit of the satellite and location of the receiver
+    pub tracker: Tracker,
+    /// Transmit frequency of the satellite in Hz
+    pub frequency: f64,
+    /// Unix time of the first sample of the recording
+    pub start: f64,
+}
+
+impl DopplerModel {
+    /// Doppler shift in Hz `seconds` after the start of the recording.
+    pub fn shift(&self, seconds: f64) -> Result<f64, DecoderError> {
+        self.tracker
+            .doppler_shift(self.frequency, self.start + seconds)
+    }
+
+    /// Doppler shift every `interval` seconds over `duration` seconds, as pairs of time and shift.
+    pub fn curve(&self, duration: f64, interval: f64) -> Result<Vec<(f64, f64)>, DecoderError> {
+        let steps = (duration / interval).ceil() as usize;
+        (0..=steps)
+            .map(|i| {
+                let time = (i as f64 * interval).min(duration);
+                self.shift(time).map(|shift| (time, shift))
+            })
+            .collect()
+    }
+}
+
+/// Shifts complex baseband samples by the negative Doppler shift predicted by a [`DopplerModel`].
+///
+/// The signal of the satellite ends up at the center frequency during the whole pass.
+/// If the orbit can not be propagated, the last known shift is kept.
+pub struct DopplerCorrection<'a> {
+    model: DopplerModel,
+    sample_rate: u32,
+    update_interval: u64,
+    sample: u64,
+    phase: f64,
+    phase_step: f64,
+    iterator: Box<dyn Iterator<Item = Complex<f32>> + 'a>,
+}
+
+impl<'a> DopplerCorrection<'a> {
+    pub fn from<I>(iterator: I, sample_rate: u32, model: DopplerModel) -> DopplerCorrection<'a>
+    where
+        I: Iterator<Item = Complex<f32>> + 'a,
+    {
+        DopplerCorrection {
+            model,
+            sample_rate,
+            update_interval: u64::max(1, (sample_rate / UPDATES_PER_SECOND) as u64),
+            sample: 0,
+            phase: 0.0,
+            phase_step: 0.0,
+            iterator: Box::new(iterator),
+        }
+    }
+}
+
+impl<'a> Iterator for DopplerCorrection<'a> {
+    type Item = Complex<f32>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let cur = self.iterator.next()?;
+
+        if self.sample % self.update_interval == 0 {
+            // Use the shift in the middle of the following interval
+            let seconds =
+                (self.sample as f64 + self.update_interval as f64 / 2.0) / self.sample_rate as f64;
+            if let Ok(shift) = self.model.shift(seconds) {
+                self.phase_step = -2.0 * PI * shift / self.sample_rate as f64;
+            }
+        }
+        self.sample += 1;
+
+        self.phase = (self.phase + self.phase_step) % (2.0 * PI);
+        let (sin, cos) = self.phase.sin_cos();
+
+        Some(cur * Complex::new(cos as f32, sin as f32))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use orbit::{Observer, SPEED_OF_LIGHT};
+    use sgp4::{Sgp4, Tle};
+
+    #[test]
+    fn range_rate_matches_distance() {
+        let tle = Tle::parse(
+            "NOAA 19",
+            "1 33591U 09005A   24121.50000000  .00000100  00000-0  80000-4 0  9990",
+            "2 33591  99.1000 150.0000 0014000 100.0000 260.0000 14.12500000790000",
+        )
+        .unwrap();
+        let observer = Observer {
+            latitude: 52.5,
+            longitude: 13.4,
+            altitude: 50.0,
+        };
+        let model = DopplerModel {
+            tracker: Tracker::new(Sgp4::new(&tle).unwrap(), observer),
+            frequency: 137.1e6,
+            start: tle.epoch + 1800.0,
+        };
+
+        let distance = |seconds: f64| {
+            let position = model.tracker.ecef(model.start + seconds).unwrap().position;
+            let observer = observer.position();
+            (0..3)
+                .map(|i| (position[i] - observer[i]).powi(2))
+                .sum::<f64>()
+                .sqrt()
+        };
+        for seconds in [0.0, 300.0, 600.0] {
+            // Central difference of the distance over one second
+            let expected = (distance(seconds + 0.5) - distance(seconds - 0.5)) / 1.0;
+            let range_rate = model.tracker.range_rate(model.start + seconds).unwrap();
+            assert!(
+                (range_rate - expected).abs() < 1e-3,
+                "Range rate {} km/s, expected {} km/s",
+                range_rate,
+                expected
+            );
+
+            let shift = model.shift(seconds).unwrap();
+            assert!((shift + 137.1e6 * range_rate / SPEED_OF_LIGHT).abs() < 1e-6);
+        }
+    }
+}
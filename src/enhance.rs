@@ -0,0 +1,286 @@
+//! Contrast enhancement and noise reduction of the decoded image.
+//!
+//! Each channel is enhanced on its own, only the 909 pixels of the image are changed,
+//! so sync, space view and telemetry keep their levels.
+//! The steps are applied in the order median filter, equalization, gamma and unsharp mask.
+
+use std::fmt;
+use std::str::FromStr;
+
+use image::{imageops, GrayImage};
+
+use decoder::{IMAGE_A_START, IMAGE_B_START, IMAGE_WIDTH};
+
+/// Clip limit of CLAHE by default, in multiples of the mean histogram bin.
+pub const DEFAULT_CLAHE_CLIP: f32 = 2.0;
+
+// Width and height of the CLAHE tiles in pixels.
+const CLAHE_TILE_SIZE: u32 = 64;
+// Standard deviation of the blur subtracted by the unsharp mask in pixels.
+const UNSHARP_SIGMA: f32 = 1.5;
+
+/// Redistribution of the grey levels.
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub enum Equalization {
+    #[default]
+    None,
+    /// Flattens the histogram of the whole channel
+    Histogram,
+    /// Contrast limited adaptive histogram equalization, flattens the histogram of small tiles
+    /// without raising any grey level above `clip_limit` times the mean
+    Clahe { clip_limit: f32 },
+}
+
+impl FromStr for Equalization {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "none" => Ok(Equalization::None),
+            "histogram" => Ok(Equalization::Histogram),
+            "clahe" => Ok(Equalization::Clahe {
+                clip_limit: DEFAULT_CLAHE_CLIP,
+            }),
+            _ => Err(format!("Unknown equalization {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for Equalization {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Equalization::None => write!(f, "None"),
+            Equalization::Histogram => write!(f, "Histogram"),
+            Equalization::Clahe { .. } => write!(f, "CLAHE"),
+        }
+    }
+}
+
+/// Settings of the enhancement, the default leaves the image unchanged.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Enhancement {
+    /// Radius of the median filter in pixels, 0 disables it
+    pub median: u32,
+    pub equalization: Equalization,
+    /// Values above 1.0 brighten the mid tones, values below darken them
+    pub gamma: f32,
+    /// Strength of the unsharp mask, 0.0 disables it
+    pub sharpen: f32,
+}
+
+impl Default for Enhancement {
+    fn default() -> Self {
+        Self {
+            median: 0,
+            equalization: Equalization::None,
+            gamma: 1.0,
+            sharpen: 0.0,
+        }
+    }
+}
+
+impl fmt::Display for Enhancement {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut steps = Vec::new();
+        if self.median > 0 {
+            steps.push(format!("Median radius {}", self.median));
+        }
+        match self.equalization {
+            Equalization::None => {}
+            Equalization::Histogram => steps.push("Histogram equalization".to_string()),
+            Equalization::Clahe { clip_limit } => steps.push(format!("CLAHE clip {}", clip_limit)),
+        }
+        if self.gamma != 1.0 {
+            steps.push(format!("Gamma {}", self.gamma));
+        }
+        if self.sharpen > 0.0 {
+            steps.push(format!("Unsharp mask {}", self.sharpen));
+        }
+
+        if steps.is_empty() {
+            write!(f, "None")
+        } else {
+            write!(f, "{}", steps.join(", "))
+        }
+    }
+}
+
+impl Enhancement {
+    /// Returns true if the enhancement leaves the image unchanged.
+    pub fn is_identity(&self) -> bool {
+        *self == Enhancement::default()
+    }
+
+    /// Enhances both channels of a full APT image.
+    pub fn apply(&self, image: &GrayImage) -> GrayImage {
+        let mut enhanced = image.clone();
+        if self.is_identity() {
+            return enhanced;
+        }
+        for start in [IMAGE_A_START, IMAGE_B_START] {
+            let channel =
+                imageops::crop_imm(image, start as u32, 0, IMAGE_WIDTH as u32, image.height())
+                    .to_image();
+            imageops::replace(&mut enhanced, &self.apply_channel(channel), start as i64, 0);
+        }
+        enhanced
+    }
+
+    fn apply_channel(&self, mut channel: GrayImage) -> GrayImage {
+        if self.median > 0 {
+            channel = median(&channel, self.median);
+        }
+        match self.equalization {
+            Equalization::None => {}
+            Equalization::Histogram => equalize_histogram(&mut channel),
+            Equalization::Clahe { clip_limit } => channel = clahe(&channel, clip_limit),
+        }
+        if self.gamma != 1.0 && self.gamma > 0.0 {
+            let lut: Vec<u8> = (0..256)
+                .map(|v| (255.0 * (v as f32 / 255.0).powf(1.0 / self.gamma)).round() as u8)
+                .collect();
+            for pixel in channel.pixels_mut() {
+                pixel[0] = lut[pixel[0] as usize];
+            }
+        }
+        if self.sharpen > 0.0 {
+            channel = unsharp_mask(&channel, self.sharpen);
+        }
+        channel
+    }
+}
+
+/// Replaces each pixel by the median of the square of `radius` pixels around it.
+pub fn median(image: &GrayImage, radius: u32) -> GrayImage {
+    let (width, height) = (image.width() as i64, image.height() as i64);
+    let radius = radius as i64;
+    let mut window = Vec::with_capacity(((2 * radius + 1) * (2 * radius + 1)) as usize);
+
+    GrayImage::from_fn(image.width(), image.height(), |x, y| {
+        window.clear();
+        for dy in -radius..=radius {
+            for dx in -radius..=radius {
+                // Repeats the pixels at the edges
+                let sx = (x as i64 + dx).clamp(0, width - 1) as u32;
+                let sy = (y as i64 + dy).clamp(0, height - 1) as u32;
+                window.push(image.get_pixel(sx, sy)[0]);
+            }
+        }
+        let middle = window.len() / 2;
+        let (_, median, _) = window.select_nth_unstable(middle);
+        image::Luma([*median])
+    })
+}
+
+/// Spreads the grey levels of `image` so its histogram becomes flat.
+pub fn equalize_histogram(image: &mut GrayImage) {
+    let mut histogram = [0u32; 256];
+    for pixel in image.pixels() {
+        histogram[pixel[0] as usize] += 1;
+    }
+    let lut = equalization_lut(&histogram);
+    for pixel in image.pixels_mut() {
+        pixel[0] = lut[pixel[0] as usize];
+    }
+}
+
+/// Contrast limited adaptive histogram equalization with tiles of 64 x 64 pixels.
+///
+/// The histogram of each tile is clipped at `clip_limit` times its mean bin,
+/// the clipped counts are spread over all bins.
+/// Pixels are mapped by interpolating the mappings of the four closest tiles.
+pub fn clahe(image: &GrayImage, clip_limit: f32) -> GrayImage {
+    let tiles_x = image.width().div_ceil(CLAHE_TILE_SIZE).max(1);
+    let tiles_y = image.height().div_ceil(CLAHE_TILE_SIZE).max(1);
+
+    let mut luts = Vec::with_capacity((tiles_x * tiles_y) as usize);
+    for tile_y in 0..tiles_y {
+        for tile_x in 0..tiles_x {
+            let mut histogram = [0u32; 256];
+            let x_end = ((tile_x + 1) * CLAHE_TILE_SIZE).min(image.width());
+            let y_end = ((tile_y + 1) * CLAHE_TILE_SIZE).min(image.height());
+            for y in tile_y * CLAHE_TILE_SIZE..y_end {
+                for x in tile_x * CLAHE_TILE_SIZE..x_end {
+                    histogram[image.get_pixel(x, y)[0] as usize] += 1;
+                }
+            }
+            clip_histogram(&mut histogram, clip_limit);
+            luts.push(equalization_lut(&histogram));
+        }
+    }
+
+    // Position of a pixel between the centres of the tiles, as tile index and weight of the next tile
+    let locate = |position: u32, tiles: u32| {
+        let tile = (position as f32 + 0.5) / CLAHE_TILE_SIZE as f32 - 0.5;
+        let tile = tile.clamp(0.0, (tiles - 1) as f32);
+        let first = (tile.floor() as u32).min(tiles.saturating_sub(2));
+        let second = (first + 1).min(tiles - 1);
+        (first, second, tile - first as f32)
+    };
+
+    GrayImage::from_fn(image.width(), image.height(), |x, y| {
+        let value = image.get_pixel(x, y)[0] as usize;
+        let (x0, x1, tx) = locate(x, tiles_x);
+        let (y0, y1, ty) = locate(y, tiles_y);
+        let level =
+            |tile_x: u32, tile_y: u32| luts[(tile_y * tiles_x + tile_x) as usize][value] as f32;
+
+        let top = level(x0, y0) * (1.0 - tx) + level(x1, y0) * tx;
+        let bottom = level(x0, y1) * (1.0 - tx) + level(x1, y1) * tx;
+        image::Luma([(top * (1.0 - ty) + bottom * ty).round() as u8])
+    })
+}
+
+/// Adds the difference between `image` and a blurred copy, scaled by `amount`.
+pub fn unsharp_mask(image: &GrayImage, amount: f32) -> GrayImage {
+    let blurred = imageops::blur(image, UNSHARP_SIGMA);
+    GrayImage::from_fn(image.width(), image.height(), |x, y| {
+        let value = image.get_pixel(x, y)[0] as f32;
+        let blur = blurred.get_pixel(x, y)[0] as f32;
+        image::Luma([(value + amount * (value - blur)).round().clamp(0.0, 255.0) as u8])
+    })
+}
+
+// Caps every bin at `clip_limit` times the mean bin and spreads the excess evenly over all bins.
+fn clip_histogram(histogram: &mut [u32; 256], clip_limit: f32) {
+    let total: u32 = histogram.iter().sum();
+    let limit = ((clip_limit * total as f32 / 256.0).ceil() as u32).max(1);
+
+    let mut excess = 0;
+    for bin in histogram.iter_mut() {
+        if *bin > limit {
+            excess += *bin - limit;
+            *bin = limit;
+        }
+    }
+    let (share, remainder) = (excess / 256, excess % 256);
+    for (i, bin) in histogram.iter_mut().enumerate() {
+        *bin += share + if (i as u32) < remainder { 1 } else { 0 };
+    }
+}
+
+// Maps each grey level to its position in the cumulative histogram.
+fn equalization_lut(histogram: &[u32; 256]) -> [u8; 256] {
+    let total: u32 = histogram.iter().sum();
+    let first = histogram
+        .iter()
+        .cloned()
+        .find(|count| *count > 0)
+        .unwrap_or(0);
+    let mut lut = [0u8; 256];
+    if total <= first {
+        // A single grey level stays as it is
+        for (i, level) in lut.iter_mut().enumerate() {
+            *level = i as u8;
+        }
+        return lut;
+    }
+
+    let mut cumulative = 0;
+    for (level, count) in lut.iter_mut().zip(histogram.iter()) {
+        cumulative += count;
+        *level = ((cumulative.saturating_sub(first)) as f32 / (total - first) as f32 * 255.0)
+            .round() as u8;
+    }
+    lut
+}
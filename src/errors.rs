@@ -1,15 +1,90 @@
 use hound;
 use image;
+use png;
 use thiserror::Error;
+use tiff;
 
+/// Errors returned by the decoder.
 #[derive(Error, Debug)]
 pub enum DecoderError {
     #[error("Unable to read input file: {0}")]
     InputFileError(#[from] hound::Error),
 
-    #[error("Expected a sampling rate of 48000Hz not {0}Hz")]
+    #[error("Unable to read input file: {0}")]
+    InputIOError(#[from] std::io::Error),
+
+    #[error("IQ input needs a stereo file, not {0} channels")]
+    UnsupportedChannelCount(u16),
+
+    #[error("Unsupported sample format with {0} bits per sample")]
+    UnsupportedSampleFormat(u16),
+
+    #[error("The input file ended before all samples were read")]
+    TruncatedInput,
+
+    #[error("Unable to read sample: {0}")]
+    CorruptInput(hound::Error),
+
+    #[error("The recording is too short, not even a single line could be decoded")]
+    RecordingTooShort,
+
+    #[error("The sampling rate of raw recordings has to be given")]
+    MissingSamplingRate,
+
+    #[error("Expected a sampling rate of at least 9000Hz not {0}Hz")]
     UnexpectedSamplingRate(u32),
 
+    #[error("Invalid {0}, it has to be positive")]
+    InvalidFilter(String),
+
+    #[error("Invalid TLE: {0}")]
+    InvalidTLE(String),
+
+    #[error("Invalid time {0}, expected e.g. 2024-05-01T12:34:56Z")]
+    InvalidTime(String),
+
+    #[error("Invalid location {0}, expected latitude,longitude[,altitude]")]
+    InvalidLocation(String),
+
+    #[error("The transmit frequency is needed for the Doppler correction, give the satellite or the frequency")]
+    MissingFrequency,
+
+    #[error("Doppler correction is only possible for IQ recordings")]
+    DopplerNeedsIQ,
+
+    #[error("The start time of the recording is needed, give it or use a WAV file with a recording date")]
+    MissingStartTime,
+
+    #[error("Unable to read map data: {0}")]
+    InvalidShapes(String),
+
+    #[error("Map overlays and projections need the orbit and start time of the recording")]
+    MissingGeoreference,
+
+    #[error("Invalid map area: {0}")]
+    InvalidBounds(String),
+
+    #[error("Invalid map: {0}")]
+    InvalidMap(String),
+
+    #[error("Invalid file name template {0}, it needs to contain {{channel}}")]
+    InvalidTemplate(String),
+
     #[error("Unable to write output file: {0}")]
     OutputFileError(#[from] image::ImageError),
+
+    #[error("Unable to write output file: {0}")]
+    OutputIOError(std::io::Error),
+
+    #[error("Unable to write PNG file: {0}")]
+    PngError(#[from] png::EncodingError),
+
+    #[error("Unable to write GeoTIFF file: {0}")]
+    GeoTiffError(tiff::TiffError),
+
+    #[error("Unable to write TIFF file: {0}")]
+    TiffError(tiff::TiffError),
+
+    #[error("Unable to read palette: {0}")]
+    PaletteError(image::ImageError),
 }
@@ -1,19 +1,25 @@
-pub struct FIRFilter<'a> {
+use std::f32::consts::PI;
+use std::ops::{AddAssign, Mul};
+
+/// Sample types that can be filtered, implemented for `f32` and `Complex<f32>`.
+pub trait FilterSample: Copy + Default + Mul<f32, Output = Self> + AddAssign {}
+
+impl<T> FilterSample for T where T: Copy + Default + Mul<f32, Output = T> + AddAssign {}
+
+/// Finite impulse response filter applying `coeffs` to the input signal.
+pub struct FIRFilter<'a, T = f32> {
     coeffs: &'a [f32],
-    state: Vec<f32>,
+    state: Vec<T>,
     pos: usize,
-    iterator: Box<dyn Iterator<Item = f32> + 'a>,
+    iterator: Box<dyn Iterator<Item = T> + 'a>,
 }
 
-impl<'a> FIRFilter<'a> {
-    pub fn from<I>(iterator: I, coeffs: &'a [f32]) -> FIRFilter<'a>
+impl<'a, T: FilterSample> FIRFilter<'a, T> {
+    pub fn from<I>(iterator: I, coeffs: &'a [f32]) -> FIRFilter<'a, T>
     where
-        I: Iterator<Item = f32> + 'a,
+        I: Iterator<Item = T> + 'a,
     {
-        let mut state = Vec::new();
-        for _ in 0..coeffs.len() {
-            state.push(0.0);
-        }
+        let state = vec![T::default(); coeffs.len()];
 
         FIRFilter {
             coeffs,
@@ -24,19 +30,16 @@ impl<'a> FIRFilter<'a> {
     }
 }
 
-impl<'a> Iterator for FIRFilter<'a> {
-    type Item = f32;
+impl<'a, T: FilterSample> Iterator for FIRFilter<'a, T> {
+    type Item = T;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let cur = match self.iterator.next() {
-            Some(x) => x,
-            None => return None,
-        };
+        let cur = self.iterator.next()?;
 
         self.pos = (self.pos + 1) % self.coeffs.len();
         self.state[self.pos] = cur;
 
-        let mut result = 0.0;
+        let mut result = T::default();
         for i in 0..self.coeffs.len() {
             let pos = (self.pos + self.coeffs.len() - i) % self.coeffs.len();
             result += self.state[pos] * self.coeffs[i];
@@ -45,3 +48,39 @@ impl<'a> Iterator for FIRFilter<'a> {
         Some(result)
     }
 }
+
+/// Designs a windowed sinc lowpass with unity gain at DC.
+/// The cutoff frequency is given relative to the sampling rate (0.0 - 0.5).
+///
+/// Panics if the cutoff is not positive, the coefficients would not sum up to a usable gain.
+pub fn lowpass_coeffs(cutoff: f32, taps: usize) -> Vec<f32> {
+    assert!(cutoff > 0.0, "Lowpass cutoff {} is not positive", cutoff);
+    let center = (taps - 1) as f32 / 2.0;
+
+    let mut coeffs: Vec<f32> = (0..taps)
+        .map(|i| {
+            let t = i as f32 - center;
+            let sinc = if t == 0.0 {
+                2.0 * cutoff
+            } else {
+                (2.0 * PI * cutoff * t).sin() / (PI * t)
+            };
+            sinc * blackman(i, taps)
+        })
+        .collect();
+
+    let sum: f32 = coeffs.iter().sum();
+    for coeff in coeffs.iter_mut() {
+        *coeff /= sum;
+    }
+
+    coeffs
+}
+
+fn blackman(i: usize, taps: usize) -> f32 {
+    if taps < 2 {
+        return 1.0;
+    }
+    let x = 2.0 * PI * i as f32 / (taps - 1) as f32;
+    0.42 - 0.5 * x.cos() + 0.08 * (2.0 * x).cos()
+}
@@ -0,0 +1,78 @@
+//! FM demodulation of complex baseband samples, with optional de-emphasis of the audio.
+
+use std::f32::consts::PI;
+
+use num_complex::Complex;
+
+/// Frequency deviation of the FM carrier used by the NOAA satellites for APT.
+pub const APT_DEVIATION: f32 = 17000.0;
+
+/// Quadrature discriminator demodulating an FM signal from complex baseband samples.
+///
+/// The phase difference between two consecutive samples is proportional to the momentary frequency.
+/// The output is scaled so that a frequency offset of `deviation` results in an amplitude of 1.0.
+pub struct QuadratureDemodulator<'a> {
+    gain: f32,
+    previous: Complex<f32>,
+    iterator: Box<dyn Iterator<Item = Complex<f32>> + 'a>,
+}
+
+impl<'a> QuadratureDemodulator<'a> {
+    pub fn from<I>(iterator: I, sample_rate: u32, deviation: f32) -> QuadratureDemodulator<'a>
+    where
+        I: Iterator<Item = Complex<f32>> + 'a,
+    {
+        QuadratureDemodulator {
+            gain: sample_rate as f32 / (2.0 * PI * deviation),
+            previous: Complex::new(0.0, 0.0),
+            iterator: Box::new(iterator),
+        }
+    }
+}
+
+impl<'a> Iterator for QuadratureDemodulator<'a> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let cur = self.iterator.next()?;
+        let delta = cur * self.previous.conj();
+        self.previous = cur;
+
+        Some(delta.arg() * self.gain)
+    }
+}
+
+/// Single pole de-emphasis filter with the time constant `tau` in seconds.
+///
+/// APT itself does not use pre-emphasis, but some receivers add it to their audio output.
+pub struct DeEmphasis<'a> {
+    alpha: f32,
+    state: f32,
+    iterator: Box<dyn Iterator<Item = f32> + 'a>,
+}
+
+impl<'a> DeEmphasis<'a> {
+    pub fn from<I>(iterator: I, sample_rate: u32, tau: f32) -> DeEmphasis<'a>
+    where
+        I: Iterator<Item = f32> + 'a,
+    {
+        let dt = 1.0 / sample_rate as f32;
+
+        DeEmphasis {
+            alpha: dt / (tau + dt),
+            state: 0.0,
+            iterator: Box::new(iterator),
+        }
+    }
+}
+
+impl<'a> Iterator for DeEmphasis<'a> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let cur = self.iterator.next()?;
+        self.state += self.alpha * (cur - self.state);
+
+        Some(self.state)
+    }
+}
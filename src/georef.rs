@@ -0,0 +1,486 @@
+//! Geographic location of the pixels of a decoded image.
+//!
+//! The AVHRR instrument scans perpendicular to the ground track, up to 55.37° to either side of nadir.
+//! Every line of a channel covers one full scan, the right side of the satellite's ground track
+//! comes first. With the orbit from a TLE and the time each line was received,
+//! the line of sight of every pixel is intersected with the WGS-84 ellipsoid.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+use decoder::{IMAGE_WIDTH, LINES_PER_SECOND};
+use errors::DecoderError;
+use geotiff::TiePoint;
+use orbit::{ecef_to_geodetic, format_time, intersect_earth, teme_to_ecef};
+use sgp4::Sgp4;
+
+/// Largest angle between the line of sight and nadir in degrees, reached at the edges of each line.
+pub const MAX_SCAN_ANGLE: f64 = 55.37;
+
+/// Number of ground control points across each line.
+pub const GCP_COLUMNS: usize = 9;
+
+// Spacing of the pixels sampled by the PixelLocator, the scan geometry changes slowly.
+const GRID_LINE_STEP: u32 = 4;
+const GRID_COLUMN_STEP: usize = 16;
+
+/// Distance between the lines with ground control points in the GeoTIFF and QGIS files.
+///
+/// One every 10 seconds is plenty, the scan geometry changes slowly along the track.
+pub const GCP_LINE_STEP: u32 = 20;
+
+/// Output written next to the image to georeference it.
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub enum GeoFormat {
+    /// GeoTIFF copy of the image, with ground control points embedded
+    #[default]
+    GeoTiff,
+    /// World file with an affine approximation, plus ground control points for the QGIS georeferencer
+    WorldFile,
+}
+
+impl FromStr for GeoFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "geotiff" => Ok(GeoFormat::GeoTiff),
+            "worldfile" => Ok(GeoFormat::WorldFile),
+            _ => Err(format!("Unknown georeferencing format {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for GeoFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            GeoFormat::GeoTiff => "GeoTIFF",
+            GeoFormat::WorldFile => "World file",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Orbit and timing needed to georeference an image.
+#[derive(Clone, Debug)]
+pub struct Georeferencing {
+    /// Orbit of the satellite
+    pub sgp4: Sgp4,
+    /// Unix time of the first line
+    pub start: f64,
+    /// Files written next to the image, if any
+    pub format: Option<GeoFormat>,
+}
+
+impl Georeferencing {
+    /// Unix time at which `line` was received.
+    pub fn line_time(&self, line: f64) -> f64 {
+        self.start + line / LINES_PER_SECOND as f64
+    }
+
+    /// Scan geometry of `line`, counted from the start of the recording.
+    pub fn scan_line(&self, line: f64) -> Result<ScanLine, DecoderError> {
+        let time = self.line_time(line);
+        let state = teme_to_ecef(&self.sgp4.propagate_to(time)?, time);
+
+        let distance = norm(&state.position);
+        let nadir = state.position.map(|p| -p / distance);
+        // Direction of the ground track, the velocity without its vertical part
+        let vertical = dot(&state.velocity, &nadir);
+        let along = normalize(&[
+            state.velocity[0] - vertical * nadir[0],
+            state.velocity[1] - vertical * nadir[1],
+            state.velocity[2] - vertical * nadir[2],
+        ]);
+
+        Ok(ScanLine {
+            time,
+            position: state.position,
+            nadir,
+            right: cross(&nadir, &along),
+        })
+    }
+
+    /// Ground control points for the first `lines` lines, on every `line_step`th and the last line.
+    pub fn ground_control_points(
+        &self,
+        lines: u32,
+        line_step: u32,
+    ) -> Result<Vec<GroundControlPoint>, DecoderError> {
+        let mut points = Vec::new();
+        let line_numbers = (0..lines).filter(|line| line % line_step == 0 || line + 1 == lines);
+        for line in line_numbers {
+            let scan_line = self.scan_line(line as f64)?;
+            for i in 0..GCP_COLUMNS {
+                let column = (i * (IMAGE_WIDTH - 1) / (GCP_COLUMNS - 1)) as u32;
+                if let Some((latitude, longitude)) = scan_line.locate(column as f64) {
+                    points.push(GroundControlPoint {
+                        line,
+                        column,
+                        time: scan_line.time,
+                        latitude,
+                        longitude,
+                    });
+                }
+            }
+        }
+        Ok(points)
+    }
+}
+
+/// Position and viewing directions of the satellite while scanning one line.
+#[derive(Clone, Copy, Debug)]
+pub struct ScanLine {
+    /// Unix time of the line
+    pub time: f64,
+    position: [f64; 3],
+    nadir: [f64; 3],
+    right: [f64; 3],
+}
+
+impl ScanLine {
+    /// Latitude and longitude in degrees seen by the center of `column` of a channel.
+    ///
+    /// `column` counts from 0 to [`IMAGE_WIDTH`] - 1 and may be fractional.
+    /// Returns `None` if the line of sight misses the earth.
+    pub fn locate(&self, column: f64) -> Option<(f64, f64)> {
+        let half_width = IMAGE_WIDTH as f64 / 2.0;
+        let angle = ((half_width - (column + 0.5)) / half_width * MAX_SCAN_ANGLE).to_radians();
+        let (sin, cos) = angle.sin_cos();
+        let direction = [
+            cos * self.nadir[0] + sin * self.right[0],
+            cos * self.nadir[1] + sin * self.right[1],
+            cos * self.nadir[2] + sin * self.right[2],
+        ];
+
+        let ground = intersect_earth(&self.position, &direction)?;
+        let (latitude, longitude, _) = ecef_to_geodetic(&ground);
+        Some((latitude, longitude))
+    }
+}
+
+/// Pixel of a channel with known location.
+#[derive(Clone, Copy, Debug)]
+pub struct GroundControlPoint {
+    /// Line in the image
+    pub line: u32,
+    /// Column within the channel, from 0 to [`IMAGE_WIDTH`] - 1
+    pub column: u32,
+    /// Unix time the line was received
+    pub time: f64,
+    /// Latitude in degrees
+    pub latitude: f64,
+    /// Longitude in degrees
+    pub longitude: f64,
+}
+
+/// Wraps a longitude or a difference of longitudes into -180° to 180°.
+pub fn wrap_longitude(longitude: f64) -> f64 {
+    (longitude + 540.0).rem_euclid(360.0) - 180.0
+}
+
+/// Finds the pixel showing a location, the inverse of [`ScanLine::locate`].
+///
+/// The location of the pixels is sampled on a coarse grid,
+/// a location is placed in the image by finding the grid cell containing it
+/// and interpolating linearly within the cell.
+pub struct PixelLocator {
+    rows: Vec<f64>,
+    columns: Vec<f64>,
+    // Longitude and latitude of every grid point, row by row
+    points: Vec<Option<(f64, f64)>>,
+    // Grid cells overlapping each square degree
+    cells: HashMap<(i32, i32), Vec<(usize, usize)>>,
+}
+
+impl PixelLocator {
+    /// Samples the location of the pixels of an image with `lines` lines.
+    pub fn new(georeference: &Georeferencing, lines: u32) -> Result<PixelLocator, DecoderError> {
+        let mut rows: Vec<f64> = (0..lines)
+            .step_by(GRID_LINE_STEP as usize)
+            .map(|line| line as f64)
+            .collect();
+        if rows.last() != Some(&((lines - 1) as f64)) {
+            rows.push((lines - 1) as f64);
+        }
+        let mut columns: Vec<f64> = (0..IMAGE_WIDTH)
+            .step_by(GRID_COLUMN_STEP)
+            .map(|column| column as f64)
+            .collect();
+        if columns.last() != Some(&((IMAGE_WIDTH - 1) as f64)) {
+            columns.push((IMAGE_WIDTH - 1) as f64);
+        }
+
+        let mut points = Vec::with_capacity(rows.len() * columns.len());
+        for row in &rows {
+            let scan_line = georeference.scan_line(*row)?;
+            points.extend(columns.iter().map(|column| {
+                scan_line
+                    .locate(*column)
+                    .map(|(latitude, longitude)| (longitude, latitude))
+            }));
+        }
+
+        let mut locator = PixelLocator {
+            rows,
+            columns,
+            points,
+            cells: HashMap::new(),
+        };
+        locator.index_cells();
+        Ok(locator)
+    }
+
+    fn index_cells(&mut self) {
+        for row in 0..self.rows.len().saturating_sub(1) {
+            for column in 0..self.columns.len() - 1 {
+                let corners = match self.corners(row, column) {
+                    Some(corners) => corners,
+                    None => continue,
+                };
+
+                let (mut min_lon, mut max_lon) = (f64::MAX, f64::MIN);
+                let (mut min_lat, mut max_lat) = (f64::MAX, f64::MIN);
+                for (lon, lat) in corners {
+                    min_lon = min_lon.min(lon);
+                    max_lon = max_lon.max(lon);
+                    min_lat = min_lat.min(lat);
+                    max_lat = max_lat.max(lat);
+                }
+                // Cells around the poles, where longitude is meaningless
+                if max_lon - min_lon > 90.0 {
+                    continue;
+                }
+
+                for lat in min_lat.floor() as i32..=max_lat.floor() as i32 {
+                    for lon in min_lon.floor() as i32..=max_lon.floor() as i32 {
+                        let key = (lat, (lon + 180).rem_euclid(360) - 180);
+                        self.cells.entry(key).or_default().push((row, column));
+                    }
+                }
+            }
+        }
+    }
+
+    // Corners of a grid cell, with the longitudes unwrapped around the first corner.
+    fn corners(&self, row: usize, column: usize) -> Option<[(f64, f64); 4]> {
+        let width = self.columns.len();
+        let point = |r: usize, c: usize| self.points[r * width + c];
+        let (lon, lat) = point(row, column)?;
+        let unwrap = |p: (f64, f64)| (lon + wrap_longitude(p.0 - lon), p.1);
+
+        Some([
+            (lon, lat),
+            unwrap(point(row, column + 1)?),
+            unwrap(point(row + 1, column)?),
+            unwrap(point(row + 1, column + 1)?),
+        ])
+    }
+
+    /// Returns the line and the channel column showing a location in degrees, if it is in the image.
+    ///
+    /// Both are fractional, the center of each pixel is at whole numbers.
+    pub fn locate(&self, longitude: f64, latitude: f64) -> Option<(f64, f64)> {
+        let key = (
+            latitude.floor() as i32,
+            wrap_longitude(longitude).floor() as i32,
+        );
+
+        for &(row, column) in self.cells.get(&key)? {
+            let corners = self.corners(row, column)?;
+            let point = (
+                corners[0].0 + wrap_longitude(longitude - corners[0].0),
+                latitude,
+            );
+            let positions = [
+                (self.rows[row], self.columns[column]),
+                (self.rows[row], self.columns[column + 1]),
+                (self.rows[row + 1], self.columns[column]),
+                (self.rows[row + 1], self.columns[column + 1]),
+            ];
+
+            // Two triangles per cell
+            for [a, b, c] in [[0, 1, 2], [3, 2, 1]] {
+                if let Some((u, v)) = barycentric(point, corners[a], corners[b], corners[c]) {
+                    return Some((
+                        positions[a].0
+                            + u * (positions[b].0 - positions[a].0)
+                            + v * (positions[c].0 - positions[a].0),
+                        positions[a].1
+                            + u * (positions[b].1 - positions[a].1)
+                            + v * (positions[c].1 - positions[a].1),
+                    ));
+                }
+            }
+        }
+
+        None
+    }
+}
+
+// Coordinates of `p` relative to the triangle `a`, `b`, `c`, if it is inside.
+fn barycentric(p: (f64, f64), a: (f64, f64), b: (f64, f64), c: (f64, f64)) -> Option<(f64, f64)> {
+    let (ab, ac, ap) = (
+        (b.0 - a.0, b.1 - a.1),
+        (c.0 - a.0, c.1 - a.1),
+        (p.0 - a.0, p.1 - a.1),
+    );
+    let det = ab.0 * ac.1 - ab.1 * ac.0;
+    if det.abs() < 1e-12 {
+        return None;
+    }
+
+    let u = (ap.0 * ac.1 - ap.1 * ac.0) / det;
+    let v = (ab.0 * ap.1 - ab.1 * ap.0) / det;
+    // Small tolerance, so points on the shared edges are not lost
+    let epsilon = 1e-9;
+    if u >= -epsilon && v >= -epsilon && u + v <= 1.0 + epsilon {
+        Some((u, v))
+    } else {
+        None
+    }
+}
+
+/// Converts ground control points to GeoTIFF tie points at the pixel centers,
+/// for a channel starting at `column_offset` in the image.
+pub fn tie_points(points: &[GroundControlPoint], column_offset: usize) -> Vec<TiePoint> {
+    points
+        .iter()
+        .map(|p| TiePoint {
+            x: (p.column as usize + column_offset) as f64 + 0.5,
+            y: p.line as f64 + 0.5,
+            longitude: p.longitude,
+            latitude: p.latitude,
+        })
+        .collect()
+}
+
+/// Writes the ground control points as CSV table.
+pub fn write_gcp_table(path: &Path, points: &[GroundControlPoint]) -> std::io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    writeln!(writer, "line,column,time,latitude,longitude")?;
+    for p in points {
+        writeln!(
+            writer,
+            "{},{},{},{:.5},{:.5}",
+            p.line,
+            p.column,
+            format_time(p.time),
+            p.latitude,
+            p.longitude
+        )?;
+    }
+    writer.flush()
+}
+
+/// Writes the ground control points in the `.points` format of the QGIS georeferencer,
+/// for a channel starting at `column_offset` in the image.
+pub fn write_qgis_points(
+    path: &Path,
+    points: &[GroundControlPoint],
+    column_offset: usize,
+) -> std::io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    writeln!(writer, "mapX,mapY,sourceX,sourceY,enable,dX,dY,residual")?;
+    for p in tie_points(points, column_offset) {
+        // QGIS counts rows downwards with negative numbers
+        writeln!(
+            writer,
+            "{:.6},{:.6},{},{},1,0,0,0",
+            p.longitude, p.latitude, p.x, -p.y
+        )?;
+    }
+    writer.flush()
+}
+
+/// Writes a world file with the affine transformation best fitting the ground control points,
+/// for a channel starting at `column_offset` in the image.
+///
+/// The scan geometry is not affine, so this is only a rough approximation for quick overviews.
+pub fn write_world_file(
+    path: &Path,
+    points: &[GroundControlPoint],
+    column_offset: usize,
+) -> std::io::Result<()> {
+    let points = tie_points(points, column_offset);
+    let reference = points.first().map(|p| p.longitude).unwrap_or(0.0);
+    // Passes crossing the date line would be torn apart otherwise
+    let longitudes: Vec<f64> = points
+        .iter()
+        .map(|p| reference + wrap_longitude(p.longitude - reference))
+        .collect();
+    let latitudes: Vec<f64> = points.iter().map(|p| p.latitude).collect();
+
+    // World files refer to the pixel centers by their indices
+    let x: Vec<f64> = points.iter().map(|p| p.x - 0.5).collect();
+    let y: Vec<f64> = points.iter().map(|p| p.y - 0.5).collect();
+    let [a, b, c] = fit_plane(&x, &y, &longitudes);
+    let [d, e, f] = fit_plane(&x, &y, &latitudes);
+
+    let mut writer = BufWriter::new(File::create(path)?);
+    for value in [a, d, b, e, c, f] {
+        writeln!(writer, "{:.10}", value)?;
+    }
+    writer.flush()
+}
+
+// Least squares fit of value = a * x + b * y + c.
+fn fit_plane(x: &[f64], y: &[f64], value: &[f64]) -> [f64; 3] {
+    let mut normal = [[0.0; 3]; 3];
+    let mut rhs = [0.0; 3];
+    for i in 0..value.len() {
+        let row = [x[i], y[i], 1.0];
+        for j in 0..3 {
+            for k in 0..3 {
+                normal[j][k] += row[j] * row[k];
+            }
+            rhs[j] += row[j] * value[i];
+        }
+    }
+
+    // Cramer's rule, a singular system only happens with too few points
+    let det = |m: &[[f64; 3]; 3]| {
+        m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+            - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+            + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+    };
+    let denominator = det(&normal);
+    if denominator.abs() < f64::EPSILON {
+        return [0.0; 3];
+    }
+
+    let mut solution = [0.0; 3];
+    for (i, s) in solution.iter_mut().enumerate() {
+        let mut m = normal;
+        for j in 0..3 {
+            m[j][i] = rhs[j];
+        }
+        *s = det(&m) / denominator;
+    }
+    solution
+}
+
+fn dot(a: &[f64; 3], b: &[f64; 3]) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn norm(a: &[f64; 3]) -> f64 {
+    dot(a, a).sqrt()
+}
+
+fn normalize(a: &[f64; 3]) -> [f64; 3] {
+    let length = norm(a);
+    a.map(|v| v / length)
+}
+
+fn cross(a: &[f64; 3], b: &[f64; 3]) -> [f64; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
@@ -0,0 +1,61 @@
+//! Minimal writer for GeoTIFF files with ground control points, as read by GDAL and QGIS.
+
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+use tiff::encoder::{colortype, TiffEncoder};
+use tiff::tags::Tag;
+use tiff::TiffResult;
+
+// GeoKey ids and values from the GeoTIFF specification
+const GT_MODEL_TYPE: u16 = 1024;
+const MODEL_TYPE_GEOGRAPHIC: u16 = 2;
+const GT_RASTER_TYPE: u16 = 1025;
+const RASTER_PIXEL_IS_AREA: u16 = 1;
+const GEOGRAPHIC_TYPE: u16 = 2048;
+const GCS_WGS_84: u16 = 4326;
+
+/// Ground control point tying a position in the raster to longitude and latitude in degrees.
+#[derive(Clone, Copy, Debug)]
+pub struct TiePoint {
+    /// Column in pixels, 0.0 is the left edge of the first pixel
+    pub x: f64,
+    /// Row in pixels, 0.0 is the top edge of the first pixel
+    pub y: f64,
+    pub longitude: f64,
+    pub latitude: f64,
+}
+
+/// Writes an 8 bit greyscale image, georeferenced in WGS-84 by `tie_points`.
+///
+/// Without a pixel scale the tie points are treated as ground control points,
+/// so the image can be warped onto a map with `gdalwarp` or QGIS.
+pub fn write_gray(
+    path: &Path,
+    image: &image::GrayImage,
+    tie_points: &[TiePoint],
+) -> TiffResult<()> {
+    let mut encoder = TiffEncoder::new(BufWriter::new(File::create(path)?))?;
+    let mut tiff = encoder.new_image::<colortype::Gray8>(image.width(), image.height())?;
+
+    let tie_points: Vec<f64> = tie_points
+        .iter()
+        .flat_map(|p| [p.x, p.y, 0.0, p.longitude, p.latitude, 0.0])
+        .collect();
+    tiff.encoder()
+        .write_tag(Tag::ModelTiepointTag, &tie_points[..])?;
+
+    // Header with version 1.1.0 and the number of keys, followed by one entry per key
+    let geo_keys = [
+        [1, 1, 0, 3],
+        [GT_MODEL_TYPE, 0, 1, MODEL_TYPE_GEOGRAPHIC],
+        [GT_RASTER_TYPE, 0, 1, RASTER_PIXEL_IS_AREA],
+        [GEOGRAPHIC_TYPE, 0, 1, GCS_WGS_84],
+    ]
+    .concat();
+    tiff.encoder()
+        .write_tag(Tag::GeoKeyDirectoryTag, &geo_keys[..])?;
+
+    tiff.write_data(image.as_raw())
+}
@@ -0,0 +1,77 @@
+//! Decoder for the Automatic Picture Transmissions (APT) broadcasted by the NOAA weather satellites.
+//!
+//! The signal processing chain is made up of iterators over `f32` samples,
+//! that can be plugged together freely:
+//!
+//! 1. [`utils::float_sample_iterator`] reads the FM demodulated signal from a WAV file
+//!    and [`utils::raw_sample_iterator`] from a headerless stream,
+//!    alternatively [`utils::complex_sample_iterator`] reads raw IQ recordings
+//!    and [`utils::complex_wav_iterator`] stereo WAV files holding I and Q,
+//!    which are FM demodulated by [`fmdemod::QuadratureDemodulator`],
+//!    optionally after removing the Doppler shift with [`doppler::DopplerCorrection`]
+//! 2. [`amdemod::AMDemodulation`] demodulates the 2400Hz AM subcarrier,
+//!    using one of the demodulators selected by [`amdemod::AMDemodulatorKind`]
+//! 3. [`firfilter::FIRFilter`] removes the upper sideband created by the demodulator
+//! 4. [`resamplers::RationalResampler`] resamples the signal to the pixel rate of 4160Hz
+//! 5. [`aptsyncer::APTSyncer`] marks the sync A and sync B patterns at the beginning of each channel
+//! 6. [`timing::TimingRecovery`] tracks the line period and resamples each line to 2080 pixels
+//!
+//! Afterwards [`telemetry::Telemetry`] extracts the telemetry frames from the decoded lines
+//! and identifies the AVHRR channels transmitted in both image channels.
+//! [`normalization::normalize`] optionally scales the image again once all lines are known.
+//! With the satellite known, [`calibration::Calibration`] converts the channels
+//! to brightness temperatures or albedo.
+//! [`enhance::Enhancement`] equalizes, denoises and sharpens both channels.
+//! [`composite::composite`] combines both channels into a false colour image.
+//! [`direction::detect_direction`] finds out whether the pass was northbound,
+//! so the image can be rotated with north up.
+//! Given the orbit, [`georef::Georeferencing`] locates the pixels on the ground
+//! and [`overlay::Overlay`] draws coastlines, borders and a grid over the image.
+//! [`reproject::reproject`] resamples both channels onto an equirectangular or Mercator map
+//! and [`mosaic::mosaic`] combines the maps of several passes.
+//!
+//! [`decode`] and [`decode_image`] run the complete chain on a recording in any of the
+//! [`InputFormat`]s: a WAV file, a headerless stream of the FM demodulated signal
+//! or a raw IQ recording.
+//! [`decode_samples`] runs it on FM demodulated samples from any other source.
+
+extern crate hound;
+extern crate image;
+extern crate num_complex;
+extern crate png;
+extern crate rustfft;
+extern crate thiserror;
+extern crate tiff;
+
+pub mod amdemod;
+pub mod aptsyncer;
+pub mod calibration;
+pub mod composite;
+pub mod decoder;
+pub mod direction;
+pub mod doppler;
+pub mod enhance;
+pub mod errors;
+pub mod firfilter;
+pub mod fmdemod;
+pub mod georef;
+pub mod geotiff;
+pub mod metadata;
+pub mod mosaic;
+pub mod normalization;
+pub mod npy;
+pub mod orbit;
+pub mod overlay;
+pub mod reproject;
+pub mod resamplers;
+pub mod sgp4;
+pub mod shapes;
+pub mod subcarrier;
+pub mod telemetry;
+pub mod timing;
+pub mod utils;
+
+pub use decoder::{
+    decode, decode_image, decode_samples, DecodedImage, DecoderOptions, InputFormat,
+};
+pub use errors::DecoderError;
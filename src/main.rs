@@ -1,42 +1,52 @@
 #![windows_subsystem = "windows"]
 
+extern crate apt_decoder;
 extern crate clap;
-extern crate hound;
-extern crate image;
+extern crate ctrlc;
 extern crate indicatif;
 extern crate rfd;
-extern crate thiserror;
 
 #[cfg(feature = "ui")]
 extern crate eframe;
+#[cfg(feature = "ui")]
+extern crate image;
 
-mod amdemod;
-mod aptsyncer;
 mod cli;
-mod decoder;
-mod errors;
-mod firfilter;
-mod resamplers;
-mod utils;
 
-use clap::{arg, command};
+use clap::{arg, command, Command};
 
 #[cfg(not(feature = "ui"))]
 fn main() {
-    let matches = command!()
-        .arg(arg!([wavfile] "Input wav file with 48kHz samplingrate").required(true))
+    let matches = cli::decoder_args(command!())
+        .arg(arg!([inputfile] "Input wav, raw audio or IQ file, - reads from stdin").required(true))
         .arg(arg!([pngfile] "Output png file").default_value("output.png"))
+        .subcommand(cli::mosaic_args(Command::new("mosaic")))
+        .subcommand_negates_reqs(true)
+        .args_conflicts_with_subcommands(true)
         .get_matches();
 
+    if let Some(("mosaic", matches)) = matches.subcommand() {
+        cli::mosaic_passes(matches);
+        return;
+    }
+
     let input_file = matches
-        .get_one::<String>("wavfile")
+        .get_one::<String>("inputfile")
         .expect("No input file given");
 
     let output_file = matches
         .get_one::<String>("pngfile")
         .expect("No output file given");
 
-    cli::decode(&input_file, &output_file);
+    let options = match cli::decoder_options(&matches) {
+        Ok(options) => options,
+        Err(error) => {
+            println!("Invalid options: {}", error);
+            std::process::exit(1);
+        }
+    };
+
+    cli::decode(input_file, output_file, &options);
 }
 
 #[cfg(feature = "ui")]
@@ -47,14 +57,24 @@ use ui::DecoderApp;
 
 #[cfg(feature = "ui")]
 fn main() {
-    let matches = command!()
-        .arg(arg!([wavfile] "Input wav file with 48kHz samplingrate").default_value("input.wav"))
+    let matches = cli::decoder_args(command!())
+        .arg(
+            arg!([inputfile] "Input wav, raw audio or IQ file, - reads from stdin")
+                .default_value("input.wav"),
+        )
         .arg(arg!([pngfile] "Output png file").default_value("output.png"))
         .arg(arg!(-n --nogui "Disable gui and run in command line mode"))
+        .subcommand(cli::mosaic_args(Command::new("mosaic")))
+        .args_conflicts_with_subcommands(true)
         .get_matches();
 
+    if let Some(("mosaic", matches)) = matches.subcommand() {
+        cli::mosaic_passes(matches);
+        return;
+    }
+
     let input_file = matches
-        .get_one::<String>("wavfile")
+        .get_one::<String>("inputfile")
         .expect("No input file given")
         .to_string();
     let output_file = matches
@@ -62,15 +82,29 @@ fn main() {
         .expect("No output file given")
         .to_string();
 
+    let options = match cli::decoder_options(&matches) {
+        Ok(options) => options,
+        Err(error) => {
+            println!("Invalid options: {}", error);
+            std::process::exit(1);
+        }
+    };
+
     if matches.get_flag("nogui") {
-        cli::decode(&input_file, &output_file);
+        cli::decode(&input_file, &output_file, &options);
     } else {
         let native_options = eframe::NativeOptions::default();
 
         eframe::run_native(
             "APT-Decoder",
             native_options,
-            Box::new(move |_cc| Ok(Box::new(DecoderApp::new(&input_file, &output_file)))),
+            Box::new(move |_cc| {
+                Ok(Box::new(DecoderApp::new(
+                    &input_file,
+                    &output_file,
+                    options,
+                )))
+            }),
         )
         .unwrap();
     }
@@ -0,0 +1,228 @@
+//! Information about a decoded pass, stored with the output image.
+//!
+//! The same information is written as PNG text chunks and optionally as JSON file,
+//! which additionally contains the sync quality of every line.
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use calibration::Satellite;
+use decoder::{DecodedImage, DecoderOptions};
+use direction::{DetectedDirection, DirectionSource};
+use enhance::Enhancement;
+use normalization::Normalization;
+use orbit::format_time;
+use telemetry::AVHRRChannel;
+
+/// Information about a decoded pass.
+#[derive(Clone, Debug)]
+pub struct Metadata {
+    /// Name of the input file, `-` for stdin
+    pub source: String,
+    /// Sampling rate of the input in Hz
+    pub sample_rate: u32,
+    /// Unix time the pass was decoded
+    pub decoded_at: f64,
+    pub satellite: Option<Satellite>,
+    /// AVHRR channel in channel A, according to the telemetry
+    pub channel_a: Option<AVHRRChannel>,
+    /// AVHRR channel in channel B, according to the telemetry
+    pub channel_b: Option<AVHRRChannel>,
+    pub lines: usize,
+    /// Number of lines with a sync marker
+    pub synced_lines: usize,
+    /// Mean confidence of the sync markers found
+    pub mean_sync_quality: f32,
+    /// Deviation of the sample clock in ppm, if any sync was found
+    pub drift_ppm: Option<f32>,
+    /// Unix time of the first line, if the recording was georeferenced
+    pub start: Option<f64>,
+    pub direction: Option<DetectedDirection>,
+    /// True if the image was rotated by 180°
+    pub rotated: bool,
+    /// Scaling of the pixel values to grey levels
+    pub normalization: Normalization,
+    pub enhancement: Enhancement,
+}
+
+impl Metadata {
+    /// Collects the metadata of a pass decoded from `source` just now.
+    pub fn new(source: &str, decoded: &DecodedImage, options: &DecoderOptions) -> Metadata {
+        let synced: Vec<f32> = decoded
+            .sync_quality
+            .iter()
+            .cloned()
+            .filter(|q| *q > 0.0)
+            .collect();
+        let mean_sync_quality = if synced.is_empty() {
+            0.0
+        } else {
+            synced.iter().sum::<f32>() / synced.len() as f32
+        };
+        let decoded_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs_f64())
+            .unwrap_or(0.0);
+
+        Metadata {
+            source: source.to_string(),
+            sample_rate: decoded.sample_rate,
+            decoded_at,
+            satellite: options.satellite,
+            channel_a: decoded.telemetry.channel_a,
+            channel_b: decoded.telemetry.channel_b,
+            lines: decoded.sync_quality.len(),
+            synced_lines: synced.len(),
+            mean_sync_quality,
+            drift_ppm: decoded.drift_ppm,
+            start: options.georeference.as_ref().map(|g| g.start),
+            direction: decoded.direction,
+            rotated: decoded.rotated,
+            normalization: decoded.normalization,
+            enhancement: options.enhancement,
+        }
+    }
+
+    /// Keywords and texts for the PNG text chunks, unknown values are left out.
+    pub fn text_chunks(&self) -> Vec<(String, String)> {
+        let direction = match self.direction {
+            Some(direction) => direction.to_string(),
+            None => "Unknown".to_string(),
+        };
+        let rotation = if self.rotated { "180 degrees" } else { "None" };
+        let chunks = vec![
+            (
+                "Software",
+                Some(format!("apt-decoder {}", env!("CARGO_PKG_VERSION"))),
+            ),
+            ("Source file", Some(self.source.clone())),
+            ("Sample rate", Some(format!("{} Hz", self.sample_rate))),
+            ("Creation Time", Some(format_time(self.decoded_at))),
+            ("Satellite", self.satellite.map(|s| s.to_string())),
+            ("Recording start", self.start.map(format_time)),
+            ("Channel A", self.channel_a.map(|c| c.to_string())),
+            ("Channel B", self.channel_b.map(|c| c.to_string())),
+            ("Lines", Some(self.lines.to_string())),
+            (
+                "Synced lines",
+                Some(format!(
+                    "{} (mean confidence {:.2})",
+                    self.synced_lines, self.mean_sync_quality
+                )),
+            ),
+            (
+                "Clock drift",
+                self.drift_ppm.map(|d| format!("{:+.1} ppm", d)),
+            ),
+            ("Pass direction", Some(direction)),
+            ("Rotation", Some(rotation.to_string())),
+            ("Normalization", Some(self.normalization.to_string())),
+            ("Enhancement", Some(self.enhancement.to_string())),
+        ];
+
+        chunks
+            .into_iter()
+            .filter_map(|(keyword, text)| Some((keyword.to_string(), text?)))
+            .collect()
+    }
+
+    /// Writes the metadata and the sync quality of every line as JSON file.
+    pub fn write_json(&self, path: &Path, sync_quality: &[f32]) -> std::io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        let optional = |value: Option<String>| value.unwrap_or_else(|| "null".to_string());
+
+        writeln!(writer, "{{")?;
+        writeln!(writer, "  \"source\": {},", json_string(&self.source))?;
+        writeln!(writer, "  \"sample_rate\": {},", self.sample_rate)?;
+        writeln!(
+            writer,
+            "  \"decoded_at\": {},",
+            json_string(&format_time(self.decoded_at))
+        )?;
+        writeln!(
+            writer,
+            "  \"satellite\": {},",
+            optional(self.satellite.map(|s| json_string(&s.to_string())))
+        )?;
+        writeln!(
+            writer,
+            "  \"recording_start\": {},",
+            optional(self.start.map(|t| json_string(&format_time(t))))
+        )?;
+        writeln!(
+            writer,
+            "  \"channel_a\": {},",
+            optional(self.channel_a.map(|c| json_string(&c.to_string())))
+        )?;
+        writeln!(
+            writer,
+            "  \"channel_b\": {},",
+            optional(self.channel_b.map(|c| json_string(&c.to_string())))
+        )?;
+        writeln!(writer, "  \"lines\": {},", self.lines)?;
+        writeln!(writer, "  \"synced_lines\": {},", self.synced_lines)?;
+        writeln!(
+            writer,
+            "  \"mean_sync_quality\": {:.4},",
+            self.mean_sync_quality
+        )?;
+        writeln!(
+            writer,
+            "  \"drift_ppm\": {},",
+            optional(self.drift_ppm.map(|d| format!("{:.2}", d)))
+        )?;
+        writeln!(
+            writer,
+            "  \"pass_direction\": {},",
+            optional(
+                self.direction
+                    .map(|d| json_string(&d.direction.to_string().to_lowercase()))
+            )
+        )?;
+        writeln!(
+            writer,
+            "  \"direction_source\": {},",
+            optional(self.direction.map(|d| match d.source {
+                DirectionSource::Orbit => json_string("orbit"),
+                DirectionSource::Thermal => json_string("thermal"),
+            }))
+        )?;
+        writeln!(writer, "  \"rotated\": {},", self.rotated)?;
+        writeln!(
+            writer,
+            "  \"normalization\": {},",
+            json_string(&self.normalization.to_string())
+        )?;
+        writeln!(
+            writer,
+            "  \"enhancement\": {},",
+            json_string(&self.enhancement.to_string())
+        )?;
+
+        // In the order of reception, even if the image was rotated
+        let qualities: Vec<String> = sync_quality.iter().map(|q| format!("{:.3}", q)).collect();
+        writeln!(writer, "  \"sync_quality\": [{}]", qualities.join(", "))?;
+        writeln!(writer, "}}")?;
+        writer.flush()
+    }
+}
+
+// Quotes and escapes a string for JSON.
+fn json_string(text: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
@@ -0,0 +1,291 @@
+//! Mosaics of several reprojected passes on one map.
+//!
+//! Maps are lined up using their world files,
+//! so all passes need the same projection and resolution.
+//! Where passes overlap, either the pass seen closest to nadir, the pass with the highest sun
+//! or a blend of all passes with feathered seams is used.
+
+use std::fmt;
+use std::str::FromStr;
+
+use image::{GrayAlphaImage, LumaA};
+
+use decoder::{sibling_path, ImageChannel};
+use errors::DecoderError;
+use orbit::sun_elevation;
+use reproject::{map_suffix, read_map_info, Projection, WorldFile, MAX_MAP_PIXELS};
+
+// Weights of the chamfer distance transform, close to 1 and sqrt(2) times 3
+const STRAIGHT_DISTANCE: f32 = 3.0;
+const DIAGONAL_DISTANCE: f32 = 4.0;
+
+/// How overlapping passes are combined.
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub enum MosaicMode {
+    /// Pixel of the pass farthest from the edge of its swath, which is closest to nadir
+    #[default]
+    Nadir,
+    /// Pixel of the pass with the highest sun, for the visible channels
+    Sun,
+    /// Average of all passes, fading out towards the edges of their swaths
+    Feather,
+}
+
+impl FromStr for MosaicMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "nadir" => Ok(MosaicMode::Nadir),
+            "sun" => Ok(MosaicMode::Sun),
+            "feather" => Ok(MosaicMode::Feather),
+            _ => Err(format!("Unknown mosaic mode {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for MosaicMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            MosaicMode::Nadir => "Closest to nadir",
+            MosaicMode::Sun => "Highest sun",
+            MosaicMode::Feather => "Feathered",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// One channel of a reprojected pass.
+pub struct MapPass {
+    /// Channel, transparent outside of the swath
+    pub image: GrayAlphaImage,
+    pub world_file: WorldFile,
+    pub projection: Projection,
+    /// Unix time of the middle of the pass
+    pub time: f64,
+}
+
+impl MapPass {
+    /// Reads a channel of the map written when decoding to `output_file` with a map grid,
+    /// see [`decoder::DecoderOptions::map`](::decoder::DecoderOptions::map).
+    pub fn open(output_file: &str, channel: ImageChannel) -> Result<MapPass, DecoderError> {
+        let image_path = sibling_path(output_file, &format!("{}.png", map_suffix(channel)));
+        if !image_path.exists() {
+            return Err(DecoderError::InvalidMap(format!(
+                "{} not found, {} has to be decoded with a projection",
+                image_path.display(),
+                output_file
+            )));
+        }
+        let image = image::open(&image_path)
+            .map_err(|error| {
+                DecoderError::InvalidMap(format!("{}: {}", image_path.display(), error))
+            })?
+            .to_luma_alpha8();
+        let world_file = WorldFile::read(&sibling_path(
+            output_file,
+            &format!("{}.pgw", map_suffix(channel)),
+        ))?;
+        let (projection, time) = read_map_info(&sibling_path(output_file, "-map.txt"))?;
+
+        Ok(MapPass {
+            image,
+            world_file,
+            projection,
+            time,
+        })
+    }
+
+    // Elevation of the sun over every pixel at the time of the pass.
+    fn sun_elevations(&self) -> Vec<f32> {
+        let scale = self.projection.world_scale();
+        let size = self.world_file.pixel_size;
+        self.image
+            .enumerate_pixels()
+            .map(|(x, y, pixel)| {
+                if pixel[1] == 0 {
+                    return f32::MIN;
+                }
+                let (longitude, latitude) = self.projection.inverse(
+                    (self.world_file.left + x as f64 * size) / scale,
+                    (self.world_file.top - y as f64 * size) / scale,
+                );
+                sun_elevation(self.time, latitude, longitude) as f32
+            })
+            .collect()
+    }
+}
+
+/// Map combined from several passes.
+pub struct Mosaic {
+    /// Transparent where no pass covers the map
+    pub image: GrayAlphaImage,
+    pub world_file: WorldFile,
+}
+
+impl Mosaic {
+    /// Writes the mosaic to `output_file` and its world file to `<output_file stem>.pgw`.
+    pub fn save(&self, output_file: &str) -> Result<(), DecoderError> {
+        self.image
+            .save_with_format(output_file, image::ImageFormat::Png)
+            .map_err(DecoderError::OutputFileError)?;
+        self.world_file
+            .write(&sibling_path(output_file, ".pgw"))
+            .map_err(DecoderError::OutputIOError)
+    }
+}
+
+/// Combines `passes` into one map covering all of them.
+///
+/// With [`MosaicMode::Feather`] the passes fade out over `feather` pixels towards the edges of their swaths.
+pub fn mosaic(passes: &[MapPass], mode: MosaicMode, feather: f32) -> Result<Mosaic, DecoderError> {
+    let first = passes
+        .first()
+        .ok_or_else(|| DecoderError::InvalidMap("No passes given".to_string()))?;
+    let projection = first.projection;
+    let pixel_size = first.world_file.pixel_size;
+    // Full circle of longitude in world file units
+    let period = 360.0 * projection.world_scale();
+
+    // Position of the top left pixel of each pass, counted in pixels from the origin of the projection
+    let mut positions = Vec::new();
+    for pass in passes {
+        if pass.projection != projection
+            || (pass.world_file.pixel_size - pixel_size).abs() > pixel_size * 1e-6
+        {
+            return Err(DecoderError::InvalidMap(
+                "All passes need the same projection and resolution".to_string(),
+            ));
+        }
+        // Passes on the other side of the date line are moved next to the first one
+        let left = pass.world_file.left;
+        let left = left - ((left - first.world_file.left) / period).round() * period;
+        positions.push((
+            (left / pixel_size - 0.5).round() as i64,
+            (-pass.world_file.top / pixel_size - 0.5).round() as i64,
+        ));
+    }
+
+    let left = positions.iter().map(|p| p.0).min().unwrap_or(0);
+    let top = positions.iter().map(|p| p.1).min().unwrap_or(0);
+    let right = passes
+        .iter()
+        .zip(&positions)
+        .map(|(pass, p)| p.0 + pass.image.width() as i64)
+        .max()
+        .unwrap_or(0);
+    let bottom = passes
+        .iter()
+        .zip(&positions)
+        .map(|(pass, p)| p.1 + pass.image.height() as i64)
+        .max()
+        .unwrap_or(0);
+    let (width, height) = ((right - left) as u64, (bottom - top) as u64);
+    if width * height > MAX_MAP_PIXELS {
+        return Err(DecoderError::InvalidMap(format!(
+            "A mosaic of {} x {} pixels is too large, the passes are too far apart",
+            width, height
+        )));
+    }
+
+    // Sum of the weighted values and weights for feathering, otherwise the value and score of the best pass
+    let mut values = vec![0.0f32; (width * height) as usize];
+    let mut weights = vec![f32::MIN; (width * height) as usize];
+    for (pass, &(pass_left, pass_top)) in passes.iter().zip(&positions) {
+        let scores = match mode {
+            MosaicMode::Nadir | MosaicMode::Feather => edge_distances(&pass.image),
+            MosaicMode::Sun => pass.sun_elevations(),
+        };
+
+        for (x, y, pixel) in pass.image.enumerate_pixels() {
+            if pixel[1] == 0 {
+                continue;
+            }
+            let column = (pass_left - left) as usize + x as usize;
+            let row = (pass_top - top) as usize + y as usize;
+            let index = row * width as usize + column;
+            let score = scores[(y * pass.image.width() + x) as usize];
+            let value = pixel[0] as f32;
+
+            match mode {
+                MosaicMode::Feather => {
+                    let weight = (score / feather.max(1.0)).min(1.0);
+                    values[index] += weight * value;
+                    weights[index] = weights[index].max(0.0) + weight;
+                }
+                MosaicMode::Nadir | MosaicMode::Sun => {
+                    if score > weights[index] {
+                        values[index] = value;
+                        weights[index] = score;
+                    }
+                }
+            }
+        }
+    }
+
+    let image = GrayAlphaImage::from_fn(width as u32, height as u32, |x, y| {
+        let index = (y as u64 * width + x as u64) as usize;
+        match (mode, weights[index]) {
+            (_, weight) if weight == f32::MIN => LumaA([0, 0]),
+            (MosaicMode::Feather, weight) => LumaA([(values[index] / weight).round() as u8, 255]),
+            _ => LumaA([values[index].round() as u8, 255]),
+        }
+    });
+
+    Ok(Mosaic {
+        image,
+        world_file: WorldFile {
+            pixel_size,
+            left: first.world_file.left + (left - positions[0].0) as f64 * pixel_size,
+            top: first.world_file.top - (top - positions[0].1) as f64 * pixel_size,
+        },
+    })
+}
+
+// Distance of every pixel to the closest transparent pixel, in pixels.
+//
+// The map may cut off the swath, so the edges of the image don't count.
+fn edge_distances(image: &GrayAlphaImage) -> Vec<f32> {
+    let (width, height) = (image.width() as usize, image.height() as usize);
+    let mut distances: Vec<f32> = image
+        .pixels()
+        .map(|pixel| if pixel[1] == 0 { 0.0 } else { f32::INFINITY })
+        .collect();
+
+    let neighbours = [
+        (-1, 0, STRAIGHT_DISTANCE),
+        (-1, -1, DIAGONAL_DISTANCE),
+        (0, -1, STRAIGHT_DISTANCE),
+        (1, -1, DIAGONAL_DISTANCE),
+    ];
+    let mut relax = |x: usize, y: usize, direction: i64| {
+        let index = y * width + x;
+        for &(dx, dy, step) in &neighbours {
+            let (nx, ny) = (x as i64 + dx * direction, y as i64 + dy * direction);
+            if nx < 0 || ny < 0 || nx >= width as i64 || ny >= height as i64 {
+                continue;
+            }
+            let neighbour = distances[ny as usize * width + nx as usize] + step;
+            if neighbour < distances[index] {
+                distances[index] = neighbour;
+            }
+        }
+    };
+
+    // Forward pass from the top left, backward pass from the bottom right
+    for y in 0..height {
+        for x in 0..width {
+            relax(x, y, 1);
+        }
+    }
+    for y in (0..height).rev() {
+        for x in (0..width).rev() {
+            relax(x, y, -1);
+        }
+    }
+
+    distances
+        .into_iter()
+        .map(|distance| distance / STRAIGHT_DISTANCE)
+        .collect()
+}
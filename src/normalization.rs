@@ -0,0 +1,396 @@
+//! Scaling of the unscaled pixel values to 8 bit grey levels.
+//!
+//! While decoding, each pixel is scaled to the highest level seen so far,
+//! which is all a live preview can do, but leaves the top of the image too bright
+//! and shifts the contrast whenever something brighter comes along.
+//! Once all lines are decoded they can be scaled again with one scale per channel,
+//! either from percentiles of the channel or from the grey scale of the telemetry wedges.
+//! Fitting a transfer function to the grey scale of every telemetry frame also evens out
+//! changes of the signal level during the pass, so passes from different receivers look alike.
+
+use std::fmt;
+use std::str::FromStr;
+
+use decoder::{IMAGE_A_START, IMAGE_B_START, IMAGE_WIDTH, PIXELS_PER_LINE};
+use telemetry::{Telemetry, TelemetryFrame, FRAME_MARKER, LINES_PER_FRAME, WEDGES};
+
+/// Share of the darkest and brightest pixels clipped by default, in percent.
+pub const DEFAULT_CLIP: f32 = 0.5;
+/// Highest degree of the polynomial fitted to the wedges, more than 9 wedges can't support anyway.
+pub const MAX_FIT_DEGREE: usize = 3;
+
+// Index of wedge 8 (full modulation) and wedge 9 (zero modulation) in a telemetry frame.
+const WHITE_WEDGE: usize = 7;
+const BLACK_WEDGE: usize = 8;
+
+/// How the unscaled pixel values are mapped to grey levels.
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub enum Normalization {
+    /// Highest level seen so far while decoding
+    #[default]
+    Running,
+    /// Levels between the `clip` and the 100 - `clip` percentile of each channel span the grey scale
+    Percentile { clip: f32 },
+    /// Zero modulation (wedge 9) is black and full modulation (wedge 8) is white,
+    /// averaged over all telemetry frames
+    Wedges,
+    /// Polynomial of `degree` fitted to wedges 1 - 9 of each telemetry frame,
+    /// interpolated between the frames
+    WedgeFit { degree: usize },
+}
+
+impl FromStr for Normalization {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "running" => Ok(Normalization::Running),
+            "percentile" => Ok(Normalization::Percentile { clip: DEFAULT_CLIP }),
+            "wedges" => Ok(Normalization::Wedges),
+            "wedge-fit" => Ok(Normalization::WedgeFit { degree: 1 }),
+            _ => Err(format!("Unknown normalization {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for Normalization {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Normalization::Running => write!(f, "Running maximum"),
+            Normalization::Percentile { clip } => {
+                write!(f, "Percentiles {}% - {}%", clip, 100.0 - clip)
+            }
+            Normalization::Wedges => write!(f, "Telemetry wedges"),
+            Normalization::WedgeFit { degree: 1 } => {
+                write!(f, "Linear fit to the wedges of each frame")
+            }
+            Normalization::WedgeFit { degree } => {
+                write!(f, "Degree {} fit to the wedges of each frame", degree)
+            }
+        }
+    }
+}
+
+/// Levels mapped to black and white for one channel.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Levels {
+    pub black: f32,
+    pub white: f32,
+}
+
+impl Levels {
+    fn scale(&self, value: f32) -> u8 {
+        let range = (self.white - self.black).max(f32::EPSILON);
+        ((value - self.black) / range * 255.0)
+            .round()
+            .clamp(0.0, 255.0) as u8
+    }
+}
+
+/// Scales `lines` to 8 bit pixels, one line after the other.
+///
+/// Returns `None` for [`Normalization::Running`], which is applied while decoding.
+/// Without telemetry [`Normalization::Wedges`] and [`Normalization::WedgeFit`] fall back
+/// to the default percentiles, the normalization actually used is returned with the pixels.
+pub fn normalize(
+    lines: &[Vec<f32>],
+    sync_quality: &[f32],
+    telemetry: &Telemetry,
+    normalization: Normalization,
+) -> Option<(Vec<u8>, Normalization)> {
+    let levels = match normalization {
+        Normalization::Running => return None,
+        Normalization::Percentile { clip } => Some(percentile_levels(lines, sync_quality, clip)),
+        Normalization::Wedges => wedge_levels(telemetry),
+        Normalization::WedgeFit { degree } => {
+            if let Some(pixels) = wedge_fit(lines, telemetry, degree) {
+                return Some((pixels, normalization));
+            }
+            None
+        }
+    };
+
+    match levels {
+        Some(levels) => Some((scale_lines(lines, &levels), normalization)),
+        None => Some((
+            scale_lines(lines, &percentile_levels(lines, sync_quality, DEFAULT_CLIP)),
+            Normalization::Percentile { clip: DEFAULT_CLIP },
+        )),
+    }
+}
+
+/// Scales the half line of channel A with the first levels and channel B with the second.
+pub fn scale_lines(lines: &[Vec<f32>], levels: &[Levels; 2]) -> Vec<u8> {
+    let half = PIXELS_PER_LINE as usize / 2;
+    lines
+        .iter()
+        .flat_map(|line| {
+            line.iter()
+                .enumerate()
+                .map(move |(x, value)| levels[x / half].scale(*value))
+        })
+        .collect()
+}
+
+/// Levels at the `clip` and 100 - `clip` percentile of the image area of each channel.
+///
+/// Only lines with a sync marker are used, unless there are none.
+pub fn percentile_levels(lines: &[Vec<f32>], sync_quality: &[f32], clip: f32) -> [Levels; 2] {
+    let has_sync = sync_quality.iter().any(|q| *q > 0.0);
+    let synced: Vec<&Vec<f32>> = lines
+        .iter()
+        .zip(sync_quality)
+        .filter(|(_, quality)| !has_sync || **quality > 0.0)
+        .map(|(line, _)| line)
+        .collect();
+
+    let channel_levels = |start: usize| {
+        let mut values: Vec<f32> = synced
+            .iter()
+            .flat_map(|line| line[start..start + IMAGE_WIDTH].iter().cloned())
+            .collect();
+        if values.is_empty() {
+            return Levels {
+                black: 0.0,
+                white: 1.0,
+            };
+        }
+        Levels {
+            black: percentile(&mut values, clip),
+            white: percentile(&mut values, 100.0 - clip),
+        }
+    };
+
+    [channel_levels(IMAGE_A_START), channel_levels(IMAGE_B_START)]
+}
+
+/// Levels of wedge 9 and wedge 8 of each channel, averaged over all telemetry frames.
+///
+/// Returns `None` if no telemetry frame was found.
+pub fn wedge_levels(telemetry: &Telemetry) -> Option<[Levels; 2]> {
+    if telemetry.frames.is_empty() {
+        return None;
+    }
+    let count = telemetry.frames.len() as f32;
+    let mean =
+        |level: fn(&TelemetryFrame) -> f32| telemetry.frames.iter().map(level).sum::<f32>() / count;
+
+    Some([
+        Levels {
+            black: mean(|frame| frame.wedges_a[BLACK_WEDGE]),
+            white: mean(|frame| frame.wedges_a[WHITE_WEDGE]),
+        },
+        Levels {
+            black: mean(|frame| frame.wedges_b[BLACK_WEDGE]),
+            white: mean(|frame| frame.wedges_b[WHITE_WEDGE]),
+        },
+    ])
+}
+
+/// Scales `lines` by polynomials of `degree` mapping the measured levels of wedges 1 - 9
+/// to their modulation, fitted separately for both channels of every telemetry frame.
+///
+/// Each fit applies to the middle of its frame, lines in between blend the two closest fits.
+/// Returns `None` if no frame could be fitted.
+pub fn wedge_fit(lines: &[Vec<f32>], telemetry: &Telemetry, degree: usize) -> Option<Vec<u8>> {
+    let degree = degree.clamp(1, MAX_FIT_DEGREE);
+    let fits: Vec<(f32, [Transfer; 2])> = telemetry
+        .frames
+        .iter()
+        .filter_map(|frame| {
+            let centre = (frame.start_line + LINES_PER_FRAME / 2) as f32;
+            let fit_a = Transfer::fit(&frame.wedges_a, degree)?;
+            let fit_b = Transfer::fit(&frame.wedges_b, degree)?;
+            Some((centre, [fit_a, fit_b]))
+        })
+        .collect();
+    let last = fits.len().checked_sub(1)?;
+
+    let half = PIXELS_PER_LINE as usize / 2;
+    let mut pixels = Vec::with_capacity(lines.len() * PIXELS_PER_LINE as usize);
+    for (y, line) in lines.iter().enumerate() {
+        let y = y as f32;
+        let (before, after, t) = match fits.iter().position(|(centre, _)| *centre > y) {
+            Some(0) => (0, 0, 0.0),
+            Some(next) => {
+                let (start, end) = (fits[next - 1].0, fits[next].0);
+                (next - 1, next, ((y - start) / (end - start)) as f64)
+            }
+            None => (last, last, 0.0),
+        };
+
+        for (x, value) in line.iter().enumerate() {
+            let channel = x / half;
+            let modulation = (1.0 - t) * fits[before].1[channel].apply(*value)
+                + t * fits[after].1[channel].apply(*value);
+            pixels.push((modulation * 255.0).round().clamp(0.0, 255.0) as u8);
+        }
+    }
+    Some(pixels)
+}
+
+// Polynomial mapping the measured levels to the modulation.
+struct Transfer {
+    // Levels are divided by the brightest wedge, which keeps the fit well conditioned
+    scale: f64,
+    // Starting with the constant term
+    coefficients: Vec<f64>,
+    // Range of the scaled wedge levels, the polynomial may turn back outside of it
+    lowest: f64,
+    highest: f64,
+}
+
+impl Transfer {
+    // Least squares fit of a polynomial of `degree` through the levels of wedges 1 - 9 and their modulation.
+    //
+    // Returns `None` if the wedges are too similar to fit, e.g. without signal.
+    fn fit(wedges: &[f32; WEDGES], degree: usize) -> Option<Transfer> {
+        let levels = &wedges[0..FRAME_MARKER.len()];
+        let scale = levels
+            .iter()
+            .fold(0.0f32, |max, level| max.max(level.abs())) as f64;
+        if scale <= 0.0 {
+            return None;
+        }
+
+        // Normal equations, augmented with the right hand side
+        let size = degree + 1;
+        let mut matrix = vec![vec![0.0f64; size + 1]; size];
+        for (level, modulation) in levels.iter().zip(FRAME_MARKER.iter()) {
+            let x = *level as f64 / scale;
+            let powers: Vec<f64> = (0..size).map(|i| x.powi(i as i32)).collect();
+            for row in 0..size {
+                for column in 0..size {
+                    matrix[row][column] += powers[row] * powers[column];
+                }
+                matrix[row][size] += powers[row] * *modulation as f64;
+            }
+        }
+
+        // Gaussian elimination with partial pivoting
+        for column in 0..size {
+            let pivot = (column..size)
+                .max_by(|a, b| {
+                    matrix[*a][column]
+                        .abs()
+                        .total_cmp(&matrix[*b][column].abs())
+                })
+                .unwrap_or(column);
+            matrix.swap(column, pivot);
+            if matrix[column][column].abs() < 1e-9 {
+                return None;
+            }
+            let (upper, lower) = matrix.split_at_mut(column + 1);
+            let pivot_row = &upper[column];
+            for row in lower.iter_mut() {
+                let factor = row[column] / pivot_row[column];
+                for (value, pivot) in row.iter_mut().zip(pivot_row).skip(column) {
+                    *value -= factor * pivot;
+                }
+            }
+        }
+
+        let mut coefficients = vec![0.0; size];
+        for row in (0..size).rev() {
+            let sum: f64 = (row + 1..size)
+                .map(|k| matrix[row][k] * coefficients[k])
+                .sum();
+            coefficients[row] = (matrix[row][size] - sum) / matrix[row][row];
+        }
+        let scaled = levels.iter().map(|level| *level as f64 / scale);
+        Some(Transfer {
+            scale,
+            coefficients,
+            lowest: scaled.clone().fold(f64::INFINITY, f64::min),
+            highest: scaled.fold(f64::NEG_INFINITY, f64::max),
+        })
+    }
+
+    // Modulation of `level`, levels beyond the wedges map to the modulation of the closest wedge.
+    fn apply(&self, level: f32) -> f64 {
+        let x = (level as f64 / self.scale).clamp(self.lowest, self.highest);
+        self.coefficients
+            .iter()
+            .rev()
+            .fold(0.0, |sum, c| sum * x + c)
+    }
+}
+
+// Value below which `percent` of `values` lie, reorders `values`.
+fn percentile(values: &mut [f32], percent: f32) -> f32 {
+    let index = ((values.len() - 1) as f32 * percent.clamp(0.0, 100.0) / 100.0).round() as usize;
+    *values
+        .select_nth_unstable_by(index, |a, b| a.total_cmp(b))
+        .1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Wedge levels producing the modulations of wedges 1 - 9 through `transfer`,
+    // which has to be increasing on [0, 1].
+    fn wedges(transfer: impl Fn(f64) -> f64) -> [f32; WEDGES] {
+        let mut wedges = [0.0; WEDGES];
+        for (wedge, modulation) in wedges.iter_mut().zip(FRAME_MARKER.iter()) {
+            let (mut low, mut high) = (0.0, 1.0);
+            for _ in 0..60 {
+                let middle = (low + high) / 2.0;
+                if transfer(middle) < *modulation as f64 {
+                    low = middle;
+                } else {
+                    high = middle;
+                }
+            }
+            *wedge = low as f32;
+        }
+        wedges
+    }
+
+    // Coefficients of `fit` in terms of the unscaled levels.
+    fn unscaled(fit: &Transfer) -> Vec<f64> {
+        fit.coefficients
+            .iter()
+            .enumerate()
+            .map(|(power, c)| c / fit.scale.powi(power as i32))
+            .collect()
+    }
+
+    fn assert_coefficients(fit: &Transfer, expected: &[f64]) {
+        let coefficients = unscaled(fit);
+        assert_eq!(coefficients.len(), expected.len());
+        for (c, e) in coefficients.iter().zip(expected) {
+            assert!((c - e).abs() < 1e-3, "{:?} != {:?}", coefficients, expected);
+        }
+    }
+
+    #[test]
+    fn fits_linear_ramp() {
+        let expected = [-0.125, 1.25];
+        let fit = Transfer::fit(&wedges(|x| expected[0] + expected[1] * x), 1).unwrap();
+        assert_coefficients(&fit, &expected);
+    }
+
+    #[test]
+    fn fits_cubic_ramp() {
+        let expected = [-0.05, 0.4, 0.3, 0.6];
+        let cubic =
+            |x: f64| expected[0] + expected[1] * x + expected[2] * x * x + expected[3] * x.powi(3);
+        let fit = Transfer::fit(&wedges(cubic), 3).unwrap();
+        assert_coefficients(&fit, &expected);
+    }
+
+    #[test]
+    fn clamps_levels_beyond_the_wedges() {
+        let wedges = wedges(|x| -0.125 + 1.25 * x);
+        let fit = Transfer::fit(&wedges, 1).unwrap();
+        assert!((fit.apply(2.0) - 1.0).abs() < 1e-3);
+        assert!(fit.apply(-1.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn rejects_flat_wedges() {
+        assert!(Transfer::fit(&[0.0; WEDGES], 1).is_none());
+        assert!(Transfer::fit(&[0.5; WEDGES], 1).is_none());
+    }
+}
@@ -0,0 +1,294 @@
+//! Satellite positions relative to an observer on the ground.
+//!
+//! Times are unix timestamps in seconds (UTC), positions are in km.
+//! The SGP4 results in the TEME frame are rotated into the earth fixed frame
+//! using the Greenwich mean sidereal time, polar motion is neglected.
+
+use std::f64::consts::PI;
+use std::str::FromStr;
+
+use errors::DecoderError;
+use sgp4::{days_from_civil, Sgp4, StateVector};
+
+const SECONDS_PER_DAY: f64 = 86400.0;
+const UNIX_EPOCH_JULIAN_DATE: f64 = 2440587.5;
+const J2000_JULIAN_DATE: f64 = 2451545.0;
+
+/// Rotation rate of the earth in rad/s.
+const EARTH_ROTATION: f64 = 7.292115e-5;
+
+// WGS-84 ellipsoid
+const WGS84_RADIUS: f64 = 6378.137;
+const WGS84_FLATTENING: f64 = 1.0 / 298.257223563;
+
+/// Speed of light in km/s.
+pub const SPEED_OF_LIGHT: f64 = 299792.458;
+
+/// Parses a UTC time like `2024-05-01T12:34:56Z` or a unix timestamp into a unix timestamp.
+///
+/// The date and time may be separated by a space instead of `T`,
+/// fractional seconds are supported and the trailing `Z` is optional.
+pub fn parse_time(text: &str) -> Result<f64, DecoderError> {
+    let invalid = || DecoderError::InvalidTime(text.to_string());
+
+    if let Ok(timestamp) = text.parse::<f64>() {
+        return Ok(timestamp);
+    }
+
+    let text = text.trim().trim_end_matches('Z');
+    let (date, time) = text.split_once(['T', ' ']).ok_or_else(invalid)?;
+
+    let date: Vec<i64> = date
+        .split('-')
+        .map(|part| part.parse().map_err(|_| invalid()))
+        .collect::<Result<_, _>>()?;
+    let time: Vec<f64> = time
+        .split(':')
+        .map(|part| part.parse().map_err(|_| invalid()))
+        .collect::<Result<_, _>>()?;
+    if date.len() != 3 || time.len() < 2 || time.len() > 3 {
+        return Err(invalid());
+    }
+
+    let days = days_from_civil(date[0], date[1], date[2]);
+    let seconds = time[0] * 3600.0 + time[1] * 60.0 + time.get(2).unwrap_or(&0.0);
+    Ok(days as f64 * SECONDS_PER_DAY + seconds)
+}
+
+/// Formats a unix timestamp as UTC time like `2024-05-01T12:34:56.789Z`.
+pub fn format_time(time: f64) -> String {
+    let millis = (time * 1000.0).round() as i64;
+    let days = millis.div_euclid(86_400_000);
+    let millis = millis.rem_euclid(86_400_000);
+
+    // Inverse of days_from_civil
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        millis / 3_600_000,
+        millis / 60_000 % 60,
+        millis / 1000 % 60,
+        millis % 1000
+    )
+}
+
+/// Greenwich mean sidereal time in radians at the unix time `time`.
+pub fn gmst(time: f64) -> f64 {
+    let julian_date = time / SECONDS_PER_DAY + UNIX_EPOCH_JULIAN_DATE;
+    let t = (julian_date - J2000_JULIAN_DATE) / 36525.0;
+    let seconds = -6.2e-6 * t * t * t
+        + 0.093104 * t * t
+        + (876600.0 * 3600.0 + 8640184.812866) * t
+        + 67310.54841;
+    // 240 seconds of time are one degree
+    (seconds / 240.0).to_radians().rem_euclid(2.0 * PI)
+}
+
+/// Location on the ground.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Observer {
+    /// Geodetic latitude in degrees, north is positive
+    pub latitude: f64,
+    /// Longitude in degrees, east is positive
+    pub longitude: f64,
+    /// Height above the ellipsoid in m
+    pub altitude: f64,
+}
+
+impl Observer {
+    /// Position in the earth fixed frame in km.
+    pub fn position(&self) -> [f64; 3] {
+        geodetic_to_ecef(self.latitude, self.longitude, self.altitude / 1000.0)
+    }
+}
+
+impl FromStr for Observer {
+    type Err = DecoderError;
+
+    /// Parses `latitude,longitude[,altitude]` in degrees and m.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || DecoderError::InvalidLocation(s.to_string());
+        let values: Vec<f64> = s
+            .split(',')
+            .map(|part| part.trim().parse().map_err(|_| invalid()))
+            .collect::<Result<_, _>>()?;
+
+        match values.as_slice() {
+            [latitude, longitude] | [latitude, longitude, _]
+                if latitude.abs() > 90.0 || longitude.abs() > 180.0 =>
+            {
+                Err(invalid())
+            }
+            [latitude, longitude] => Ok(Observer {
+                latitude: *latitude,
+                longitude: *longitude,
+                altitude: 0.0,
+            }),
+            [latitude, longitude, altitude] => Ok(Observer {
+                latitude: *latitude,
+                longitude: *longitude,
+                altitude: *altitude,
+            }),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+/// Converts geodetic coordinates in degrees and km to the earth fixed frame.
+pub fn geodetic_to_ecef(latitude: f64, longitude: f64, altitude: f64) -> [f64; 3] {
+    let e2 = WGS84_FLATTENING * (2.0 - WGS84_FLATTENING);
+    let (sin_lat, cos_lat) = latitude.to_radians().sin_cos();
+    let (sin_lon, cos_lon) = longitude.to_radians().sin_cos();
+    let n = WGS84_RADIUS / (1.0 - e2 * sin_lat * sin_lat).sqrt();
+
+    [
+        (n + altitude) * cos_lat * cos_lon,
+        (n + altitude) * cos_lat * sin_lon,
+        (n * (1.0 - e2) + altitude) * sin_lat,
+    ]
+}
+
+/// Converts a position in the earth fixed frame in km to geodetic latitude, longitude and altitude.
+///
+/// Latitude and longitude are in degrees, the altitude is in km.
+pub fn ecef_to_geodetic(position: &[f64; 3]) -> (f64, f64, f64) {
+    let e2 = WGS84_FLATTENING * (2.0 - WGS84_FLATTENING);
+    let [x, y, z] = *position;
+    let p = (x * x + y * y).sqrt();
+
+    // Converges to well below a millimetre within a few iterations
+    let mut latitude = z.atan2(p * (1.0 - e2));
+    let mut altitude = 0.0;
+    for _ in 0..5 {
+        let sin_lat = latitude.sin();
+        let n = WGS84_RADIUS / (1.0 - e2 * sin_lat * sin_lat).sqrt();
+        altitude = p / latitude.cos() - n;
+        latitude = z.atan2(p * (1.0 - e2 * n / (n + altitude)));
+    }
+
+    (latitude.to_degrees(), y.atan2(x).to_degrees(), altitude)
+}
+
+/// Point where a ray from `origin` in `direction` first hits the WGS-84 ellipsoid, in km.
+///
+/// Returns `None` if the ray misses the earth.
+pub fn intersect_earth(origin: &[f64; 3], direction: &[f64; 3]) -> Option<[f64; 3]> {
+    // Scaling the axes turns the ellipsoid into the unit sphere
+    let polar_radius = WGS84_RADIUS * (1.0 - WGS84_FLATTENING);
+    let scale = [WGS84_RADIUS, WGS84_RADIUS, polar_radius];
+    let o: Vec<f64> = (0..3).map(|i| origin[i] / scale[i]).collect();
+    let d: Vec<f64> = (0..3).map(|i| direction[i] / scale[i]).collect();
+
+    let a: f64 = d.iter().map(|d| d * d).sum();
+    let b: f64 = (0..3).map(|i| o[i] * d[i]).sum();
+    let c: f64 = o.iter().map(|o| o * o).sum::<f64>() - 1.0;
+    let discriminant = b * b - a * c;
+    if discriminant < 0.0 {
+        return None;
+    }
+
+    let t = (-b - discriminant.sqrt()) / a;
+    if t < 0.0 {
+        return None;
+    }
+    Some([
+        origin[0] + t * direction[0],
+        origin[1] + t * direction[1],
+        origin[2] + t * direction[2],
+    ])
+}
+
+/// Rotates a TEME state vector at the unix time `time` into the earth fixed frame.
+pub fn teme_to_ecef(state: &StateVector, time: f64) -> StateVector {
+    let (sin, cos) = gmst(time).sin_cos();
+    let rotate = |v: &[f64; 3]| [cos * v[0] + sin * v[1], -sin * v[0] + cos * v[1], v[2]];
+
+    let position = rotate(&state.position);
+    let velocity = rotate(&state.velocity);
+    StateVector {
+        position,
+        // Remove the velocity of the rotating frame
+        velocity: [
+            velocity[0] + EARTH_ROTATION * position[1],
+            velocity[1] - EARTH_ROTATION * position[0],
+            velocity[2],
+        ],
+    }
+}
+
+/// Elevation of the sun in degrees at the unix time `time`, seen from a location on the ground.
+///
+/// Uses the low precision solar coordinates of the Astronomical Almanac,
+/// accurate to about 0.01 degrees, refraction is neglected.
+pub fn sun_elevation(time: f64, latitude: f64, longitude: f64) -> f64 {
+    let days = time / SECONDS_PER_DAY + UNIX_EPOCH_JULIAN_DATE - J2000_JULIAN_DATE;
+    let mean_longitude = 280.460 + 0.9856474 * days;
+    let mean_anomaly = (357.528 + 0.9856003 * days).to_radians();
+    let ecliptic_longitude =
+        (mean_longitude + 1.915 * mean_anomaly.sin() + 0.020 * (2.0 * mean_anomaly).sin())
+            .to_radians();
+    let obliquity = (23.439 - 0.0000004 * days).to_radians();
+
+    let right_ascension =
+        (obliquity.cos() * ecliptic_longitude.sin()).atan2(ecliptic_longitude.cos());
+    let declination = (obliquity.sin() * ecliptic_longitude.sin()).asin();
+    let hour_angle = gmst(time) + longitude.to_radians() - right_ascension;
+
+    let latitude = latitude.to_radians();
+    (latitude.sin() * declination.sin() + latitude.cos() * declination.cos() * hour_angle.cos())
+        .asin()
+        .to_degrees()
+}
+
+/// Follows a satellite from a location on the ground.
+#[derive(Clone, Debug)]
+pub struct Tracker {
+    pub sgp4: Sgp4,
+    pub observer: Observer,
+}
+
+impl Tracker {
+    pub fn new(sgp4: Sgp4, observer: Observer) -> Tracker {
+        Tracker { sgp4, observer }
+    }
+
+    /// State of the satellite in the earth fixed frame at the unix time `time`.
+    pub fn ecef(&self, time: f64) -> Result<StateVector, DecoderError> {
+        let state = self.sgp4.propagate_to(time)?;
+        Ok(teme_to_ecef(&state, time))
+    }
+
+    /// Rate of change of the distance between observer and satellite in km/s.
+    ///
+    /// Positive while the satellite moves away.
+    pub fn range_rate(&self, time: f64) -> Result<f64, DecoderError> {
+        let state = self.ecef(time)?;
+        let observer = self.observer.position();
+
+        let range: Vec<f64> = (0..3).map(|i| state.position[i] - observer[i]).collect();
+        let distance = range.iter().map(|r| r * r).sum::<f64>().sqrt();
+        Ok((0..3).map(|i| range[i] * state.velocity[i]).sum::<f64>() / distance)
+    }
+
+    /// Doppler shift in Hz of a signal transmitted at `frequency` Hz at the unix time `time`.
+    pub fn doppler_shift(&self, frequency: f64, time: f64) -> Result<f64, DecoderError> {
+        Ok(-frequency * self.range_rate(time)? / SPEED_OF_LIGHT)
+    }
+}
@@ -0,0 +1,200 @@
+//! Coastlines, borders and a latitude / longitude grid drawn over decoded images.
+//!
+//! Every point of a line is placed in the image using a [`PixelLocator`].
+//! Both channels show the same area, so everything is drawn over both of them.
+
+use std::fmt;
+use std::str::FromStr;
+
+use image::{GrayImage, Rgb, RgbImage, Rgba, RgbaImage};
+
+use decoder::{IMAGE_A_START, IMAGE_B_START, IMAGE_WIDTH, PIXELS_PER_LINE};
+use errors::DecoderError;
+use georef::{wrap_longitude, Georeferencing, PixelLocator};
+use shapes::Polyline;
+
+/// Colour of coastlines.
+pub const COASTLINE_COLOR: Rgba<u8> = Rgba([255, 220, 0, 255]);
+/// Colour of borders.
+pub const BORDER_COLOR: Rgba<u8> = Rgba([255, 64, 64, 255]);
+/// Colour of the latitude / longitude grid.
+pub const GRATICULE_COLOR: Rgba<u8> = Rgba([96, 160, 255, 255]);
+/// Smallest spacing of the latitude / longitude grid in degrees, finer grids just cover the image.
+pub const MIN_GRATICULE_SPACING: f64 = 0.1;
+
+// Lines are split into pieces of at most this many degrees, so they follow the curved scan geometry.
+const MAX_STEP: f64 = 0.1;
+// Longer pieces are jumps across the edge of the image, e.g. at the date line.
+const MAX_SEGMENT_PIXELS: f64 = 50.0;
+
+/// How the overlay is written next to the output image.
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub enum OverlayMode {
+    /// Drawn over a colour copy of the image
+    #[default]
+    BurnIn,
+    /// Transparent image of the same size, to be stacked on top of the image
+    Layer,
+}
+
+impl FromStr for OverlayMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "burn-in" => Ok(OverlayMode::BurnIn),
+            "layer" => Ok(OverlayMode::Layer),
+            _ => Err(format!("Unknown overlay mode {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for OverlayMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            OverlayMode::BurnIn => "Burned in",
+            OverlayMode::Layer => "Separate layer",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Lines drawn in the same colour, e.g. all coastlines.
+#[derive(Clone, Debug)]
+pub struct MapLayer {
+    pub lines: Vec<Polyline>,
+    pub color: Rgba<u8>,
+}
+
+/// Everything drawn over the image.
+#[derive(Clone, Debug, Default)]
+pub struct Overlay {
+    /// Layers in drawing order, later layers are drawn on top
+    pub layers: Vec<MapLayer>,
+    /// Spacing of the latitude / longitude grid in degrees, no grid if `None`
+    pub graticule: Option<f64>,
+    pub mode: OverlayMode,
+}
+
+impl Overlay {
+    /// Draws the overlay for an image with `lines` lines on a transparent image.
+    pub fn render(
+        &self,
+        georeference: &Georeferencing,
+        lines: u32,
+    ) -> Result<RgbaImage, DecoderError> {
+        let mut image = RgbaImage::new(PIXELS_PER_LINE, lines);
+        if lines == 0 {
+            return Ok(image);
+        }
+        let locator = PixelLocator::new(georeference, lines)?;
+
+        if let Some(spacing) = self.graticule {
+            for line in graticule(spacing) {
+                draw_polyline(&mut image, &locator, &line, GRATICULE_COLOR);
+            }
+        }
+        for layer in &self.layers {
+            for line in &layer.lines {
+                draw_polyline(&mut image, &locator, line, layer.color);
+            }
+        }
+
+        Ok(image)
+    }
+}
+
+/// Draws `overlay` over a colour copy of `image`, blending by the alpha of the overlay.
+pub fn burn_in(image: &GrayImage, overlay: &RgbaImage) -> RgbImage {
+    RgbImage::from_fn(image.width(), image.height(), |x, y| {
+        let gray = image.get_pixel(x, y)[0] as f32;
+        let Rgba([r, g, b, a]) = *overlay.get_pixel(x, y);
+        let alpha = a as f32 / 255.0;
+        let blend = |c: u8| (gray * (1.0 - alpha) + c as f32 * alpha) as u8;
+        Rgb([blend(r), blend(g), blend(b)])
+    })
+}
+
+/// Lines of constant latitude and longitude every `spacing` degrees.
+///
+/// Spacings below [`MIN_GRATICULE_SPACING`] give no lines.
+pub fn graticule(spacing: f64) -> Vec<Polyline> {
+    let mut lines = Vec::new();
+    if spacing.is_nan() || spacing < MIN_GRATICULE_SPACING {
+        return lines;
+    }
+    let count = (180.0 / spacing).ceil() as i32;
+
+    for i in -count..=count {
+        let latitude = i as f64 * spacing;
+        if latitude.abs() < 90.0 {
+            lines.push((-180..=180).map(|lon| (lon as f64, latitude)).collect());
+        }
+    }
+    for i in -(2 * count)..(2 * count) {
+        let longitude = i as f64 * spacing;
+        if (-180.0..180.0).contains(&longitude) {
+            // The grid ends short of the poles, where all meridians meet
+            lines.push((-89..=89).map(|lat| (longitude, lat as f64)).collect());
+        }
+    }
+
+    lines
+}
+
+fn draw_polyline(image: &mut RgbaImage, locator: &PixelLocator, line: &Polyline, color: Rgba<u8>) {
+    let mut previous: Option<(f64, f64)> = None;
+    for (i, &(lon, lat)) in line.iter().enumerate() {
+        // Split long pieces, so they follow the scan geometry
+        let steps = match i {
+            0 => 1,
+            _ => {
+                let (prev_lon, prev_lat) = line[i - 1];
+                let delta_lon = wrap_longitude(lon - prev_lon);
+                (delta_lon.abs().max((lat - prev_lat).abs()) / MAX_STEP).ceil() as usize
+            }
+        }
+        .max(1);
+
+        for step in 1..=steps {
+            let point = if i == 0 {
+                (lon, lat)
+            } else {
+                let (prev_lon, prev_lat) = line[i - 1];
+                let t = step as f64 / steps as f64;
+                (
+                    prev_lon + wrap_longitude(lon - prev_lon) * t,
+                    prev_lat + (lat - prev_lat) * t,
+                )
+            };
+
+            let pixel = locator.locate(point.0, point.1);
+            if let (Some(from), Some(to)) = (previous, pixel) {
+                draw_segment(image, from, to, color);
+            }
+            previous = pixel;
+        }
+    }
+}
+
+// Draws a line between two positions given as line and channel column, in both channels.
+fn draw_segment(image: &mut RgbaImage, from: (f64, f64), to: (f64, f64), color: Rgba<u8>) {
+    let (delta_y, delta_x) = (to.0 - from.0, to.1 - from.1);
+    let length = delta_x.abs().max(delta_y.abs());
+    if length > MAX_SEGMENT_PIXELS {
+        return;
+    }
+
+    let steps = length.ceil().max(1.0) as usize;
+    for step in 0..=steps {
+        let t = step as f64 / steps as f64;
+        let y = (from.0 + delta_y * t).round();
+        let x = (from.1 + delta_x * t).round();
+        if y < 0.0 || y >= image.height() as f64 || x < 0.0 || x >= IMAGE_WIDTH as f64 {
+            continue;
+        }
+        for start in [IMAGE_A_START, IMAGE_B_START] {
+            image.put_pixel(x as u32 + start as u32, y as u32, color);
+        }
+    }
+}
@@ -0,0 +1,385 @@
+//! Reprojection of both channels from the scan geometry onto regular map grids.
+//!
+//! Every pixel of the map is located in the decoded image using a [`PixelLocator`]
+//! and interpolated bilinearly from the surrounding pixels of each channel.
+//! Pixels outside of the recorded swath are transparent,
+//! so maps of several passes on the same grid can be stacked.
+
+use std::f64::consts::PI;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+use image::{GrayAlphaImage, GrayImage, LumaA};
+
+use decoder::{ImageChannel, IMAGE_A_START, IMAGE_B_START, IMAGE_WIDTH};
+use errors::DecoderError;
+use georef::{wrap_longitude, Georeferencing, GroundControlPoint, PixelLocator};
+use orbit::{format_time, parse_time};
+
+// Mercator maps end here, the poles are infinitely far away.
+const MAX_MERCATOR_LATITUDE: f64 = 85.05112878;
+// Radius of the spherical earth of EPSG:3857 in m.
+const PSEUDO_MERCATOR_RADIUS: f64 = 6378137.0;
+// Larger maps are most likely a typo in the resolution.
+pub(crate) const MAX_MAP_PIXELS: u64 = 100_000_000;
+
+/// Map projections the channels can be reprojected to.
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub enum Projection {
+    /// Longitude and latitude mapped linearly to x and y, EPSG:4326
+    #[default]
+    Equirectangular,
+    /// Spherical Mercator as used by web maps, EPSG:3857
+    Mercator,
+}
+
+impl Projection {
+    /// Projects longitude and latitude in degrees to map coordinates.
+    ///
+    /// Map coordinates are scaled so x equals the longitude in degrees for both projections.
+    pub fn forward(&self, longitude: f64, latitude: f64) -> (f64, f64) {
+        match self {
+            Projection::Equirectangular => (longitude, latitude),
+            Projection::Mercator => {
+                let latitude = latitude
+                    .clamp(-MAX_MERCATOR_LATITUDE, MAX_MERCATOR_LATITUDE)
+                    .to_radians();
+                (
+                    longitude,
+                    (PI / 4.0 + latitude / 2.0).tan().ln().to_degrees(),
+                )
+            }
+        }
+    }
+
+    /// Converts map coordinates back to longitude and latitude in degrees.
+    pub fn inverse(&self, x: f64, y: f64) -> (f64, f64) {
+        match self {
+            Projection::Equirectangular => (x, y),
+            Projection::Mercator => (
+                x,
+                (2.0 * y.to_radians().exp().atan() - PI / 2.0).to_degrees(),
+            ),
+        }
+    }
+
+    /// Units of the world file per unit of map coordinates, degrees or m.
+    pub fn world_scale(&self) -> f64 {
+        match self {
+            Projection::Equirectangular => 1.0,
+            Projection::Mercator => PSEUDO_MERCATOR_RADIUS * PI / 180.0,
+        }
+    }
+}
+
+impl FromStr for Projection {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "equirectangular" => Ok(Projection::Equirectangular),
+            "mercator" => Ok(Projection::Mercator),
+            _ => Err(format!("Unknown projection {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for Projection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Projection::Equirectangular => "Equirectangular",
+            Projection::Mercator => "Mercator",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Area covered by a map in degrees.
+///
+/// If `east` is smaller than `west` the map crosses the date line.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Bounds {
+    pub west: f64,
+    pub south: f64,
+    pub east: f64,
+    pub north: f64,
+}
+
+impl Bounds {
+    /// Smallest area containing all ground control points.
+    pub fn around(points: &[GroundControlPoint]) -> Option<Bounds> {
+        let reference = points.first()?.longitude;
+        let mut bounds = Bounds {
+            west: f64::MAX,
+            south: f64::MAX,
+            east: f64::MIN,
+            north: f64::MIN,
+        };
+        for point in points {
+            // Relative to the first point, so passes over the date line stay in one piece
+            let longitude = reference + wrap_longitude(point.longitude - reference);
+            bounds.west = bounds.west.min(longitude);
+            bounds.east = bounds.east.max(longitude);
+            bounds.south = bounds.south.min(point.latitude);
+            bounds.north = bounds.north.max(point.latitude);
+        }
+        bounds.west = wrap_longitude(bounds.west);
+        bounds.east = wrap_longitude(bounds.east);
+        Some(bounds)
+    }
+
+    // Eastern edge, moved behind the western edge if the area crosses the date line.
+    fn unwrapped_east(&self) -> f64 {
+        if self.east < self.west {
+            self.east + 360.0
+        } else {
+            self.east
+        }
+    }
+}
+
+impl FromStr for Bounds {
+    type Err = DecoderError;
+
+    /// Parses `west,south,east,north` in degrees.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid =
+            || DecoderError::InvalidBounds(format!("{}, expected west,south,east,north", s));
+        let values: Vec<f64> = s
+            .split(',')
+            .map(|part| part.trim().parse().map_err(|_| invalid()))
+            .collect::<Result<_, _>>()?;
+
+        match values.as_slice() {
+            [west, south, east, north]
+                if south < north
+                    && south.abs() <= 90.0
+                    && north.abs() <= 90.0
+                    && west.abs() <= 180.0
+                    && east.abs() <= 180.0 =>
+            {
+                Ok(Bounds {
+                    west: *west,
+                    south: *south,
+                    east: *east,
+                    north: *north,
+                })
+            }
+            _ => Err(invalid()),
+        }
+    }
+}
+
+/// Suffix of the map files of `channel` next to the output image, e.g. `-A-map`.
+pub fn map_suffix(channel: ImageChannel) -> String {
+    format!("-{}-map", channel)
+}
+
+/// World file of a north up map, in degrees or m depending on the projection.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WorldFile {
+    /// Size of a pixel
+    pub pixel_size: f64,
+    /// x coordinate of the center of the top left pixel
+    pub left: f64,
+    /// y coordinate of the center of the top left pixel
+    pub top: f64,
+}
+
+impl WorldFile {
+    /// Reads a world file, rotated or stretched maps are not supported.
+    pub fn read(path: &Path) -> Result<WorldFile, DecoderError> {
+        let invalid =
+            || DecoderError::InvalidMap(format!("{} is no valid world file", path.display()));
+        let text = fs::read_to_string(path).map_err(DecoderError::InputIOError)?;
+        let values: Vec<f64> = text
+            .split_whitespace()
+            .map(|value| value.parse().map_err(|_| invalid()))
+            .collect::<Result<_, _>>()?;
+
+        match values.as_slice() {
+            [size, 0.0, 0.0, negative_size, left, top]
+                if *size > 0.0 && (size + negative_size).abs() < size * 1e-9 =>
+            {
+                Ok(WorldFile {
+                    pixel_size: *size,
+                    left: *left,
+                    top: *top,
+                })
+            }
+            _ => Err(invalid()),
+        }
+    }
+
+    pub fn write(&self, path: &Path) -> std::io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        // Pixel size, rotation and the center of the top left pixel
+        for value in [
+            self.pixel_size,
+            0.0,
+            0.0,
+            -self.pixel_size,
+            self.left,
+            self.top,
+        ] {
+            writeln!(writer, "{:.10}", value)?;
+        }
+        writer.flush()
+    }
+}
+
+/// Map grid the channels are reprojected to.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MapGrid {
+    pub projection: Projection,
+    /// Area of the map, the area covered by the pass if `None`
+    pub bounds: Option<Bounds>,
+    /// Size of a pixel in degrees of longitude, the same size is used vertically in map coordinates
+    pub resolution: f64,
+}
+
+/// Both channels reprojected onto a map grid.
+pub struct MapImage {
+    pub projection: Projection,
+    pub bounds: Bounds,
+    pub resolution: f64,
+    /// Unix time of the middle of the pass
+    pub time: f64,
+    /// Channel A, transparent where the pass does not cover the map
+    pub channel_a: GrayAlphaImage,
+    /// Channel B, transparent where the pass does not cover the map
+    pub channel_b: GrayAlphaImage,
+}
+
+impl MapImage {
+    /// World file of both channels.
+    pub fn world_file(&self) -> WorldFile {
+        let scale = self.projection.world_scale();
+        let (x, _) = self.projection.forward(self.bounds.west, 0.0);
+        let (_, y) = self.projection.forward(0.0, self.bounds.north);
+        let pixel_size = self.resolution * scale;
+
+        WorldFile {
+            pixel_size,
+            left: x * scale + pixel_size / 2.0,
+            top: y * scale - pixel_size / 2.0,
+        }
+    }
+
+    /// Writes the projection and time of the pass, which are not part of the world file.
+    pub fn write_info(&self, path: &Path) -> std::io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writeln!(writer, "projection={}", self.projection)?;
+        writeln!(writer, "time={}", format_time(self.time))?;
+        writer.flush()
+    }
+}
+
+/// Reads the projection and time of a pass written by [`MapImage::write_info`].
+pub fn read_map_info(path: &Path) -> Result<(Projection, f64), DecoderError> {
+    let invalid = || DecoderError::InvalidMap(format!("{} is no valid map info", path.display()));
+    let text = fs::read_to_string(path).map_err(DecoderError::InputIOError)?;
+
+    let mut projection = None;
+    let mut time = None;
+    for line in text.lines() {
+        match line.split_once('=') {
+            Some(("projection", value)) => projection = Some(value.parse().map_err(|_| invalid())?),
+            Some(("time", value)) => time = Some(parse_time(value)?),
+            _ => {}
+        }
+    }
+
+    match (projection, time) {
+        (Some(projection), Some(time)) => Ok((projection, time)),
+        _ => Err(invalid()),
+    }
+}
+
+/// Reprojects both channels of a decoded `image` onto `grid`.
+///
+/// `points` are only used to find the area of the pass if the grid has no bounds.
+pub fn reproject(
+    image: &GrayImage,
+    georeference: &Georeferencing,
+    grid: &MapGrid,
+    points: &[GroundControlPoint],
+) -> Result<MapImage, DecoderError> {
+    if !(grid.resolution.is_finite() && grid.resolution > 0.0) {
+        return Err(DecoderError::InvalidMap(format!(
+            "{} is no valid resolution",
+            grid.resolution
+        )));
+    }
+    let bounds = match grid.bounds.or_else(|| Bounds::around(points)) {
+        Some(bounds) => bounds,
+        None => return Err(DecoderError::InvalidBounds("empty pass".to_string())),
+    };
+
+    let projection = grid.projection;
+    let (mut west, mut north) = projection.forward(bounds.west, bounds.north);
+    let (east, south) = projection.forward(bounds.unwrapped_east(), bounds.south);
+    let mut bounds = bounds;
+    if grid.bounds.is_none() {
+        // Snapped to the resolution, so maps of different passes line up for mosaics
+        west = (west / grid.resolution).floor() * grid.resolution;
+        north = (north / grid.resolution).ceil() * grid.resolution;
+        let (snapped_west, snapped_north) = projection.inverse(west, north);
+        bounds.west = wrap_longitude(snapped_west);
+        bounds.north = snapped_north;
+    }
+    let width = ((east - west) / grid.resolution).ceil().max(1.0) as u64;
+    let height = ((north - south) / grid.resolution).ceil().max(1.0) as u64;
+    if width
+        .checked_mul(height)
+        .map_or(true, |pixels| pixels > MAX_MAP_PIXELS)
+    {
+        return Err(DecoderError::InvalidBounds(format!(
+            "a map of {} x {} pixels is too large",
+            width, height
+        )));
+    }
+
+    let locator = PixelLocator::new(georeference, image.height())?;
+    let mut channel_a = GrayAlphaImage::new(width as u32, height as u32);
+    let mut channel_b = GrayAlphaImage::new(width as u32, height as u32);
+    for y in 0..height as u32 {
+        for x in 0..width as u32 {
+            let (longitude, latitude) = projection.inverse(
+                west + (x as f64 + 0.5) * grid.resolution,
+                north - (y as f64 + 0.5) * grid.resolution,
+            );
+            if let Some((line, column)) = locator.locate(longitude, latitude) {
+                channel_a.put_pixel(x, y, sample(image, line, column, IMAGE_A_START));
+                channel_b.put_pixel(x, y, sample(image, line, column, IMAGE_B_START));
+            }
+        }
+    }
+
+    Ok(MapImage {
+        projection,
+        bounds,
+        resolution: grid.resolution,
+        time: georeference.line_time(image.height() as f64 / 2.0),
+        channel_a,
+        channel_b,
+    })
+}
+
+// Bilinear interpolation of a channel starting at `offset`, staying within the channel.
+fn sample(image: &GrayImage, line: f64, column: f64, offset: usize) -> LumaA<u8> {
+    let line = line.clamp(0.0, (image.height() - 1) as f64);
+    let column = column.clamp(0.0, (IMAGE_WIDTH - 1) as f64);
+    let (y0, x0) = (line.floor() as u32, column.floor() as u32);
+    let y1 = (y0 + 1).min(image.height() - 1);
+    let x1 = (x0 + 1).min(IMAGE_WIDTH as u32 - 1);
+    let (fy, fx) = (line - y0 as f64, column - x0 as f64);
+
+    let pixel = |y: u32, x: u32| image.get_pixel(x + offset as u32, y)[0] as f64;
+    let top = pixel(y0, x0) * (1.0 - fx) + pixel(y0, x1) * fx;
+    let bottom = pixel(y1, x0) * (1.0 - fx) + pixel(y1, x1) * fx;
+    LumaA([(top * (1.0 - fy) + bottom * fy).round() as u8, 255])
+}
@@ -1,67 +1,198 @@
-pub struct Upsampler<'a> {
-    factor: u16,
-    state: u16,
-    iterator: Box<dyn Iterator<Item = f32> + 'a>,
+use firfilter::{lowpass_coeffs, FilterSample};
+
+// Number of zero crossings of the anti-alias sinc on each side of its center.
+// More zero crossings give a steeper filter at the cost of more taps per output sample.
+const ZERO_CROSSINGS: usize = 16;
+
+/// Largest interpolation or decimation factor, which bounds the prototype filter to 131073 taps.
+/// Rates without a small ratio are approximated, e.g. 44101Hz to 4160Hz becomes 44100.8Hz.
+pub const MAX_RATE_FACTOR: usize = 4096;
+
+/// Rational resampler changing the sampling rate by L/M.
+/// Conceptually the input is upsampled by inserting L - 1 zeros after every sample,
+/// lowpass filtered and then decimated by M.
+/// The polyphase structure only evaluates the filter taps that hit non zero input samples
+/// for the output samples that are actually kept.
+/// If L or M would exceed [`MAX_RATE_FACTOR`] the ratio is approximated,
+/// see [`resampling_ratio`] and [`resampled_rate`].
+pub struct RationalResampler<'a, T = f32> {
+    interpolation: usize,
+    decimation: usize,
+    taps_per_phase: usize,
+    coeffs: Vec<f32>,
+    state: Vec<T>,
+    pos: usize,
+    phase: usize,
+    pending: usize,
+    iterator: Box<dyn Iterator<Item = T> + 'a>,
 }
 
-impl<'a> Upsampler<'a> {
-    pub fn from<I>(iterator: I, factor: u16) -> Upsampler<'a>
+impl<'a, T: FilterSample> RationalResampler<'a, T> {
+    pub fn from<I>(iterator: I, input_rate: u32, output_rate: u32) -> RationalResampler<'a, T>
     where
-        I: Iterator<Item = f32> + 'a,
+        I: Iterator<Item = T> + 'a,
     {
-        Upsampler {
-            factor: factor,
-            state: 0,
+        let (interpolation, decimation) = resampling_ratio(input_rate, output_rate);
+
+        // The filter runs at the upsampled rate and has to cut off at the lower nyquist frequency
+        // of both the input and the output signal.
+        let rate_factor = usize::max(interpolation, decimation);
+        let prototype = lowpass_coeffs(
+            0.5 / rate_factor as f32,
+            2 * ZERO_CROSSINGS * rate_factor + 1,
+        );
+
+        // Sort the prototype filter into phases, so all taps for one phase are adjacent.
+        // The gain is multiplied by L to make up for the inserted zeros.
+        let taps_per_phase = prototype.len().div_ceil(interpolation);
+        let mut coeffs = vec![0.0; interpolation * taps_per_phase];
+        for (i, coeff) in prototype.iter().enumerate() {
+            let phase = i % interpolation;
+            let tap = i / interpolation;
+            coeffs[phase * taps_per_phase + tap] = coeff * interpolation as f32;
+        }
+
+        RationalResampler {
+            interpolation,
+            decimation,
+            taps_per_phase,
+            coeffs,
+            state: vec![T::default(); taps_per_phase],
+            pos: 0,
+            phase: 0,
+            pending: 1,
             iterator: Box::new(iterator),
         }
     }
 }
 
-impl<'a> Iterator for Upsampler<'a> {
-    type Item = f32;
+impl<'a, T: FilterSample> Iterator for RationalResampler<'a, T> {
+    type Item = T;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let result = if self.state == 0 {
-            self.iterator.next()
-        } else {
-            Some(0.0)
-        };
-        self.state = (self.state + 1) % self.factor;
-
-        return result;
+        while self.pending > 0 {
+            self.pos = (self.pos + 1) % self.taps_per_phase;
+            self.state[self.pos] = self.iterator.next()?;
+            self.pending -= 1;
+        }
+
+        let phase_coeffs = &self.coeffs[self.phase * self.taps_per_phase..];
+        let mut result = T::default();
+        for (i, coeff) in phase_coeffs.iter().take(self.taps_per_phase).enumerate() {
+            let pos = (self.pos + self.taps_per_phase - i) % self.taps_per_phase;
+            result += self.state[pos] * *coeff;
+        }
+
+        self.phase += self.decimation;
+        self.pending = self.phase / self.interpolation;
+        self.phase %= self.interpolation;
+
+        Some(result)
     }
 }
 
-pub struct Downsampler<'a> {
-    factor: u16,
-    iterator: Box<dyn Iterator<Item = f32> + 'a>,
-}
+/// Interpolation L and decimation M used to resample from `input_rate` to `output_rate`.
+///
+/// Exact if both fit [`MAX_RATE_FACTOR`] after cancelling common factors,
+/// otherwise the closest continued fraction convergent that does.
+pub fn resampling_ratio(input_rate: u32, output_rate: u32) -> (usize, usize) {
+    let divisor = gcd(input_rate, output_rate).max(1);
+    let interpolation = (output_rate / divisor) as usize;
+    let decimation = (input_rate / divisor) as usize;
+    if interpolation.max(decimation) <= MAX_RATE_FACTOR {
+        return (interpolation.max(1), decimation.max(1));
+    }
 
-impl<'a> Downsampler<'a> {
-    pub fn from<I>(iterator: I, factor: u16) -> Downsampler<'a>
-    where
-        I: Iterator<Item = f32> + 'a,
-    {
-        Downsampler {
-            factor: factor,
-            iterator: Box::new(iterator),
+    // Convergents p/q of the continued fraction of L/M, until they get too large
+    let (mut p, mut q) = (1, 0);
+    let (mut previous_p, mut previous_q) = (0, 1);
+    let (mut numerator, mut denominator) = (interpolation, decimation);
+    while denominator != 0 {
+        let term = numerator / denominator;
+        let (next_p, next_q) = (term * p + previous_p, term * q + previous_q);
+        if next_p.max(next_q) > MAX_RATE_FACTOR {
+            break;
         }
+        (previous_p, previous_q, p, q) = (p, q, next_p, next_q);
+        (numerator, denominator) = (denominator, numerator - term * denominator);
     }
+    (p.max(1), q.max(1))
 }
 
-impl<'a> Iterator for Downsampler<'a> {
-    type Item = f32;
+/// Output rate in Hz actually produced when resampling from `input_rate` to `output_rate`.
+pub fn resampled_rate(input_rate: u32, output_rate: u32) -> f64 {
+    let (interpolation, decimation) = resampling_ratio(input_rate, output_rate);
+    input_rate as f64 * interpolation as f64 / decimation as f64
+}
 
-    fn next(&mut self) -> Option<Self::Item> {
-        let mut result = 0.0;
-        for _ in 0..self.factor {
-            match self.iterator.next() {
-                Some(x) => result += x,
-                None => return None,
-            }
+fn gcd(a: u32, b: u32) -> u32 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::PI;
+
+    const OUTPUT_RATE: u32 = 4160;
+    const TONE: f32 = 1000.0;
+
+    // Resamples one second of a 1kHz sine, returns the number of output samples
+    // and the peak amplitude after the filter has settled.
+    fn resample_tone(input_rate: u32) -> (usize, f32) {
+        let input =
+            (0..input_rate).map(move |i| (2.0 * PI * TONE * i as f32 / input_rate as f32).sin());
+        let output: Vec<f32> = RationalResampler::from(input, input_rate, OUTPUT_RATE).collect();
+        let settled = &output[output.len() / 4..output.len() * 3 / 4];
+        let rms = (settled.iter().map(|x| x * x).sum::<f32>() / settled.len() as f32).sqrt();
+        (output.len(), rms * 2.0f32.sqrt())
+    }
+
+    #[test]
+    fn output_length_and_passband_gain() {
+        for input_rate in [11025, 20800, 44100, 62500] {
+            let (length, amplitude) = resample_tone(input_rate);
+            let expected = OUTPUT_RATE as usize;
+            assert!(
+                length.abs_diff(expected) <= 1,
+                "{}Hz gave {} samples",
+                input_rate,
+                length
+            );
+            assert!(
+                (amplitude - 1.0).abs() < 0.01,
+                "{}Hz gave amplitude {}",
+                input_rate,
+                amplitude
+            );
         }
-        result /= self.factor as f32;
+    }
 
-        return Some(result);
+    #[test]
+    fn exact_ratios() {
+        assert_eq!(resampling_ratio(11025, OUTPUT_RATE), (832, 2205));
+        assert_eq!(resampling_ratio(20800, OUTPUT_RATE), (1, 5));
+        assert_eq!(resampling_ratio(44100, OUTPUT_RATE), (208, 2205));
+        assert_eq!(resampling_ratio(62500, OUTPUT_RATE), (208, 3125));
+        assert_eq!(resampled_rate(44100, OUTPUT_RATE), OUTPUT_RATE as f64);
+    }
+
+    #[test]
+    fn approximates_large_ratios() {
+        for input_rate in [44101, 250001, 2_048_000] {
+            let (interpolation, decimation) = resampling_ratio(input_rate, OUTPUT_RATE);
+            assert!(interpolation.max(decimation) <= MAX_RATE_FACTOR);
+            let rate = resampled_rate(input_rate, OUTPUT_RATE);
+            assert!(
+                (rate / OUTPUT_RATE as f64 - 1.0).abs() < 1e-4,
+                "{}Hz resampled to {}Hz",
+                input_rate,
+                rate
+            );
+        }
     }
 }
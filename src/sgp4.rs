@@ -0,0 +1,599 @@
+//! Two line element sets and the SGP4 orbit propagator.
+//!
+//! This follows the revised SGP4 implementation by Vallado et al.,
+//! "Revisiting Spacetrack Report #3" (AIAA 2006-6753), using the WGS-72 constants.
+//! Only the near earth part is implemented,
+//! deep space orbits with periods above 225 minutes are rejected.
+//! The weather satellites transmitting APT are all in low earth orbits.
+
+use std::f64::consts::PI;
+use std::fs;
+use std::path::Path;
+
+use errors::DecoderError;
+
+const TWO_PI: f64 = 2.0 * PI;
+const MINUTES_PER_DAY: f64 = 1440.0;
+const SECONDS_PER_DAY: f64 = 86400.0;
+
+// WGS-72 constants, as used for generating the element sets
+const EARTH_RADIUS: f64 = 6378.135;
+const MU: f64 = 398600.8;
+const J2: f64 = 0.001082616;
+const J3: f64 = -0.00000253881;
+const J4: f64 = -0.00000165597;
+
+// Orbits with longer periods need the deep space perturbations.
+const MAX_PERIOD_MINUTES: f64 = 225.0;
+
+/// Orbital elements of a satellite, parsed from a two line element set.
+#[derive(Clone, Debug)]
+pub struct Tle {
+    /// Name from the line in front of the elements, empty for plain two line sets
+    pub name: String,
+    /// NORAD catalog number
+    pub catalog_number: u32,
+    /// Epoch of the elements as unix time in seconds
+    pub epoch: f64,
+    /// Drag term in 1 / earth radii
+    pub bstar: f64,
+    /// Inclination in radians
+    pub inclination: f64,
+    /// Right ascension of the ascending node in radians
+    pub right_ascension: f64,
+    /// Eccentricity
+    pub eccentricity: f64,
+    /// Argument of perigee in radians
+    pub argument_of_perigee: f64,
+    /// Mean anomaly in radians
+    pub mean_anomaly: f64,
+    /// Mean motion in revolutions per day
+    pub mean_motion: f64,
+}
+
+impl Tle {
+    /// Parses the two lines of an element set, `name` is the optional title line.
+    pub fn parse(name: &str, line1: &str, line2: &str) -> Result<Tle, DecoderError> {
+        if !line1.starts_with('1') || !line2.starts_with('2') || line1.len() < 62 {
+            return Err(DecoderError::InvalidTLE(format!(
+                "Expected lines 1 and 2 of an element set for {}",
+                name
+            )));
+        }
+        if line2.len() < 63 {
+            return Err(DecoderError::InvalidTLE(format!(
+                "Line 2 of {} is too short",
+                name
+            )));
+        }
+
+        let epoch_year = field(line1, 18, 20)? as i64;
+        let epoch_day = field(line1, 20, 32)?;
+        // Two digit years from 57 on are in the 20th century
+        let year = if epoch_year < 57 {
+            2000 + epoch_year
+        } else {
+            1900 + epoch_year
+        };
+        let epoch = days_from_civil(year, 1, 1) as f64 * SECONDS_PER_DAY
+            + (epoch_day - 1.0) * SECONDS_PER_DAY;
+
+        Ok(Tle {
+            name: name.trim().to_string(),
+            catalog_number: field(line1, 2, 7)? as u32,
+            epoch,
+            bstar: exponent_field(line1, 53, 61)?,
+            inclination: field(line2, 8, 16)?.to_radians(),
+            right_ascension: field(line2, 17, 25)?.to_radians(),
+            eccentricity: field(&format!("0.{}", &line2[26..33]), 0, 9)?,
+            argument_of_perigee: field(line2, 34, 42)?.to_radians(),
+            mean_anomaly: field(line2, 43, 51)?.to_radians(),
+            mean_motion: field(line2, 52, 63)?,
+        })
+    }
+
+    /// Reads all element sets from a file in two or three line format.
+    pub fn from_file(path: &Path) -> Result<Vec<Tle>, DecoderError> {
+        let content = fs::read_to_string(path).map_err(DecoderError::InputIOError)?;
+        let lines: Vec<&str> = content
+            .lines()
+            .map(|line| line.trim_end())
+            .filter(|line| !line.is_empty())
+            .collect();
+
+        let mut tles = Vec::new();
+        let mut i = 0;
+        while i < lines.len() {
+            if lines[i].starts_with("1 ") && i + 1 < lines.len() {
+                tles.push(Tle::parse("", lines[i], lines[i + 1])?);
+                i += 2;
+            } else if i + 2 < lines.len() {
+                tles.push(Tle::parse(lines[i], lines[i + 1], lines[i + 2])?);
+                i += 3;
+            } else {
+                return Err(DecoderError::InvalidTLE(format!(
+                    "Incomplete element set {}",
+                    lines[i]
+                )));
+            }
+        }
+
+        Ok(tles)
+    }
+
+    /// Returns true if the name matches `name`, ignoring case, spaces and dashes.
+    pub fn matches(&self, name: &str) -> bool {
+        let normalize = |s: &str| s.to_uppercase().replace(['-', ' ', '_'], "");
+        normalize(&self.name).starts_with(&normalize(name))
+    }
+}
+
+fn field(line: &str, start: usize, end: usize) -> Result<f64, DecoderError> {
+    let text = line.get(start..end.min(line.len())).unwrap_or("").trim();
+    text.parse()
+        .map_err(|_| DecoderError::InvalidTLE(format!("Invalid number '{}'", text)))
+}
+
+// Fields like " 28098-4" with an implied decimal point, meaning 0.28098e-4.
+fn exponent_field(line: &str, start: usize, end: usize) -> Result<f64, DecoderError> {
+    let text = line.get(start..end).unwrap_or("").trim();
+    if text.len() < 2 {
+        return Err(DecoderError::InvalidTLE(format!(
+            "Invalid number '{}'",
+            text
+        )));
+    }
+    let (mantissa, exponent) = text.split_at(text.len() - 2);
+    let (sign, digits) = match mantissa.strip_prefix('-') {
+        Some(digits) => (-1.0, digits),
+        None => (1.0, mantissa.trim_start_matches('+')),
+    };
+    let mantissa: f64 = format!("0.{}", digits.trim())
+        .parse()
+        .map_err(|_| DecoderError::InvalidTLE(format!("Invalid number '{}'", text)))?;
+    let exponent: i32 = exponent
+        .parse()
+        .map_err(|_| DecoderError::InvalidTLE(format!("Invalid number '{}'", text)))?;
+
+    Ok(sign * mantissa * 10f64.powi(exponent))
+}
+
+/// Days since 1970-01-01 of a date in the proleptic Gregorian calendar.
+pub fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = if year >= 0 { year } else { year - 399 } / 400;
+    let year_of_era = year - era * 400;
+    let month_index = (month + 9) % 12;
+    let day_of_year = (153 * month_index + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// Position and velocity of a satellite in the TEME frame.
+#[derive(Clone, Copy, Debug)]
+pub struct StateVector {
+    /// Position in km
+    pub position: [f64; 3],
+    /// Velocity in km/s
+    pub velocity: [f64; 3],
+}
+
+/// SGP4 propagator initialised from an element set.
+#[derive(Clone, Debug)]
+pub struct Sgp4 {
+    /// Element set the propagator was initialised with
+    pub tle: Tle,
+    simple: bool,
+    mean_motion: f64,
+    aycof: f64,
+    con41: f64,
+    cc1: f64,
+    cc4: f64,
+    cc5: f64,
+    d2: f64,
+    d3: f64,
+    d4: f64,
+    delmo: f64,
+    eta: f64,
+    argpdot: f64,
+    omgcof: f64,
+    sinmao: f64,
+    t2cof: f64,
+    t3cof: f64,
+    t4cof: f64,
+    t5cof: f64,
+    x1mth2: f64,
+    x7thm1: f64,
+    mdot: f64,
+    nodedot: f64,
+    xlcof: f64,
+    xmcof: f64,
+    nodecf: f64,
+}
+
+impl Sgp4 {
+    pub fn new(tle: &Tle) -> Result<Sgp4, DecoderError> {
+        let xke = 60.0 / (EARTH_RADIUS * EARTH_RADIUS * EARTH_RADIUS / MU).sqrt();
+        let j3oj2 = J3 / J2;
+        let x2o3 = 2.0 / 3.0;
+
+        let ecco = tle.eccentricity;
+        let inclo = tle.inclination;
+        let argpo = tle.argument_of_perigee;
+        let mo = tle.mean_anomaly;
+        let bstar = tle.bstar;
+        let no_kozai = tle.mean_motion * TWO_PI / MINUTES_PER_DAY;
+
+        if TWO_PI / no_kozai >= MAX_PERIOD_MINUTES {
+            return Err(DecoderError::InvalidTLE(format!(
+                "{} is in a deep space orbit, which is not supported",
+                tle.name
+            )));
+        }
+
+        // Recover the original mean motion and semi major axis from the Kozai mean motion
+        let eccsq = ecco * ecco;
+        let omeosq = 1.0 - eccsq;
+        let rteosq = omeosq.sqrt();
+        let cosio = inclo.cos();
+        let cosio2 = cosio * cosio;
+
+        let ak = (xke / no_kozai).powf(x2o3);
+        let d1 = 0.75 * J2 * (3.0 * cosio2 - 1.0) / (rteosq * omeosq);
+        let del = d1 / (ak * ak);
+        let adel = ak * (1.0 - del * del - del * (1.0 / 3.0 + 134.0 * del * del / 81.0));
+        let del = d1 / (adel * adel);
+        let no = no_kozai / (1.0 + del);
+
+        let ao = (xke / no).powf(x2o3);
+        let sinio = inclo.sin();
+        let po = ao * omeosq;
+        let con42 = 1.0 - 5.0 * cosio2;
+        let con41 = -con42 - cosio2 - cosio2;
+        let posq = po * po;
+        let rp = ao * (1.0 - ecco);
+
+        if rp < 1.0 {
+            return Err(DecoderError::InvalidTLE(format!(
+                "The perigee of {} is below the surface",
+                tle.name
+            )));
+        }
+
+        let simple = rp < 220.0 / EARTH_RADIUS + 1.0;
+
+        // Atmospheric density parameters, adjusted for low perigees
+        let mut sfour = 78.0 / EARTH_RADIUS + 1.0;
+        let mut qzms24 = ((120.0 - 78.0) / EARTH_RADIUS).powi(4);
+        let perige = (rp - 1.0) * EARTH_RADIUS;
+        if perige < 156.0 {
+            sfour = if perige < 98.0 { 20.0 } else { perige - 78.0 };
+            qzms24 = ((120.0 - sfour) / EARTH_RADIUS).powi(4);
+            sfour = sfour / EARTH_RADIUS + 1.0;
+        }
+
+        let pinvsq = 1.0 / posq;
+        let tsi = 1.0 / (ao - sfour);
+        let eta = ao * ecco * tsi;
+        let etasq = eta * eta;
+        let eeta = ecco * eta;
+        let psisq = (1.0 - etasq).abs();
+        let coef = qzms24 * tsi.powi(4);
+        let coef1 = coef / psisq.powf(3.5);
+        let cc2 = coef1
+            * no
+            * (ao * (1.0 + 1.5 * etasq + eeta * (4.0 + etasq))
+                + 0.375 * J2 * tsi / psisq * con41 * (8.0 + 3.0 * etasq * (8.0 + etasq)));
+        let cc1 = bstar * cc2;
+        let cc3 = if ecco > 1.0e-4 {
+            -2.0 * coef * tsi * j3oj2 * no * sinio / ecco
+        } else {
+            0.0
+        };
+        let x1mth2 = 1.0 - cosio2;
+        let cc4 = 2.0
+            * no
+            * coef1
+            * ao
+            * omeosq
+            * (eta * (2.0 + 0.5 * etasq) + ecco * (0.5 + 2.0 * etasq)
+                - J2 * tsi / (ao * psisq)
+                    * (-3.0 * con41 * (1.0 - 2.0 * eeta + etasq * (1.5 - 0.5 * eeta))
+                        + 0.75
+                            * x1mth2
+                            * (2.0 * etasq - eeta * (1.0 + etasq))
+                            * (2.0 * argpo).cos()));
+        let cc5 = 2.0 * coef1 * ao * omeosq * (1.0 + 2.75 * (etasq + eeta) + eeta * etasq);
+
+        let cosio4 = cosio2 * cosio2;
+        let temp1 = 1.5 * J2 * pinvsq * no;
+        let temp2 = 0.5 * temp1 * J2 * pinvsq;
+        let temp3 = -0.46875 * J4 * pinvsq * pinvsq * no;
+        let mdot = no
+            + 0.5 * temp1 * rteosq * con41
+            + 0.0625 * temp2 * rteosq * (13.0 - 78.0 * cosio2 + 137.0 * cosio4);
+        let argpdot = -0.5 * temp1 * con42
+            + 0.0625 * temp2 * (7.0 - 114.0 * cosio2 + 395.0 * cosio4)
+            + temp3 * (3.0 - 36.0 * cosio2 + 49.0 * cosio4);
+        let xhdot1 = -temp1 * cosio;
+        let nodedot = xhdot1
+            + (0.5 * temp2 * (4.0 - 19.0 * cosio2) + 2.0 * temp3 * (3.0 - 7.0 * cosio2)) * cosio;
+        let omgcof = bstar * cc3 * argpo.cos();
+        let xmcof = if ecco > 1.0e-4 {
+            -x2o3 * coef * bstar / eeta
+        } else {
+            0.0
+        };
+        let nodecf = 3.5 * omeosq * xhdot1 * cc1;
+        let t2cof = 1.5 * cc1;
+        let xlcof = if (cosio + 1.0).abs() > 1.5e-12 {
+            -0.25 * j3oj2 * sinio * (3.0 + 5.0 * cosio) / (1.0 + cosio)
+        } else {
+            -0.25 * j3oj2 * sinio * (3.0 + 5.0 * cosio) / 1.5e-12
+        };
+        let aycof = -0.5 * j3oj2 * sinio;
+        let delmo = (1.0 + eta * mo.cos()).powi(3);
+        let sinmao = mo.sin();
+        let x7thm1 = 7.0 * cosio2 - 1.0;
+
+        let (d2, d3, d4, t3cof, t4cof, t5cof) = if simple {
+            (0.0, 0.0, 0.0, 0.0, 0.0, 0.0)
+        } else {
+            let cc1sq = cc1 * cc1;
+            let d2 = 4.0 * ao * tsi * cc1sq;
+            let temp = d2 * tsi * cc1 / 3.0;
+            let d3 = (17.0 * ao + sfour) * temp;
+            let d4 = 0.5 * temp * ao * tsi * (221.0 * ao + 31.0 * sfour) * cc1;
+            let t3cof = d2 + 2.0 * cc1sq;
+            let t4cof = 0.25 * (3.0 * d3 + cc1 * (12.0 * d2 + 10.0 * cc1sq));
+            let t5cof = 0.2
+                * (3.0 * d4 + 12.0 * cc1 * d3 + 6.0 * d2 * d2 + 15.0 * cc1sq * (2.0 * d2 + cc1sq));
+            (d2, d3, d4, t3cof, t4cof, t5cof)
+        };
+
+        Ok(Sgp4 {
+            tle: tle.clone(),
+            simple,
+            mean_motion: no,
+            aycof,
+            con41,
+            cc1,
+            cc4,
+            cc5,
+            d2,
+            d3,
+            d4,
+            delmo,
+            eta,
+            argpdot,
+            omgcof,
+            sinmao,
+            t2cof,
+            t3cof,
+            t4cof,
+            t5cof,
+            x1mth2,
+            x7thm1,
+            mdot,
+            nodedot,
+            xlcof,
+            xmcof,
+            nodecf,
+        })
+    }
+
+    /// Position and velocity at the unix time `time` in seconds.
+    pub fn propagate_to(&self, time: f64) -> Result<StateVector, DecoderError> {
+        self.propagate((time - self.tle.epoch) / 60.0)
+    }
+
+    /// Position and velocity `minutes` after the epoch of the element set.
+    pub fn propagate(&self, minutes: f64) -> Result<StateVector, DecoderError> {
+        let xke = 60.0 / (EARTH_RADIUS * EARTH_RADIUS * EARTH_RADIUS / MU).sqrt();
+        let tle = &self.tle;
+        let t = minutes;
+
+        // Secular effects of gravity and drag
+        let xmdf = tle.mean_anomaly + self.mdot * t;
+        let argpdf = tle.argument_of_perigee + self.argpdot * t;
+        let nodedf = tle.right_ascension + self.nodedot * t;
+        let mut argpm = argpdf;
+        let mut mm = xmdf;
+        let t2 = t * t;
+        let mut nodem = nodedf + self.nodecf * t2;
+        let mut tempa = 1.0 - self.cc1 * t;
+        let mut tempe = tle.bstar * self.cc4 * t;
+        let mut templ = self.t2cof * t2;
+
+        if !self.simple {
+            let delomg = self.omgcof * t;
+            let delm = self.xmcof * ((1.0 + self.eta * xmdf.cos()).powi(3) - self.delmo);
+            let temp = delomg + delm;
+            mm = xmdf + temp;
+            argpm = argpdf - temp;
+            let t3 = t2 * t;
+            let t4 = t3 * t;
+            tempa = tempa - self.d2 * t2 - self.d3 * t3 - self.d4 * t4;
+            tempe += tle.bstar * self.cc5 * (mm.sin() - self.sinmao);
+            templ += self.t3cof * t3 + t4 * (self.t4cof + t * self.t5cof);
+        }
+
+        let am = (xke / self.mean_motion).powf(2.0 / 3.0) * tempa * tempa;
+        let nm = xke / am.powf(1.5);
+        let mut em = tle.eccentricity - tempe;
+        if !(-0.001..1.0).contains(&em) {
+            return Err(DecoderError::InvalidTLE(format!(
+                "The orbit of {} can not be propagated {:.0} minutes from its epoch",
+                tle.name, minutes
+            )));
+        }
+        if em < 1.0e-6 {
+            em = 1.0e-6;
+        }
+        mm += self.mean_motion * templ;
+        let xlm = mm + argpm + nodem;
+
+        nodem %= TWO_PI;
+        argpm %= TWO_PI;
+        let xlm = xlm % TWO_PI;
+        mm = (xlm - argpm - nodem) % TWO_PI;
+
+        let sinip = tle.inclination.sin();
+        let cosip = tle.inclination.cos();
+
+        // Long period periodics
+        let axnl = em * argpm.cos();
+        let temp = 1.0 / (am * (1.0 - em * em));
+        let aynl = em * argpm.sin() + temp * self.aycof;
+        let xl = mm + argpm + nodem + temp * self.xlcof * axnl;
+
+        // Solve Kepler's equation
+        let u = (xl - nodem) % TWO_PI;
+        let mut eo1 = u;
+        let mut sineo1 = eo1.sin();
+        let mut coseo1 = eo1.cos();
+        for _ in 0..10 {
+            sineo1 = eo1.sin();
+            coseo1 = eo1.cos();
+            let mut step =
+                (u - aynl * coseo1 + axnl * sineo1 - eo1) / (1.0 - coseo1 * axnl - sineo1 * aynl);
+            if step.abs() >= 0.95 {
+                step = 0.95 * step.signum();
+            }
+            eo1 += step;
+            if step.abs() < 1.0e-12 {
+                break;
+            }
+        }
+
+        // Short period preliminary quantities
+        let ecose = axnl * coseo1 + aynl * sineo1;
+        let esine = axnl * sineo1 - aynl * coseo1;
+        let el2 = axnl * axnl + aynl * aynl;
+        let pl = am * (1.0 - el2);
+        if pl < 0.0 {
+            return Err(DecoderError::InvalidTLE(format!(
+                "The orbit of {} can not be propagated {:.0} minutes from its epoch",
+                tle.name, minutes
+            )));
+        }
+
+        let rl = am * (1.0 - ecose);
+        let rdotl = am.sqrt() * esine / rl;
+        let rvdotl = pl.sqrt() / rl;
+        let betal = (1.0 - el2).sqrt();
+        let temp = esine / (1.0 + betal);
+        let sinu = am / rl * (sineo1 - aynl - axnl * temp);
+        let cosu = am / rl * (coseo1 - axnl + aynl * temp);
+        let su = sinu.atan2(cosu);
+        let sin2u = (cosu + cosu) * sinu;
+        let cos2u = 1.0 - 2.0 * sinu * sinu;
+        let temp = 1.0 / pl;
+        let temp1 = 0.5 * J2 * temp;
+        let temp2 = temp1 * temp;
+
+        // Short period periodics
+        let mrt = rl * (1.0 - 1.5 * temp2 * betal * self.con41) + 0.5 * temp1 * self.x1mth2 * cos2u;
+        let su = su - 0.25 * temp2 * self.x7thm1 * sin2u;
+        let xnode = nodem + 1.5 * temp2 * cosip * sin2u;
+        let xinc = tle.inclination + 1.5 * temp2 * cosip * sinip * cos2u;
+        let mvt = rdotl - nm * temp1 * self.x1mth2 * sin2u / xke;
+        let rvdot = rvdotl + nm * temp1 * (self.x1mth2 * cos2u + 1.5 * self.con41) / xke;
+
+        if mrt < 1.0 {
+            return Err(DecoderError::InvalidTLE(format!(
+                "{} has decayed {:.0} minutes from its epoch",
+                tle.name, minutes
+            )));
+        }
+
+        // Orientation vectors
+        let (sinsu, cossu) = su.sin_cos();
+        let (snod, cnod) = xnode.sin_cos();
+        let (sini, cosi) = xinc.sin_cos();
+        let xmx = -snod * cosi;
+        let xmy = cnod * cosi;
+        let ux = xmx * sinsu + cnod * cossu;
+        let uy = xmy * sinsu + snod * cossu;
+        let uz = sini * sinsu;
+        let vx = xmx * cossu - cnod * sinsu;
+        let vy = xmy * cossu - snod * sinsu;
+        let vz = sini * cossu;
+
+        let velocity_scale = EARTH_RADIUS * xke / 60.0;
+        Ok(StateVector {
+            position: [
+                mrt * ux * EARTH_RADIUS,
+                mrt * uy * EARTH_RADIUS,
+                mrt * uz * EARTH_RADIUS,
+            ],
+            velocity: [
+                (mvt * ux + rvdot * vx) * velocity_scale,
+                (mvt * uy + rvdot * vy) * velocity_scale,
+                (mvt * uz + rvdot * vz) * velocity_scale,
+            ],
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Test cases and results from the verification of Vallado et al., "Revisiting Spacetrack Report #3"
+    fn assert_state(state: &StateVector, position: [f64; 3], velocity: [f64; 3]) {
+        for i in 0..3 {
+            assert!(
+                (state.position[i] - position[i]).abs() < 1e-3,
+                "Position {:?}, expected {:?}",
+                state.position,
+                position
+            );
+            assert!(
+                (state.velocity[i] - velocity[i]).abs() < 1e-6,
+                "Velocity {:?}, expected {:?}",
+                state.velocity,
+                velocity
+            );
+        }
+    }
+
+    #[test]
+    fn vanguard() {
+        let tle = Tle::parse(
+            "00005",
+            "1 00005U 58002B   00179.78495062  .00000023  00000-0  28098-4 0  4753",
+            "2 00005  34.2682 348.7242 1859667 331.7664  19.3264 10.82419157413667",
+        )
+        .unwrap();
+        let sgp4 = Sgp4::new(&tle).unwrap();
+
+        assert_state(
+            &sgp4.propagate(0.0).unwrap(),
+            [7022.46529266, -1400.08296755, 0.03995155],
+            [1.893841015, 6.405893759, 4.534807250],
+        );
+        assert_state(
+            &sgp4.propagate(360.0).unwrap(),
+            [-7154.03120202, -3783.17682504, -3536.19412294],
+            [4.741887409, -4.151817765, -2.093935425],
+        );
+    }
+
+    #[test]
+    fn delta_debris() {
+        let tle = Tle::parse(
+            "06251",
+            "1 06251U 62025E   06176.82412014  .00008885  00000-0  12808-3 0  3985",
+            "2 06251  58.0579  54.0425 0030035 139.1568 221.1854 15.56387291  6338",
+        )
+        .unwrap();
+        let sgp4 = Sgp4::new(&tle).unwrap();
+
+        assert_state(
+            &sgp4.propagate(0.0).unwrap(),
+            [3988.31022699, 5498.96657235, 0.90055879],
+            [-3.290032738, 2.357652820, 6.496623475],
+        );
+    }
+}
@@ -0,0 +1,395 @@
+//! Readers for coastlines and borders from ESRI shapefiles and GeoJSON files.
+//!
+//! Only the geometry is read, as polylines of longitude and latitude in degrees.
+//! Polygons are turned into their outlines, points and attributes are ignored.
+
+use std::convert::TryFrom;
+use std::fs;
+use std::path::Path;
+
+use errors::DecoderError;
+
+/// Connected line of longitude, latitude pairs in degrees.
+pub type Polyline = Vec<(f64, f64)>;
+
+/// Reads all lines and polygon outlines from a `.shp` or a GeoJSON file, chosen by the extension.
+pub fn read_shapes(path: &Path) -> Result<Vec<Polyline>, DecoderError> {
+    let data = fs::read(path).map_err(DecoderError::InputIOError)?;
+    let extension = path
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+
+    match extension.as_str() {
+        "shp" => read_shapefile(&data),
+        "json" | "geojson" => {
+            let text = String::from_utf8_lossy(&data);
+            read_geojson(&text)
+        }
+        _ => Err(DecoderError::InvalidShapes(format!(
+            "Unknown file type {}, expected .shp, .json or .geojson",
+            path.display()
+        ))),
+    }
+}
+
+// Shape types with parts made up of points, including their Z and M variants
+const POLYLINE_TYPES: [i32; 6] = [3, 5, 13, 15, 23, 25];
+const SHAPEFILE_HEADER: usize = 100;
+
+/// Reads the polylines and polygons of the main file of an ESRI shapefile.
+pub fn read_shapefile(data: &[u8]) -> Result<Vec<Polyline>, DecoderError> {
+    let invalid = |reason: &str| DecoderError::InvalidShapes(reason.to_string());
+    if data.len() < SHAPEFILE_HEADER || be_i32(data, 0) != Some(9994) {
+        return Err(invalid("Not a shapefile"));
+    }
+
+    let mut lines = Vec::new();
+    let mut pos = SHAPEFILE_HEADER;
+    while pos + 8 <= data.len() {
+        // Lengths are counted in 16 bit words
+        let length = to_count(be_i32(data, pos + 4))
+            .and_then(|length| length.checked_mul(2))
+            .ok_or_else(|| invalid("Invalid record length"))?;
+        let end = (pos + 8)
+            .checked_add(length)
+            .ok_or_else(|| invalid("Invalid record length"))?;
+        let record = data
+            .get(pos + 8..end)
+            .ok_or_else(|| invalid("Truncated record"))?;
+        pos = end;
+
+        let shape_type = le_i32(record, 0).unwrap_or(0);
+        if !POLYLINE_TYPES.contains(&shape_type) {
+            continue;
+        }
+
+        // Shape type and bounding box come first
+        let part_count =
+            to_count(le_i32(record, 36)).ok_or_else(|| invalid("Invalid part count"))?;
+        let point_count =
+            to_count(le_i32(record, 40)).ok_or_else(|| invalid("Invalid point count"))?;
+        let parts_start = 44;
+        let points_start = part_count
+            .checked_mul(4)
+            .and_then(|size| size.checked_add(parts_start))
+            .filter(|start| *start <= record.len())
+            .ok_or_else(|| invalid("Truncated record"))?;
+        let points_end = point_count
+            .checked_mul(16)
+            .and_then(|size| size.checked_add(points_start))
+            .filter(|end| *end <= record.len());
+        if points_end.is_none() {
+            return Err(invalid("Truncated record"));
+        }
+
+        let point = |i: usize| {
+            let offset = points_start + 16 * i;
+            (
+                le_f64(record, offset).unwrap_or(0.0),
+                le_f64(record, offset + 8).unwrap_or(0.0),
+            )
+        };
+        // Part indices that don't fit the points are skipped
+        let part_start = |part: usize| to_count(le_i32(record, parts_start + 4 * part));
+        for part in 0..part_count {
+            let start = part_start(part);
+            let end = if part + 1 < part_count {
+                part_start(part + 1)
+            } else {
+                Some(point_count)
+            };
+            if let (Some(start), Some(end)) = (start, end) {
+                if start < end && end <= point_count {
+                    lines.push((start..end).map(point).collect());
+                }
+            }
+        }
+    }
+
+    Ok(lines)
+}
+
+// Counts and lengths are signed in the file, negative values are invalid.
+fn to_count(value: Option<i32>) -> Option<usize> {
+    value.and_then(|value| usize::try_from(value).ok())
+}
+
+fn be_i32(data: &[u8], offset: usize) -> Option<i32> {
+    let bytes = data.get(offset..offset + 4)?;
+    Some(i32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+fn le_i32(data: &[u8], offset: usize) -> Option<i32> {
+    let bytes = data.get(offset..offset + 4)?;
+    Some(i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+fn le_f64(data: &[u8], offset: usize) -> Option<f64> {
+    let bytes = data.get(offset..offset + 8)?;
+    let mut array = [0; 8];
+    array.copy_from_slice(bytes);
+    Some(f64::from_le_bytes(array))
+}
+
+/// Reads the lines and polygons of a GeoJSON feature collection, feature or geometry.
+pub fn read_geojson(text: &str) -> Result<Vec<Polyline>, DecoderError> {
+    let mut parser = JsonParser {
+        text: text.as_bytes(),
+        pos: 0,
+    };
+    let json = parser.parse()?;
+
+    let mut lines = Vec::new();
+    collect_geometry(&json, &mut lines);
+    Ok(lines)
+}
+
+fn collect_geometry(json: &Json, lines: &mut Vec<Polyline>) {
+    let coordinates = json.get("coordinates");
+    match json.get("type").and_then(Json::as_str) {
+        Some("FeatureCollection") => {
+            for feature in json.get("features").map(Json::items).unwrap_or_default() {
+                collect_geometry(feature, lines);
+            }
+        }
+        Some("Feature") => {
+            if let Some(geometry) = json.get("geometry") {
+                collect_geometry(geometry, lines);
+            }
+        }
+        Some("GeometryCollection") => {
+            for geometry in json.get("geometries").map(Json::items).unwrap_or_default() {
+                collect_geometry(geometry, lines);
+            }
+        }
+        Some("LineString") => lines.extend(coordinates.and_then(polyline)),
+        Some("MultiLineString") | Some("Polygon") => lines.extend(
+            coordinates
+                .map(Json::items)
+                .unwrap_or_default()
+                .into_iter()
+                .filter_map(polyline),
+        ),
+        Some("MultiPolygon") => {
+            for polygon in coordinates.map(Json::items).unwrap_or_default() {
+                lines.extend(polygon.items().into_iter().filter_map(polyline));
+            }
+        }
+        _ => {}
+    }
+}
+
+fn polyline(json: &Json) -> Option<Polyline> {
+    json.items()
+        .into_iter()
+        .map(|position| {
+            let position = position.items();
+            Some((position.first()?.as_f64()?, position.get(1)?.as_f64()?))
+        })
+        .collect()
+}
+
+// Just enough JSON for GeoJSON files.
+enum Json {
+    Null,
+    Bool,
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(members) => members.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    fn items(&self) -> Vec<&Json> {
+        match self {
+            Json::Array(items) => items.iter().collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    fn as_f64(&self) -> Option<f64> {
+        match self {
+            Json::Number(n) => Some(*n),
+            _ => None,
+        }
+    }
+}
+
+struct JsonParser<'a> {
+    text: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> JsonParser<'a> {
+    fn parse(&mut self) -> Result<Json, DecoderError> {
+        self.skip_whitespace();
+        let value = match self.text.get(self.pos) {
+            Some(b'{') => self.parse_object()?,
+            Some(b'[') => self.parse_array()?,
+            Some(b'"') => Json::String(self.parse_string()?),
+            Some(b't') => self.parse_literal("true", Json::Bool)?,
+            Some(b'f') => self.parse_literal("false", Json::Bool)?,
+            Some(b'n') => self.parse_literal("null", Json::Null)?,
+            Some(_) => self.parse_number()?,
+            None => return Err(self.error("Unexpected end of file")),
+        };
+        self.skip_whitespace();
+        Ok(value)
+    }
+
+    fn parse_object(&mut self) -> Result<Json, DecoderError> {
+        let mut members = Vec::new();
+        self.pos += 1;
+        self.skip_whitespace();
+        if self.text.get(self.pos) == Some(&b'}') {
+            self.pos += 1;
+            return Ok(Json::Object(members));
+        }
+
+        loop {
+            self.skip_whitespace();
+            let key = self.parse_string()?;
+            self.skip_whitespace();
+            self.expect(b':')?;
+            members.push((key, self.parse()?));
+            match self.text.get(self.pos) {
+                Some(b',') => self.pos += 1,
+                Some(b'}') => {
+                    self.pos += 1;
+                    return Ok(Json::Object(members));
+                }
+                _ => return Err(self.error("Expected , or }")),
+            }
+        }
+    }
+
+    fn parse_array(&mut self) -> Result<Json, DecoderError> {
+        let mut items = Vec::new();
+        self.pos += 1;
+        self.skip_whitespace();
+        if self.text.get(self.pos) == Some(&b']') {
+            self.pos += 1;
+            return Ok(Json::Array(items));
+        }
+
+        loop {
+            items.push(self.parse()?);
+            match self.text.get(self.pos) {
+                Some(b',') => self.pos += 1,
+                Some(b']') => {
+                    self.pos += 1;
+                    return Ok(Json::Array(items));
+                }
+                _ => return Err(self.error("Expected , or ]")),
+            }
+        }
+    }
+
+    fn parse_string(&mut self) -> Result<String, DecoderError> {
+        self.expect(b'"')?;
+        let mut bytes = Vec::new();
+        loop {
+            match self.text.get(self.pos) {
+                Some(b'"') => break,
+                Some(b'\\') => {
+                    let escaped = self.text.get(self.pos + 1).copied();
+                    match escaped {
+                        Some(b'n') => bytes.push(b'\n'),
+                        Some(b't') => bytes.push(b'\t'),
+                        Some(b'r') => bytes.push(b'\r'),
+                        Some(b'b') => bytes.push(0x08),
+                        Some(b'f') => bytes.push(0x0c),
+                        Some(b'u') => {
+                            let code = self
+                                .text
+                                .get(self.pos + 2..self.pos + 6)
+                                .and_then(|hex| std::str::from_utf8(hex).ok())
+                                .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                                .ok_or_else(|| self.error("Invalid escape sequence"))?;
+                            // Surrogate pairs are not needed for names, replace them
+                            let c = char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER);
+                            bytes.extend_from_slice(c.to_string().as_bytes());
+                            self.pos += 4;
+                        }
+                        Some(c) => bytes.push(c),
+                        None => return Err(self.error("Unexpected end of file")),
+                    }
+                    self.pos += 2;
+                }
+                Some(c) => {
+                    bytes.push(*c);
+                    self.pos += 1;
+                }
+                None => return Err(self.error("Unterminated string")),
+            }
+        }
+        self.pos += 1;
+        Ok(String::from_utf8_lossy(&bytes).to_string())
+    }
+
+    fn parse_number(&mut self) -> Result<Json, DecoderError> {
+        let start = self.pos;
+        while let Some(c) = self.text.get(self.pos) {
+            if c.is_ascii_digit() || b"+-.eE".contains(c) {
+                self.pos += 1;
+            } else {
+                break;
+            }
+        }
+        std::str::from_utf8(&self.text[start..self.pos])
+            .ok()
+            .and_then(|number| number.parse().ok())
+            .map(Json::Number)
+            .ok_or_else(|| self.error("Invalid number"))
+    }
+
+    fn parse_literal(&mut self, literal: &str, value: Json) -> Result<Json, DecoderError> {
+        if self.text[self.pos..].starts_with(literal.as_bytes()) {
+            self.pos += literal.len();
+            Ok(value)
+        } else {
+            Err(self.error("Invalid literal"))
+        }
+    }
+
+    fn expect(&mut self, c: u8) -> Result<(), DecoderError> {
+        if self.text.get(self.pos) == Some(&c) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(self.error(&format!("Expected {}", c as char)))
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self
+            .text
+            .get(self.pos)
+            .is_some_and(|c| c.is_ascii_whitespace())
+        {
+            self.pos += 1;
+        }
+    }
+
+    fn error(&self, reason: &str) -> DecoderError {
+        DecoderError::InvalidShapes(format!(
+            "{} at byte {} of the GeoJSON file",
+            reason, self.pos
+        ))
+    }
+}
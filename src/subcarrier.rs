@@ -0,0 +1,115 @@
+//! Estimation of the actual subcarrier frequency.
+//!
+//! Mistuned receivers and sample clock errors move the subcarrier away from 2400Hz.
+//! The spectrum of overlapping windows is searched for the carrier peak close to 2400Hz,
+//! the peak is interpolated between the FFT bins for a resolution well below the bin width.
+
+use std::sync::Arc;
+
+use num_complex::Complex;
+use rustfft::{Fft, FftPlanner};
+
+use amdemod::SUBCARRIER_FREQUENCY;
+
+// Length of the analysed windows, consecutive windows overlap by half.
+const WINDOW_DURATION: f32 = 0.5;
+// The carrier is searched for this far from its nominal frequency.
+const SEARCH_RANGE: f32 = 400.0;
+// The peak has to exceed the mean power in the search range by this factor.
+const MIN_PEAK_RATIO: f32 = 10.0;
+
+/// Subcarrier frequency offset measured at a point in time.
+#[derive(Clone, Copy, Debug)]
+pub struct SubcarrierOffset {
+    /// Center of the analysed window in seconds since the start of the recording
+    pub time: f32,
+    /// Difference between the measured and the nominal subcarrier frequency in Hz
+    pub offset: f32,
+}
+
+/// Estimates the subcarrier frequency from the spectrum of the incoming samples.
+pub struct SubcarrierEstimator {
+    sample_rate: u32,
+    fft: Arc<dyn Fft<f32>>,
+    window: Vec<f32>,
+    buffer: Vec<f32>,
+    sample_count: u64,
+}
+
+impl SubcarrierEstimator {
+    pub fn new(sample_rate: u32) -> SubcarrierEstimator {
+        let size = ((sample_rate as f32 * WINDOW_DURATION) as usize).next_power_of_two();
+        let window = (0..size)
+            .map(|i| 0.5 - 0.5 * (2.0 * std::f32::consts::PI * i as f32 / size as f32).cos())
+            .collect();
+
+        SubcarrierEstimator {
+            sample_rate,
+            fft: FftPlanner::new().plan_fft_forward(size),
+            window,
+            buffer: Vec::with_capacity(size),
+            sample_count: 0,
+        }
+    }
+
+    /// Adds the next sample, returns a new estimate whenever a window is complete.
+    ///
+    /// Windows without a clear carrier peak, e.g. before the satellite rises, yield no estimate.
+    pub fn push(&mut self, sample: f32) -> Option<SubcarrierOffset> {
+        self.buffer.push(sample);
+        self.sample_count += 1;
+
+        let size = self.window.len();
+        if self.buffer.len() < size {
+            return None;
+        }
+
+        let estimate = self.estimate();
+        self.buffer.drain(..size / 2);
+        estimate
+    }
+
+    fn estimate(&self) -> Option<SubcarrierOffset> {
+        let size = self.window.len();
+        let mut spectrum: Vec<Complex<f32>> = self
+            .buffer
+            .iter()
+            .zip(self.window.iter())
+            .map(|(x, w)| Complex::new(x * w, 0.0))
+            .collect();
+        self.fft.process(&mut spectrum);
+
+        let bin_width = self.sample_rate as f32 / size as f32;
+        let first = ((SUBCARRIER_FREQUENCY - SEARCH_RANGE) / bin_width).floor() as usize;
+        let last = ((SUBCARRIER_FREQUENCY + SEARCH_RANGE) / bin_width).ceil() as usize;
+
+        let power: Vec<f32> = spectrum[first..=last]
+            .iter()
+            .map(|x| x.norm_sqr())
+            .collect();
+        let mean = power.iter().sum::<f32>() / power.len() as f32;
+        let (peak, peak_power) =
+            power.iter().enumerate().fold(
+                (0, 0.0),
+                |best, (i, p)| if *p > best.1 { (i, *p) } else { best },
+            );
+
+        if peak == 0 || peak == power.len() - 1 || peak_power < MIN_PEAK_RATIO * mean {
+            return None;
+        }
+
+        // Parabolic interpolation of the log magnitude around the peak
+        let alpha = power[peak - 1].max(f32::MIN_POSITIVE).ln();
+        let beta = power[peak].ln();
+        let gamma = power[peak + 1].max(f32::MIN_POSITIVE).ln();
+        let delta = 0.5 * (alpha - gamma) / (alpha - 2.0 * beta + gamma);
+
+        let frequency = (first as f32 + peak as f32 + delta) * bin_width;
+        let center = self.sample_count - size as u64 / 2;
+
+        Some(SubcarrierOffset {
+            time: center as f32 / self.sample_rate as f32,
+            offset: frequency - SUBCARRIER_FREQUENCY,
+        })
+    }
+}
@@ -0,0 +1,364 @@
+//! Parsing of the telemetry strips next to each image channel.
+//!
+//! The telemetry of each channel is a 45 pixel wide strip made up of 16 wedges,
+//! each wedge is 8 lines high, so a full telemetry frame spans 128 lines.
+//! Wedges 1 - 8 are a grey scale from 1/8 to 8/8 of the full modulation,
+//! wedge 9 is zero modulation, wedges 10 - 15 carry calibration data
+//! and wedge 16 repeats the level of the wedge matching the AVHRR channel in the image.
+
+use std::fmt;
+
+/// Number of wedges in a telemetry frame.
+pub const WEDGES: usize = 16;
+/// Number of lines per wedge.
+pub const LINES_PER_WEDGE: usize = 8;
+/// Number of lines per telemetry frame.
+pub const LINES_PER_FRAME: usize = WEDGES * LINES_PER_WEDGE;
+
+/// First column of the telemetry strip of channel A.
+pub const TELEMETRY_A_START: usize = 995;
+/// First column of the telemetry strip of channel B.
+pub const TELEMETRY_B_START: usize = 2035;
+/// Width of the telemetry strips.
+pub const TELEMETRY_WIDTH: usize = 45;
+
+// Columns at the edges of the strip are blurred by the neighbouring image data.
+const TELEMETRY_MARGIN: usize = 5;
+
+/// Modulation of wedges 1 - 9, relative to the full modulation.
+/// Used to find the start of the frames, since they are the same in every frame.
+pub const FRAME_MARKER: [f32; 9] = [
+    1.0 / 8.0,
+    2.0 / 8.0,
+    3.0 / 8.0,
+    4.0 / 8.0,
+    5.0 / 8.0,
+    6.0 / 8.0,
+    7.0 / 8.0,
+    1.0,
+    0.0,
+];
+
+// Minimal correlation between the measured wedges and the frame marker to trust the telemetry.
+const MIN_CORRELATION: f32 = 0.8;
+
+/// AVHRR sensor channels that can be transmitted in the image channels.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AVHRRChannel {
+    /// Visible, 0.58 - 0.68µm
+    Ch1,
+    /// Near infrared, 0.725 - 1.0µm
+    Ch2,
+    /// Near infrared, 1.58 - 1.64µm
+    Ch3A,
+    /// Mid infrared, 3.55 - 3.93µm
+    Ch3B,
+    /// Thermal infrared, 10.3 - 11.3µm
+    Ch4,
+    /// Thermal infrared, 11.5 - 12.5µm
+    Ch5,
+}
+
+impl AVHRRChannel {
+    // Wedge 16 repeats the level of wedge 1 - 6, depending on the channel.
+    fn from_wedge(wedge: usize) -> Option<AVHRRChannel> {
+        match wedge {
+            1 => Some(AVHRRChannel::Ch1),
+            2 => Some(AVHRRChannel::Ch2),
+            3 => Some(AVHRRChannel::Ch3A),
+            4 => Some(AVHRRChannel::Ch4),
+            5 => Some(AVHRRChannel::Ch5),
+            6 => Some(AVHRRChannel::Ch3B),
+            _ => None,
+        }
+    }
+
+    /// Returns true for the channels measuring emitted thermal radiation instead of reflected sunlight.
+    pub fn is_thermal(&self) -> bool {
+        matches!(
+            self,
+            AVHRRChannel::Ch3B | AVHRRChannel::Ch4 | AVHRRChannel::Ch5
+        )
+    }
+}
+
+impl fmt::Display for AVHRRChannel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            AVHRRChannel::Ch1 => "1",
+            AVHRRChannel::Ch2 => "2",
+            AVHRRChannel::Ch3A => "3A",
+            AVHRRChannel::Ch3B => "3B",
+            AVHRRChannel::Ch4 => "4",
+            AVHRRChannel::Ch5 => "5",
+        };
+        write!(f, "AVHRR {}", name)
+    }
+}
+
+/// Telemetry of a single frame of 128 lines.
+#[derive(Clone, Debug)]
+pub struct TelemetryFrame {
+    /// First line of the frame in the image
+    pub start_line: usize,
+    /// Averaged levels of the 16 wedges of channel A
+    pub wedges_a: [f32; WEDGES],
+    /// Averaged levels of the 16 wedges of channel B
+    pub wedges_b: [f32; WEDGES],
+    /// AVHRR channel transmitted in channel A
+    pub channel_a: Option<AVHRRChannel>,
+    /// AVHRR channel transmitted in channel B
+    pub channel_b: Option<AVHRRChannel>,
+}
+
+/// Telemetry of a complete pass.
+#[derive(Clone, Debug, Default)]
+pub struct Telemetry {
+    /// All complete frames found in the image
+    pub frames: Vec<TelemetryFrame>,
+    /// AVHRR channel transmitted in channel A in most frames
+    pub channel_a: Option<AVHRRChannel>,
+    /// AVHRR channel transmitted in channel B in most frames
+    pub channel_b: Option<AVHRRChannel>,
+}
+
+impl Telemetry {
+    /// Extracts the telemetry from the unscaled pixel values of each line.
+    ///
+    /// Returns an empty telemetry if no frame start could be found.
+    pub fn from_lines(lines: &[Vec<f32>]) -> Telemetry {
+        let strip_a: Vec<f32> = lines
+            .iter()
+            .map(|line| strip_level(line, TELEMETRY_A_START))
+            .collect();
+        let strip_b: Vec<f32> = lines
+            .iter()
+            .map(|line| strip_level(line, TELEMETRY_B_START))
+            .collect();
+
+        // Wedges 1 - 9 are identical for both channels
+        let combined: Vec<f32> = strip_a
+            .iter()
+            .zip(strip_b.iter())
+            .map(|(a, b)| (a + b) / 2.0)
+            .collect();
+
+        let offset = match find_frame_offset(&combined) {
+            Some(offset) => offset,
+            None => return Telemetry::default(),
+        };
+
+        let frames: Vec<TelemetryFrame> = (offset..)
+            .step_by(LINES_PER_FRAME)
+            .take_while(|start| start + LINES_PER_FRAME <= lines.len())
+            .map(|start| {
+                let wedges_a = wedge_levels(&strip_a[start..start + LINES_PER_FRAME]);
+                let wedges_b = wedge_levels(&strip_b[start..start + LINES_PER_FRAME]);
+                TelemetryFrame {
+                    start_line: start,
+                    wedges_a,
+                    wedges_b,
+                    channel_a: identify_channel(&wedges_a),
+                    channel_b: identify_channel(&wedges_b),
+                }
+            })
+            .collect();
+
+        let channel_a = majority(frames.iter().filter_map(|frame| frame.channel_a));
+        let channel_b = majority(frames.iter().filter_map(|frame| frame.channel_b));
+
+        Telemetry {
+            frames,
+            channel_a,
+            channel_b,
+        }
+    }
+}
+
+fn strip_level(line: &[f32], start: usize) -> f32 {
+    let strip = &line[start + TELEMETRY_MARGIN..start + TELEMETRY_WIDTH - TELEMETRY_MARGIN];
+    strip.iter().sum::<f32>() / strip.len() as f32
+}
+
+// Finds the line offset where the grey scale of wedges 1 - 9 matches best over all frames.
+fn find_frame_offset(levels: &[f32]) -> Option<usize> {
+    let marker_lines = FRAME_MARKER.len() * LINES_PER_WEDGE;
+    if levels.len() < LINES_PER_FRAME + marker_lines {
+        return None;
+    }
+
+    let mut best_offset = 0;
+    let mut best_correlation = f32::MIN;
+
+    for offset in 0..LINES_PER_FRAME {
+        let mut measured = Vec::new();
+        let mut expected = Vec::new();
+        for start in (offset..levels.len() - marker_lines).step_by(LINES_PER_FRAME) {
+            for (i, level) in levels[start..start + marker_lines].iter().enumerate() {
+                measured.push(*level);
+                expected.push(FRAME_MARKER[i / LINES_PER_WEDGE]);
+            }
+        }
+
+        let correlation = correlation(&measured, &expected);
+        if correlation > best_correlation {
+            best_correlation = correlation;
+            best_offset = offset;
+        }
+    }
+
+    if best_correlation >= MIN_CORRELATION {
+        Some(best_offset)
+    } else {
+        None
+    }
+}
+
+fn correlation(a: &[f32], b: &[f32]) -> f32 {
+    let n = a.len() as f32;
+    let mean_a = a.iter().sum::<f32>() / n;
+    let mean_b = b.iter().sum::<f32>() / n;
+
+    let mut cov = 0.0;
+    let mut var_a = 0.0;
+    let mut var_b = 0.0;
+    for (x, y) in a.iter().zip(b.iter()) {
+        cov += (x - mean_a) * (y - mean_b);
+        var_a += (x - mean_a) * (x - mean_a);
+        var_b += (y - mean_b) * (y - mean_b);
+    }
+
+    if var_a == 0.0 || var_b == 0.0 {
+        return 0.0;
+    }
+    cov / (var_a * var_b).sqrt()
+}
+
+// Averages each wedge, leaving out the first and last line, which may be blurred.
+fn wedge_levels(levels: &[f32]) -> [f32; WEDGES] {
+    let mut wedges = [0.0; WEDGES];
+    for (i, wedge) in wedges.iter_mut().enumerate() {
+        let start = i * LINES_PER_WEDGE + 1;
+        let end = (i + 1) * LINES_PER_WEDGE - 1;
+        *wedge = levels[start..end].iter().sum::<f32>() / (end - start) as f32;
+    }
+    wedges
+}
+
+fn identify_channel(wedges: &[f32; WEDGES]) -> Option<AVHRRChannel> {
+    let id_level = wedges[WEDGES - 1];
+    let (wedge, _) = wedges[0..6]
+        .iter()
+        .enumerate()
+        .map(|(i, level)| (i + 1, (level - id_level).abs()))
+        .fold(
+            (0, f32::MAX),
+            |best, cur| if cur.1 < best.1 { cur } else { best },
+        );
+
+    AVHRRChannel::from_wedge(wedge)
+}
+
+fn majority<I>(channels: I) -> Option<AVHRRChannel>
+where
+    I: Iterator<Item = AVHRRChannel>,
+{
+    let mut counts: Vec<(AVHRRChannel, usize)> = Vec::new();
+    for channel in channels {
+        match counts.iter_mut().find(|(c, _)| *c == channel) {
+            Some((_, count)) => *count += 1,
+            None => counts.push((channel, 1)),
+        }
+    }
+
+    counts
+        .into_iter()
+        .max_by_key(|(_, count)| *count)
+        .map(|(channel, _)| channel)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use decoder::PIXELS_PER_LINE;
+
+    // Level of wedge 1 - 16 of a frame, wedge 16 repeats `id_wedge`.
+    fn wedge_level(wedge: usize, id_wedge: usize) -> f32 {
+        match wedge {
+            1..=9 => FRAME_MARKER[wedge - 1],
+            16 => wedge_level(id_wedge, id_wedge),
+            _ => 0.3,
+        }
+    }
+
+    // Telemetry column with frames starting at `offset`, with a bit of alternating noise.
+    fn column(lines: usize, offset: usize, id_wedge: usize) -> Vec<f32> {
+        (0..lines)
+            .map(|line| {
+                let in_frame = (line + LINES_PER_FRAME - offset) % LINES_PER_FRAME;
+                let noise = if line % 2 == 0 { 0.02 } else { -0.02 };
+                wedge_level(in_frame / LINES_PER_WEDGE + 1, id_wedge) + noise
+            })
+            .collect()
+    }
+
+    #[test]
+    fn finds_frame_offset() {
+        for offset in [0, 37, 127] {
+            let levels = column(4 * LINES_PER_FRAME + 20, offset, 2);
+            assert_eq!(find_frame_offset(&levels), Some(offset));
+        }
+    }
+
+    #[test]
+    fn rejects_missing_telemetry() {
+        assert_eq!(find_frame_offset(&[0.5; 4 * LINES_PER_FRAME]), None);
+        assert_eq!(find_frame_offset(&column(LINES_PER_FRAME, 0, 2)), None);
+    }
+
+    #[test]
+    fn identifies_channel_from_wedge_16() {
+        let expected = [
+            AVHRRChannel::Ch1,
+            AVHRRChannel::Ch2,
+            AVHRRChannel::Ch3A,
+            AVHRRChannel::Ch4,
+            AVHRRChannel::Ch5,
+            AVHRRChannel::Ch3B,
+        ];
+        for (i, channel) in expected.iter().enumerate() {
+            let mut wedges = [0.3; WEDGES];
+            wedges[..FRAME_MARKER.len()].copy_from_slice(&FRAME_MARKER);
+            wedges[WEDGES - 1] = FRAME_MARKER[i] + 0.01;
+            assert_eq!(identify_channel(&wedges), Some(*channel));
+        }
+    }
+
+    #[test]
+    fn extracts_frames_from_lines() {
+        let offset = 50;
+        let count = 3 * LINES_PER_FRAME + offset;
+        let (column_a, column_b) = (column(count, offset, 2), column(count, offset, 4));
+        let lines: Vec<Vec<f32>> = (0..count)
+            .map(|y| {
+                let mut line = vec![0.5; PIXELS_PER_LINE as usize];
+                line[TELEMETRY_A_START..TELEMETRY_A_START + TELEMETRY_WIDTH].fill(column_a[y]);
+                line[TELEMETRY_B_START..TELEMETRY_B_START + TELEMETRY_WIDTH].fill(column_b[y]);
+                line
+            })
+            .collect();
+
+        let telemetry = Telemetry::from_lines(&lines);
+        let starts: Vec<usize> = telemetry.frames.iter().map(|f| f.start_line).collect();
+        assert_eq!(
+            starts,
+            vec![
+                offset,
+                offset + LINES_PER_FRAME,
+                offset + 2 * LINES_PER_FRAME
+            ]
+        );
+        assert_eq!(telemetry.channel_a, Some(AVHRRChannel::Ch2));
+        assert_eq!(telemetry.channel_b, Some(AVHRRChannel::Ch4));
+    }
+}
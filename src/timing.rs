@@ -0,0 +1,252 @@
+use std::collections::VecDeque;
+
+use aptsyncer::SyncedSample;
+
+const LINE_LENGTH: usize = 2080;
+
+// A sync A this close to the predicted line start updates the timing.
+const SEARCH_WINDOW: f64 = 10.0;
+// Gains of the loop filter for the line start and the line period.
+const PHASE_GAIN: f64 = 0.25;
+const PERIOD_GAIN: f64 = 0.02;
+// The line period may not deviate more than this from the nominal period.
+const MAX_DEVIATION: f64 = 0.01;
+// Number of lines without a sync close to the prediction, before jumping to a new sync.
+const RELOCK_LINES: u32 = 4;
+
+/// Recovers the line timing from the sync A markers and resamples each line to 2080 pixels.
+///
+/// The start and period of each line are predicted from the previous lines
+/// and corrected by the sync A markers found close to the prediction.
+/// Every line is then interpolated from its fractional start with the estimated period,
+/// which removes the slant caused by sample clocks deviating from their nominal rate.
+/// Until the first sync A, the samples are passed through unchanged.
+/// After that every line starts with a sync A sample,
+/// its confidence is 0.0 if the line start was predicted without a matching sync.
+pub struct TimingRecovery<'a> {
+    iterator: Box<dyn Iterator<Item = SyncedSample> + 'a>,
+    // Input samples, the first one is at position `offset`
+    buffer: VecDeque<f32>,
+    offset: u64,
+    // Positions and confidences of sync A markers that have not been used yet
+    syncs: VecDeque<(u64, f32)>,
+    start: Option<f64>,
+    period: f64,
+    missed: u32,
+    line: VecDeque<SyncedSample>,
+    exhausted: bool,
+}
+
+impl<'a> TimingRecovery<'a> {
+    pub fn from<I>(iterator: I) -> TimingRecovery<'a>
+    where
+        I: Iterator<Item = SyncedSample> + 'a,
+    {
+        TimingRecovery {
+            iterator: Box::new(iterator),
+            buffer: VecDeque::new(),
+            offset: 0,
+            syncs: VecDeque::new(),
+            start: None,
+            period: LINE_LENGTH as f64,
+            missed: 0,
+            line: VecDeque::with_capacity(LINE_LENGTH),
+            exhausted: false,
+        }
+    }
+
+    /// Deviation of the estimated line period from the nominal period in ppm.
+    ///
+    /// A positive value means the sample clock of the recording runs faster than its nominal rate.
+    /// Returns `None` if no sync was found.
+    pub fn drift_ppm(&self) -> Option<f32> {
+        self.start
+            .map(|_| ((self.period / LINE_LENGTH as f64 - 1.0) * 1e6) as f32)
+    }
+
+    fn buffer_end(&self) -> u64 {
+        self.offset + self.buffer.len() as u64
+    }
+
+    // Reads input samples until the buffer reaches `end` or the input is exhausted.
+    fn fill(&mut self, end: u64) {
+        while !self.exhausted && self.buffer_end() < end {
+            match self.iterator.next() {
+                Some(SyncedSample::SyncA { sample, confidence }) => {
+                    self.syncs.push_back((self.buffer_end(), confidence));
+                    self.buffer.push_back(sample);
+                }
+                Some(SyncedSample::SyncB { sample, .. }) | Some(SyncedSample::Sample(sample)) => {
+                    self.buffer.push_back(sample)
+                }
+                None => self.exhausted = true,
+            }
+        }
+    }
+
+    // Corrects the predicted line start with the closest sync A, returns its confidence.
+    fn update_timing(&mut self, start: f64) -> (f64, f32) {
+        let half_line = self.period / 2.0;
+        while let Some((pos, _)) = self.syncs.front() {
+            if (*pos as f64) < start - half_line {
+                self.syncs.pop_front();
+            } else {
+                break;
+            }
+        }
+
+        let closest = self
+            .syncs
+            .iter()
+            .take_while(|(pos, _)| (*pos as f64) < start + half_line)
+            .map(|(pos, confidence)| (*pos as f64 - start, *confidence))
+            .fold(None, |best: Option<(f64, f32)>, cur| match best {
+                Some(best) if best.0.abs() <= cur.0.abs() => Some(best),
+                _ => Some(cur),
+            });
+
+        match closest {
+            Some((error, confidence)) if error.abs() <= SEARCH_WINDOW => {
+                let nominal = LINE_LENGTH as f64;
+                self.period = (self.period + PERIOD_GAIN * error).clamp(
+                    nominal * (1.0 - MAX_DEVIATION),
+                    nominal * (1.0 + MAX_DEVIATION),
+                );
+                self.missed = 0;
+                (start + PHASE_GAIN * error, confidence)
+            }
+            Some((error, confidence)) if self.missed >= RELOCK_LINES => {
+                self.missed = 0;
+                (start + error, confidence)
+            }
+            _ => {
+                self.missed += 1;
+                (start, 0.0)
+            }
+        }
+    }
+
+    // Interpolates the next line, returns false if there are no samples left.
+    fn next_line(&mut self, start: f64) -> bool {
+        self.fill((start + self.period + SEARCH_WINDOW).ceil() as u64 + 2);
+
+        let (start, confidence) = self.update_timing(start);
+        let step = self.period / LINE_LENGTH as f64;
+
+        for i in 0..LINE_LENGTH {
+            let t = start + i as f64 * step - self.offset as f64;
+            let index = t.floor().max(0.0) as usize;
+            if index + 1 >= self.buffer.len() {
+                break;
+            }
+            let frac = (t - index as f64).clamp(0.0, 1.0) as f32;
+            let sample = self.buffer[index] * (1.0 - frac) + self.buffer[index + 1] * frac;
+
+            self.line.push_back(if i == 0 {
+                SyncedSample::SyncA { sample, confidence }
+            } else {
+                SyncedSample::Sample(sample)
+            });
+        }
+
+        let next_start = start + self.period;
+        self.start = Some(next_start);
+
+        // Keep the samples the next line start may be corrected to
+        let keep_from = ((next_start - SEARCH_WINDOW).floor().max(0.0) as u64).saturating_sub(1);
+        while self.offset < keep_from && !self.buffer.is_empty() {
+            self.buffer.pop_front();
+            self.offset += 1;
+        }
+
+        !self.line.is_empty()
+    }
+}
+
+impl<'a> Iterator for TimingRecovery<'a> {
+    type Item = SyncedSample;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(sample) = self.line.pop_front() {
+            return Some(sample);
+        }
+
+        let start = match self.start {
+            Some(start) => start,
+            None => {
+                // Pass through until the first sync A
+                let pos = self.offset;
+                self.offset += 1;
+                match self.iterator.next()? {
+                    SyncedSample::SyncA { sample, confidence } => {
+                        self.syncs.push_back((pos, confidence));
+                        self.buffer.push_back(sample);
+                        self.offset = pos;
+                        pos as f64
+                    }
+                    other => return Some(other),
+                }
+            }
+        };
+
+        if self.next_line(start) {
+            self.line.pop_front()
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Sync A markers of `lines` lines, sampled with a clock `ppm` faster than nominal.
+    fn line_train(lines: usize, ppm: f64) -> Vec<SyncedSample> {
+        let period = LINE_LENGTH as f64 * (1.0 + ppm * 1e-6);
+        let length = (lines as f64 * period) as usize;
+        let mut syncs = (0..lines).map(|line| (line as f64 * period).round() as usize);
+        let mut next_sync = syncs.next();
+        (0..length)
+            .map(|pos| {
+                if Some(pos) == next_sync {
+                    next_sync = syncs.next();
+                    SyncedSample::SyncA {
+                        sample: 1.0,
+                        confidence: 1.0,
+                    }
+                } else {
+                    SyncedSample::Sample(0.5)
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn measures_drift() {
+        for ppm in [-2000.0, 0.0, 500.0, 3000.0] {
+            let mut timing = TimingRecovery::from(line_train(300, ppm).into_iter());
+            let lines = timing
+                .by_ref()
+                .filter(|sample| matches!(sample, SyncedSample::SyncA { .. }))
+                .count();
+            assert!(lines >= 298, "{} lines at {} ppm", lines, ppm);
+
+            let drift = timing.drift_ppm().expect("No drift measured") as f64;
+            assert!(
+                (drift - ppm).abs() < 20.0,
+                "Measured {} ppm instead of {} ppm",
+                drift,
+                ppm
+            );
+        }
+    }
+
+    #[test]
+    fn no_drift_without_sync() {
+        let samples = (0..3 * LINE_LENGTH).map(|_| SyncedSample::Sample(0.5));
+        let mut timing = TimingRecovery::from(samples);
+        assert_eq!(timing.by_ref().count(), 3 * LINE_LENGTH);
+        assert_eq!(timing.drift_ppm(), None);
+    }
+}
@@ -1,33 +1,79 @@
+use std::path::Path;
 use std::sync::{Arc, Mutex};
 
 use eframe::egui::text_edit::TextEdit;
-use eframe::egui::widgets::{Button, ProgressBar};
+use eframe::egui::widgets::{Button, ProgressBar, Slider};
 use eframe::egui::ColorImage;
 use eframe::egui::Visuals;
 use eframe::egui::{self, Image};
 use eframe::egui::{Color32, RichText};
 
-use decoder;
-use errors::DecoderError;
+use apt_decoder::amdemod::AMDemodulatorKind;
+use apt_decoder::composite::Palette;
+use apt_decoder::decoder::{self, DecoderOptions};
+use apt_decoder::enhance::{Enhancement, Equalization, DEFAULT_CLAHE_CLIP};
+use apt_decoder::metadata::Metadata;
+use apt_decoder::telemetry::Telemetry;
+use apt_decoder::utils::ChannelSelection;
+use apt_decoder::DecoderError;
+use cli::channel_name;
+
+#[derive(PartialEq, Clone, Copy)]
+enum PaletteChoice {
+    None,
+    CommandLine,
+    FalseColor,
+    File,
+}
+
+impl PaletteChoice {
+    fn label(&self) -> &'static str {
+        match self {
+            PaletteChoice::None => "None",
+            PaletteChoice::CommandLine => "From command line",
+            PaletteChoice::FalseColor => "False colour",
+            PaletteChoice::File => "Palette file",
+        }
+    }
+
+    fn load(&self, path: &str, given: &Option<Palette>) -> Result<Option<Palette>, DecoderError> {
+        match self {
+            PaletteChoice::None => Ok(None),
+            PaletteChoice::CommandLine => Ok(given.clone()),
+            PaletteChoice::FalseColor => Ok(Some(Palette::false_color())),
+            PaletteChoice::File => Palette::from_file(Path::new(path)).map(Some),
+        }
+    }
+}
 
 #[derive(PartialEq)]
 enum DecoderRunState {
-    RUNNING,
-    CANCELED,
-    DONE,
+    Running,
+    Canceled,
+    Done,
 }
 
 struct DecoderJobState {
     update_steps: u32,
     progress: f32,
     texture: Option<egui::TextureHandle>,
+    composite_texture: Option<egui::TextureHandle>,
     run_state: DecoderRunState,
     error: Option<DecoderError>,
+    telemetry: Option<Telemetry>,
+    // Decoded image before the enhancement, to preview other settings
+    unenhanced: Option<Arc<image::GrayImage>>,
+    // Settings currently enhanced in the background for the preview
+    previewing: Option<Enhancement>,
+    // Image with the enhancement shown in the preview and the settings used for it
+    enhanced: Option<(image::GrayImage, Enhancement)>,
+    // Metadata of the output file, including the enhancement it was written with
+    metadata: Option<Metadata>,
 }
 
 impl DecoderJobState {
     fn is_running(&self) -> bool {
-        self.run_state == DecoderRunState::RUNNING
+        self.run_state == DecoderRunState::Running
     }
 }
 
@@ -37,8 +83,14 @@ impl Default for DecoderJobState {
             update_steps: 10,
             progress: 0.0,
             texture: None,
-            run_state: DecoderRunState::DONE,
+            composite_texture: None,
+            run_state: DecoderRunState::Done,
             error: None,
+            telemetry: None,
+            unenhanced: None,
+            previewing: None,
+            enhanced: None,
+            metadata: None,
         }
     }
 }
@@ -46,14 +98,28 @@ impl Default for DecoderJobState {
 pub struct DecoderApp {
     input_path: String,
     output_path: String,
+    options: DecoderOptions,
+    cli_palette: Option<Palette>,
+    palette_choice: PaletteChoice,
+    palette_path: String,
+    show_composite: bool,
     decoding_state: Arc<Mutex<DecoderJobState>>,
 }
 
 impl DecoderApp {
-    pub fn new(input_path: &str, output_path: &str) -> Self {
+    pub fn new(input_path: &str, output_path: &str, options: DecoderOptions) -> Self {
         Self {
             input_path: input_path.to_owned(),
             output_path: output_path.to_owned(),
+            palette_choice: if options.palette.is_some() {
+                PaletteChoice::CommandLine
+            } else {
+                PaletteChoice::None
+            },
+            palette_path: String::new(),
+            cli_palette: options.palette.clone(),
+            show_composite: true,
+            options,
             decoding_state: Arc::new(Mutex::new(DecoderJobState::default())),
         }
     }
@@ -66,6 +132,11 @@ impl eframe::App for DecoderApp {
         let Self {
             input_path,
             output_path,
+            options,
+            cli_palette,
+            palette_choice,
+            palette_path,
+            show_composite,
             decoding_state,
         } = self;
 
@@ -115,25 +186,161 @@ impl eframe::App for DecoderApp {
                         }
                     };
                     ui.end_row();
+
+                    ui.label("WAV Channel:");
+                    ui.add_enabled_ui(!state.is_running(), |ui| {
+                        egui::ComboBox::from_id_salt("wav_channel")
+                            .selected_text(options.wav_channel.to_string())
+                            .show_ui(ui, |ui| {
+                                for channel in [
+                                    ChannelSelection::Mix,
+                                    ChannelSelection::Left,
+                                    ChannelSelection::Right,
+                                    ChannelSelection::IQ,
+                                ] {
+                                    ui.selectable_value(
+                                        &mut options.wav_channel,
+                                        channel,
+                                        channel.to_string(),
+                                    );
+                                }
+                            });
+                    });
+                    ui.end_row();
+
+                    ui.label("Demodulator:");
+                    ui.add_enabled_ui(!state.is_running(), |ui| {
+                        egui::ComboBox::from_id_salt("demodulator")
+                            .selected_text(options.demodulator.to_string())
+                            .show_ui(ui, |ui| {
+                                for demodulator in [
+                                    AMDemodulatorKind::Squaring,
+                                    AMDemodulatorKind::Hilbert,
+                                    AMDemodulatorKind::Synchronous,
+                                    AMDemodulatorKind::Tracking,
+                                ] {
+                                    ui.selectable_value(
+                                        &mut options.demodulator,
+                                        demodulator,
+                                        demodulator.to_string(),
+                                    );
+                                }
+                            });
+                    });
+                    ui.end_row();
+
+                    ui.label("Composite:");
+                    ui.add_enabled_ui(!state.is_running(), |ui| {
+                        egui::ComboBox::from_id_salt("palette_choice")
+                            .selected_text(palette_choice.label())
+                            .show_ui(ui, |ui| {
+                                let mut choices = vec![PaletteChoice::None];
+                                if cli_palette.is_some() {
+                                    choices.push(PaletteChoice::CommandLine);
+                                }
+                                choices.push(PaletteChoice::FalseColor);
+                                choices.push(PaletteChoice::File);
+                                for choice in choices {
+                                    ui.selectable_value(palette_choice, choice, choice.label());
+                                }
+                            });
+                    });
+                    ui.end_row();
+
+                    if *palette_choice == PaletteChoice::File {
+                        ui.label("Palette PNG File:");
+                        ui.add_sized(
+                            [300.0, 20.0],
+                            TextEdit::singleline(palette_path).interactive(!state.is_running()),
+                        );
+                        if ui
+                            .add_enabled(!state.is_running(), Button::new("Open"))
+                            .clicked()
+                        {
+                            if let Some(path) = rfd::FileDialog::new().pick_file() {
+                                *palette_path = path.display().to_string();
+                            }
+                        };
+                        ui.end_row();
+                    }
+
+                    let enhancement = &mut options.enhancement;
+                    ui.label("Equalization:");
+                    ui.horizontal(|ui| {
+                        egui::ComboBox::from_id_salt("equalization")
+                            .selected_text(enhancement.equalization.to_string())
+                            .show_ui(ui, |ui| {
+                                for equalization in [
+                                    Equalization::None,
+                                    Equalization::Histogram,
+                                    Equalization::Clahe {
+                                        clip_limit: DEFAULT_CLAHE_CLIP,
+                                    },
+                                ] {
+                                    let selected =
+                                        std::mem::discriminant(&enhancement.equalization)
+                                            == std::mem::discriminant(&equalization);
+                                    if ui
+                                        .selectable_label(selected, equalization.to_string())
+                                        .clicked()
+                                        && !selected
+                                    {
+                                        enhancement.equalization = equalization;
+                                    }
+                                }
+                            });
+                        if let Equalization::Clahe { clip_limit } = &mut enhancement.equalization {
+                            ui.add(Slider::new(clip_limit, 1.0..=8.0).text("Clip limit"));
+                        }
+                    });
+                    ui.end_row();
+
+                    ui.label("Median filter:");
+                    ui.add(Slider::new(&mut enhancement.median, 0..=3).text("Radius"));
+                    ui.end_row();
+
+                    ui.label("Gamma:");
+                    ui.add(Slider::new(&mut enhancement.gamma, 0.2..=3.0));
+                    ui.end_row();
+
+                    ui.label("Sharpen:");
+                    ui.add(Slider::new(&mut enhancement.sharpen, 0.0..=3.0));
+                    ui.end_row();
                 });
 
                 ui.horizontal(|ui| {
-                    if ui
+                    let decode_clicked = ui
                         .add_enabled(!state.is_running(), Button::new("Decode"))
-                        .clicked()
-                    {
+                        .clicked();
+                    if decode_clicked {
+                        state.error = None;
+                        match palette_choice.load(palette_path, cli_palette) {
+                            Ok(palette) => options.palette = palette,
+                            Err(err) => state.error = Some(err),
+                        }
+                    }
+                    if decode_clicked && state.error.is_none() {
                         let ctx = ctx.clone();
                         let decoding_state = decoding_state.clone();
                         let input_path = input_path.clone();
                         let output_path = output_path.clone();
+                        let options = options.clone();
 
-                        state.error = None;
-                        state.run_state = DecoderRunState::RUNNING;
+                        state.telemetry = None;
+                        state.run_state = DecoderRunState::Running;
                         state.texture = None;
+                        state.composite_texture = None;
+                        state.unenhanced = None;
+                        state.previewing = None;
+                        state.enhanced = None;
+                        state.metadata = None;
 
                         std::thread::spawn(move || {
-                            let decoder_res =
-                                decoder::decode(&input_path, &output_path, |progress, image| {
+                            let decoder_res = decoder::decode(
+                                &input_path,
+                                &output_path,
+                                &options,
+                                |progress, image| {
                                     let mut state = decoding_state.lock().unwrap();
 
                                     state.progress = progress;
@@ -152,15 +359,46 @@ impl eframe::App for DecoderApp {
 
                                     ctx.request_repaint();
 
-                                    return (state.is_running(), state.update_steps);
-                                });
+                                    (state.is_running(), state.update_steps)
+                                },
+                            );
 
                             let mut state = decoding_state.lock().unwrap();
-                            state.run_state = DecoderRunState::DONE;
-                            state.error = match decoder_res {
-                                Err(err) => Some(err),
-                                _ => None,
-                            };
+                            state.run_state = DecoderRunState::Done;
+                            match decoder_res {
+                                Ok(Some(decoded)) => {
+                                    if let Some(composite) = &decoded.composite {
+                                        let size =
+                                            [composite.width() as _, composite.height() as _];
+                                        let color_img = ColorImage::from_rgb(
+                                            size,
+                                            composite.as_flat_samples().as_slice(),
+                                        );
+                                        state.composite_texture = Some(ctx.load_texture(
+                                            "composite-image",
+                                            color_img,
+                                            Default::default(),
+                                        ));
+                                    }
+                                    // The last progress update shows the image before enhancement and rotation
+                                    let size =
+                                        [decoded.image.width() as _, decoded.image.height() as _];
+                                    state.texture = Some(ctx.load_texture(
+                                        "decoded-image",
+                                        ColorImage::from_gray(size, decoded.image.as_raw()),
+                                        Default::default(),
+                                    ));
+                                    state.metadata =
+                                        Some(Metadata::new(&input_path, &decoded, &options));
+                                    state.enhanced =
+                                        Some((decoded.image.clone(), options.enhancement));
+                                    state.unenhanced =
+                                        Some(Arc::new(decoded.unenhanced.unwrap_or(decoded.image)));
+                                    state.telemetry = Some(decoded.telemetry);
+                                }
+                                Ok(None) => {}
+                                Err(err) => state.error = Some(err),
+                            }
 
                             ctx.request_repaint();
                         });
@@ -172,10 +410,76 @@ impl eframe::App for DecoderApp {
                         )
                         .clicked()
                     {
-                        state.run_state = DecoderRunState::CANCELED;
+                        state.run_state = DecoderRunState::Canceled;
+                    }
+
+                    // The output file still has the enhancement it was decoded with
+                    let unsaved = match (&state.enhanced, &state.metadata) {
+                        (Some((_, enhancement)), Some(metadata)) => {
+                            *enhancement != metadata.enhancement
+                        }
+                        _ => false,
+                    };
+                    if ui
+                        .add_enabled(
+                            !state.is_running() && unsaved,
+                            Button::new("Save enhanced image"),
+                        )
+                        .clicked()
+                    {
+                        let state = &mut *state;
+                        if let (Some((image, enhancement)), Some(metadata)) =
+                            (&state.enhanced, &mut state.metadata)
+                        {
+                            metadata.enhancement = *enhancement;
+                            if let Err(err) =
+                                decoder::save_images(output_path, image, metadata, options)
+                            {
+                                state.error = Some(err);
+                            }
+                        }
                     }
                 });
 
+                // Previews changed enhancement settings on the decoded image.
+                // Enhancing takes a while, so it runs in the background, one setting at a time,
+                // the latest setting is picked up once the previous one is done.
+                if !state.is_running() && state.previewing.is_none() {
+                    let outdated = match &state.enhanced {
+                        Some((_, enhancement)) => *enhancement != options.enhancement,
+                        None => false,
+                    };
+                    if let (true, Some(unenhanced)) = (outdated, &state.unenhanced) {
+                        let ctx = ctx.clone();
+                        let decoding_state = decoding_state.clone();
+                        let unenhanced = unenhanced.clone();
+                        let enhancement = options.enhancement;
+                        state.previewing = Some(enhancement);
+
+                        std::thread::spawn(move || {
+                            let enhanced = enhancement.apply(&unenhanced);
+
+                            let mut state = decoding_state.lock().unwrap();
+                            // A new decoding job replaces the image being enhanced
+                            let current = match &state.unenhanced {
+                                Some(image) => Arc::ptr_eq(image, &unenhanced),
+                                None => false,
+                            };
+                            if current {
+                                let size = [enhanced.width() as _, enhanced.height() as _];
+                                state.texture = Some(ctx.load_texture(
+                                    "decoded-image",
+                                    ColorImage::from_gray(size, enhanced.as_raw()),
+                                    Default::default(),
+                                ));
+                                state.enhanced = Some((enhanced, enhancement));
+                                state.previewing = None;
+                            }
+                            ctx.request_repaint();
+                        });
+                    }
+                }
+
                 let progressbar = ProgressBar::new(state.progress).show_percentage();
                 ui.add(progressbar);
                 ui.end_row();
@@ -184,12 +488,28 @@ impl eframe::App for DecoderApp {
                     ui.label(RichText::new(err.to_string()).color(Color32::RED));
                 };
 
+                if let Some(telemetry) = &state.telemetry {
+                    ui.label(format!(
+                        "Channel A: {}    Channel B: {}",
+                        channel_name(telemetry.channel_a),
+                        channel_name(telemetry.channel_b)
+                    ));
+                }
+
+                if state.composite_texture.is_some() {
+                    ui.checkbox(show_composite, "Show composite");
+                }
+
                 ui.separator();
 
                 let image_size = ui.available_size();
                 state.update_steps = image_size[1] as u32;
 
-                if let Some(texture) = &state.texture {
+                let texture = match (&state.composite_texture, *show_composite) {
+                    (Some(composite), true) => Some(composite),
+                    _ => state.texture.as_ref(),
+                };
+                if let Some(texture) = texture {
                     ui.add(Image::new(texture).shrink_to_fit());
                 }
             });
@@ -1,31 +1,451 @@
-use std;
-
-extern crate hound;
-
-type FileReader = std::io::BufReader<std::fs::File>;
-
-pub fn float_sample_iterator<'a>(
-    reader: &'a mut hound::WavReader<FileReader>,
-) -> Box<dyn Iterator<Item = f32> + 'a> {
-    match reader.spec().sample_format {
-        hound::SampleFormat::Float => Box::new(reader.samples::<f32>().map(|x| x.unwrap())),
-        hound::SampleFormat::Int => match reader.spec().bits_per_sample {
-            8 => Box::new(
-                reader
-                    .samples::<i8>()
-                    .map(|x| (x.unwrap() as f32) / (i16::max_value() as f32)),
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, ErrorKind, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::str::FromStr;
+
+use num_complex::Complex;
+
+use errors::DecoderError;
+use orbit::parse_time;
+
+type InputReader = BufReader<Box<dyn Read>>;
+type WavSamples<'a, T> = Box<dyn Iterator<Item = Result<T, hound::Error>> + 'a>;
+
+/// Channel of a stereo or multi channel WAV file to decode.
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub enum ChannelSelection {
+    /// First channel
+    Left,
+    /// Second channel
+    Right,
+    /// Average of all channels
+    #[default]
+    Mix,
+    /// Left and right channel as I and Q of a complex baseband recording
+    IQ,
+}
+
+impl FromStr for ChannelSelection {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "left" => Ok(ChannelSelection::Left),
+            "right" => Ok(ChannelSelection::Right),
+            "mix" => Ok(ChannelSelection::Mix),
+            "iq" => Ok(ChannelSelection::IQ),
+            _ => Err(format!("Unknown channel {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for ChannelSelection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            ChannelSelection::Left => "Left",
+            ChannelSelection::Right => "Right",
+            ChannelSelection::Mix => "Mix",
+            ChannelSelection::IQ => "Left/Right as I/Q",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// Reads the interleaved samples of all channels as `f32`.
+fn interleaved_sample_iterator<'a, R>(
+    reader: &'a mut hound::WavReader<R>,
+) -> Result<WavSamples<'a, f32>, DecoderError>
+where
+    R: Read,
+{
+    let spec = reader.spec();
+    match (spec.sample_format, spec.bits_per_sample) {
+        (hound::SampleFormat::Float, 32) => Ok(Box::new(reader.samples::<f32>())),
+        (hound::SampleFormat::Int, 8) => Ok(Box::new(
+            reader
+                .samples::<i8>()
+                .map(|x| x.map(|x| (x as f32) / (i8::MAX as f32))),
+        )),
+        (hound::SampleFormat::Int, 16) => Ok(Box::new(
+            reader
+                .samples::<i16>()
+                .map(|x| x.map(|x| (x as f32) / (i16::MAX as f32))),
+        )),
+        // hound reads 24 bit samples as i32
+        (hound::SampleFormat::Int, 24) => Ok(Box::new(
+            reader
+                .samples::<i32>()
+                .map(|x| x.map(|x| (x as f32) / (((1 << 23) - 1) as f32))),
+        )),
+        (hound::SampleFormat::Int, 32) => Ok(Box::new(
+            reader
+                .samples::<i32>()
+                .map(|x| x.map(|x| (x as f32) / (i32::MAX as f32))),
+        )),
+        (_, bits) => Err(DecoderError::UnsupportedSampleFormat(bits)),
+    }
+}
+
+// Groups the interleaved samples into frames holding one sample of each channel.
+fn frame_iterator<'a, R>(
+    reader: &'a mut hound::WavReader<R>,
+) -> Result<impl Iterator<Item = Result<Vec<f32>, hound::Error>> + 'a, DecoderError>
+where
+    R: Read,
+{
+    let channels = reader.spec().channels as usize;
+    let mut samples = interleaved_sample_iterator(reader)?;
+
+    Ok(std::iter::from_fn(move || {
+        let mut frame = Vec::with_capacity(channels);
+        for _ in 0..channels {
+            match samples.next()? {
+                Ok(sample) => frame.push(sample),
+                Err(error) => return Some(Err(error)),
+            }
+        }
+        Some(Ok(frame))
+    }))
+}
+
+/// Reads the samples of a WAV file as `f32`, independent of the sample format of the file.
+///
+/// Files with more than one channel are reduced to the selected channel,
+/// mono files are read as they are.
+/// Fails for sample formats other than 32bit float and 8, 16, 24 or 32bit integer
+/// and if `selection` is [`ChannelSelection::IQ`], see [`complex_wav_iterator`] instead.
+pub fn float_sample_iterator<'a, R>(
+    reader: &'a mut hound::WavReader<R>,
+    selection: ChannelSelection,
+) -> Result<WavSamples<'a, f32>, DecoderError>
+where
+    R: Read,
+{
+    let channels = reader.spec().channels;
+    if channels == 1 {
+        return interleaved_sample_iterator(reader);
+    }
+
+    let frames = frame_iterator(reader)?;
+    match selection {
+        ChannelSelection::Left => Ok(Box::new(frames.map(|frame| frame.map(|f| f[0])))),
+        ChannelSelection::Right => Ok(Box::new(frames.map(|frame| frame.map(|f| f[1])))),
+        ChannelSelection::Mix => {
+            Ok(Box::new(frames.map(|frame| {
+                frame.map(|f| f.iter().sum::<f32>() / f.len() as f32)
+            })))
+        }
+        ChannelSelection::IQ => Err(DecoderError::UnsupportedChannelCount(channels)),
+    }
+}
+
+/// Reads a stereo WAV file as complex baseband samples, left is I and right is Q.
+///
+/// Fails for files with other than two channels.
+pub fn complex_wav_iterator<'a, R>(
+    reader: &'a mut hound::WavReader<R>,
+) -> Result<WavSamples<'a, Complex<f32>>, DecoderError>
+where
+    R: Read,
+{
+    let channels = reader.spec().channels;
+    if channels != 2 {
+        return Err(DecoderError::UnsupportedChannelCount(channels));
+    }
+
+    let frames = frame_iterator(reader)?;
+    Ok(Box::new(
+        frames.map(|frame| frame.map(|f| Complex::new(f[0], f[1]))),
+    ))
+}
+
+/// Opens `path` for reading, `-` reads from stdin.
+///
+/// Returns the reader and the length of the input in bytes,
+/// the length is unknown for stdin and named pipes.
+pub fn open_input(path: &str) -> std::io::Result<(InputReader, Option<u64>)> {
+    if path == "-" {
+        return Ok((BufReader::new(Box::new(std::io::stdin().lock())), None));
+    }
+
+    let file = File::open(path)?;
+    let metadata = file.metadata()?;
+    let length = if metadata.is_file() {
+        Some(metadata.len())
+    } else {
+        None
+    };
+
+    Ok((BufReader::new(Box::new(file)), length))
+}
+
+/// Reads the time the WAV file at `path` was recorded from its metadata, as unix timestamp.
+///
+/// The origination date and time of Broadcast WAV `bext` chunks
+/// and the `ICRD` entry of `LIST INFO` chunks are used, both are assumed to be in UTC.
+/// Returns `None` if the file contains neither.
+pub fn wav_recording_time(path: &Path) -> Option<f64> {
+    let mut file = File::open(path).ok()?;
+    let mut header = [0; 12];
+    file.read_exact(&mut header).ok()?;
+    if &header[0..4] != b"RIFF" || &header[8..12] != b"WAVE" {
+        return None;
+    }
+
+    let mut chunk_header = [0; 8];
+    while file.read_exact(&mut chunk_header).is_ok() {
+        let size = u32::from_le_bytes([
+            chunk_header[4],
+            chunk_header[5],
+            chunk_header[6],
+            chunk_header[7],
+        ]) as u64;
+        // Chunks are padded to an even length
+        let padded = size + size % 2;
+
+        let time = match &chunk_header[0..4] {
+            b"bext" | b"LIST" if size <= 65536 => {
+                let mut data = vec![0; size as usize];
+                file.read_exact(&mut data).ok()?;
+                file.seek(SeekFrom::Current((padded - size) as i64)).ok()?;
+                if &chunk_header[0..4] == b"bext" {
+                    bext_time(&data)
+                } else {
+                    info_time(&data)
+                }
+            }
+            _ => {
+                file.seek(SeekFrom::Current(padded as i64)).ok()?;
+                None
+            }
+        };
+        if time.is_some() {
+            return time;
+        }
+    }
+
+    None
+}
+
+// OriginationDate and OriginationTime follow the description, originator and reference fields.
+fn bext_time(data: &[u8]) -> Option<f64> {
+    let date = std::str::from_utf8(data.get(320..330)?).ok()?;
+    let time = std::str::from_utf8(data.get(330..338)?).ok()?;
+    // The standard allows any separator in the date and time
+    let date = date.replace(|c: char| !c.is_ascii_digit(), "-");
+    let time = time.replace(|c: char| !c.is_ascii_digit(), ":");
+    parse_time(&format!("{}T{}", date, time)).ok()
+}
+
+fn info_time(data: &[u8]) -> Option<f64> {
+    if data.get(0..4)? != b"INFO" {
+        return None;
+    }
+
+    let mut pos = 4;
+    while pos + 8 <= data.len() {
+        let id = &data[pos..pos + 4];
+        let size = u32::from_le_bytes([data[pos + 4], data[pos + 5], data[pos + 6], data[pos + 7]])
+            as usize;
+        let value = data.get(pos + 8..pos + 8 + size)?;
+        if id == b"ICRD" {
+            let value = std::str::from_utf8(value).ok()?;
+            return parse_time(value.trim_end_matches('\0').trim()).ok();
+        }
+        pos += 8 + size + size % 2;
+    }
+
+    None
+}
+
+/// Sample formats of headerless mono audio streams.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RawFormat {
+    /// 32bit float, e.g. from `sox -t f32`
+    F32,
+    /// Signed 16bit, e.g. from `rtl_fm`
+    S16,
+}
+
+impl RawFormat {
+    /// Size of a single sample in bytes.
+    pub fn sample_size(&self) -> usize {
+        match self {
+            RawFormat::F32 => 4,
+            RawFormat::S16 => 2,
+        }
+    }
+
+    fn convert(&self, bytes: &[u8]) -> f32 {
+        match self {
+            RawFormat::F32 => f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+            RawFormat::S16 => i16::from_le_bytes([bytes[0], bytes[1]]) as f32 / (i16::MAX as f32),
+        }
+    }
+}
+
+/// Reads mono samples in the given format from `reader` until it is exhausted.
+///
+/// Read errors other than running out of data are passed on, a partial sample at the end is dropped.
+pub fn raw_sample_iterator<'a, R>(
+    mut reader: R,
+    format: RawFormat,
+) -> Box<dyn Iterator<Item = io::Result<f32>> + 'a>
+where
+    R: Read + 'a,
+{
+    let mut buffer = vec![0; format.sample_size()];
+    Box::new(std::iter::from_fn(move || {
+        read_sample(&mut reader, &mut buffer).map(|read| read.map(|_| format.convert(&buffer)))
+    }))
+}
+
+/// Sample formats of interleaved IQ recordings.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum IQFormat {
+    /// 32bit float I and Q, e.g. from SDR# or GNU Radio
+    ComplexF32,
+    /// Unsigned 8bit I and Q, e.g. from rtl_sdr
+    ComplexU8,
+    /// Signed 16bit I and Q, e.g. from SDRplay or HackRF tools
+    ComplexS16,
+}
+
+impl IQFormat {
+    /// Size of a single IQ sample in bytes.
+    pub fn sample_size(&self) -> usize {
+        match self {
+            IQFormat::ComplexF32 => 8,
+            IQFormat::ComplexU8 => 2,
+            IQFormat::ComplexS16 => 4,
+        }
+    }
+
+    fn convert(&self, bytes: &[u8]) -> Complex<f32> {
+        match self {
+            IQFormat::ComplexF32 => Complex::new(
+                f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+                f32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]),
             ),
-            16 => Box::new(
-                reader
-                    .samples::<i16>()
-                    .map(|x| (x.unwrap() as f32) / (i16::max_value() as f32)),
+            IQFormat::ComplexU8 => Complex::new(
+                (bytes[0] as f32 - 127.5) / 127.5,
+                (bytes[1] as f32 - 127.5) / 127.5,
             ),
-            32 => Box::new(
-                reader
-                    .samples::<i32>()
-                    .map(|x| (x.unwrap() as f32) / (i32::max_value() as f32)),
+            IQFormat::ComplexS16 => Complex::new(
+                i16::from_le_bytes([bytes[0], bytes[1]]) as f32 / (i16::MAX as f32),
+                i16::from_le_bytes([bytes[2], bytes[3]]) as f32 / (i16::MAX as f32),
             ),
-            _ => panic!("Unsupported sample format"),
-        },
+        }
+    }
+}
+
+/// Reads interleaved IQ samples in the given format from `reader` until it is exhausted.
+///
+/// Read errors other than running out of data are passed on, a partial sample at the end is dropped.
+pub fn complex_sample_iterator<'a, R>(
+    mut reader: R,
+    format: IQFormat,
+) -> Box<dyn Iterator<Item = io::Result<Complex<f32>>> + 'a>
+where
+    R: Read + 'a,
+{
+    let mut buffer = vec![0; format.sample_size()];
+    Box::new(std::iter::from_fn(move || {
+        read_sample(&mut reader, &mut buffer).map(|read| read.map(|_| format.convert(&buffer)))
+    }))
+}
+
+// Fills `buffer` with the next sample, returns `None` at the end of the input.
+fn read_sample<R: Read>(reader: &mut R, buffer: &mut [u8]) -> Option<io::Result<()>> {
+    match reader.read_exact(buffer) {
+        Ok(()) => Some(Ok(())),
+        Err(error) if error.kind() == ErrorKind::UnexpectedEof => None,
+        Err(error) => Some(Err(error)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    // WAV file in memory with the interleaved `samples`.
+    fn wav<S: hound::Sample + Copy>(spec: hound::WavSpec, samples: &[S]) -> Cursor<Vec<u8>> {
+        let mut data = Cursor::new(Vec::new());
+        let mut writer = hound::WavWriter::new(&mut data, spec).unwrap();
+        for sample in samples {
+            writer.write_sample(*sample).unwrap();
+        }
+        writer.finalize().unwrap();
+        data.set_position(0);
+        data
+    }
+
+    #[test]
+    fn reads_24_bit_samples() {
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: 48000,
+            bits_per_sample: 24,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let max = (1 << 23) - 1;
+        let mut reader = hound::WavReader::new(wav(spec, &[0i32, max, -max, max / 2])).unwrap();
+        let samples: Vec<f32> = float_sample_iterator(&mut reader, ChannelSelection::Mix)
+            .unwrap()
+            .map(|sample| sample.unwrap())
+            .collect();
+        assert_eq!(samples.len(), 4);
+        for (sample, expected) in samples.iter().zip([0.0, 1.0, -1.0, 0.5]) {
+            assert!(
+                (sample - expected).abs() < 1e-6,
+                "{} != {}",
+                sample,
+                expected
+            );
+        }
+    }
+
+    // Stereo file with the left samples 0.1, 0.2, 0.3 and the right samples -0.5, 0.0, 0.5.
+    fn stereo() -> hound::WavReader<Cursor<Vec<u8>>> {
+        let spec = hound::WavSpec {
+            channels: 2,
+            sample_rate: 48000,
+            bits_per_sample: 32,
+            sample_format: hound::SampleFormat::Float,
+        };
+        let samples = [0.1f32, -0.5, 0.2, 0.0, 0.3, 0.5];
+        hound::WavReader::new(wav(spec, &samples)).unwrap()
+    }
+
+    fn read(selection: ChannelSelection) -> Vec<f32> {
+        let mut reader = stereo();
+        float_sample_iterator(&mut reader, selection)
+            .unwrap()
+            .map(|sample| sample.unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn selects_channel() {
+        assert_eq!(read(ChannelSelection::Left), vec![0.1, 0.2, 0.3]);
+        assert_eq!(read(ChannelSelection::Right), vec![-0.5, 0.0, 0.5]);
+        assert_eq!(read(ChannelSelection::Mix), vec![-0.2, 0.1, 0.4]);
+
+        let mut reader = stereo();
+        assert!(float_sample_iterator(&mut reader, ChannelSelection::IQ).is_err());
+        let iq: Vec<Complex<f32>> = complex_wav_iterator(&mut reader)
+            .unwrap()
+            .map(|sample| sample.unwrap())
+            .collect();
+        assert_eq!(
+            iq,
+            vec![
+                Complex::new(0.1, -0.5),
+                Complex::new(0.2, 0.0),
+                Complex::new(0.3, 0.5)
+            ]
+        );
     }
 }
@@ -0,0 +1,108 @@
+//! Decodes a synthetic APT signal and checks lines, syncs and telemetry.
+
+extern crate apt_decoder;
+
+use std::f32::consts::PI;
+
+use apt_decoder::decoder::{
+    decode_samples, DecoderOptions, IMAGE_A_START, IMAGE_WIDTH, PIXELS_PER_LINE, PIXEL_RATE,
+};
+use apt_decoder::telemetry::{AVHRRChannel, LINES_PER_WEDGE, TELEMETRY_A_START, WEDGES};
+
+const SAMPLE_RATE: u32 = 20800;
+const SUBCARRIER: f32 = 2400.0;
+const LINES: usize = 300;
+
+// Sync A is 7 pulses of 1040Hz, sync B 7 pulses of 832Hz, both starting after 4 dark pixels.
+fn sync_a(x: usize) -> f32 {
+    if (4..32).contains(&x) && (x - 4) % 4 < 2 {
+        1.0
+    } else {
+        0.0
+    }
+}
+
+fn sync_b(x: usize) -> f32 {
+    if (4..39).contains(&x) && (x - 4) % 5 < 3 {
+        1.0
+    } else {
+        0.0
+    }
+}
+
+// Wedges 1 - 8 ramp up, wedge 9 is zero, wedge 16 repeats the wedge of the AVHRR channel.
+fn wedge(line: usize, channel_wedge: usize) -> f32 {
+    match (line / LINES_PER_WEDGE) % WEDGES {
+        w @ 0..=7 => (w + 1) as f32 / 8.0,
+        8 => 0.0,
+        15 => channel_wedge as f32 / 8.0,
+        _ => 0.4,
+    }
+}
+
+// Level of every pixel, channel A carries AVHRR 2, channel B AVHRR 4.
+fn pixel(line: usize, x: usize) -> f32 {
+    let half = PIXELS_PER_LINE as usize / 2;
+    let (column, channel_wedge) = (x % half, if x < half { 2 } else { 4 });
+    if column < 39 {
+        if x < half {
+            sync_a(column)
+        } else {
+            sync_b(column)
+        }
+    } else if column < IMAGE_A_START {
+        // Space view
+        0.1
+    } else if column < TELEMETRY_A_START {
+        0.5 + 0.3 * ((column - IMAGE_A_START) as f32 / IMAGE_WIDTH as f32 * 6.0 * PI).sin()
+    } else {
+        wedge(line, channel_wedge)
+    }
+}
+
+// AM modulates the pixel levels onto the 2400Hz subcarrier.
+fn synthetic_signal() -> Vec<f32> {
+    let samples_per_pixel = (SAMPLE_RATE / PIXEL_RATE) as usize;
+    let mut samples = Vec::new();
+    for line in 0..LINES {
+        for x in 0..PIXELS_PER_LINE as usize {
+            let level = 0.05 + 0.9 * pixel(line, x);
+            for _ in 0..samples_per_pixel {
+                let t = samples.len() as f32 / SAMPLE_RATE as f32;
+                samples.push(level * (2.0 * PI * SUBCARRIER * t).sin());
+            }
+        }
+    }
+    samples
+}
+
+#[test]
+fn decodes_synthetic_signal() {
+    let samples = synthetic_signal();
+    let count = samples.len() as u32;
+    let decoded = decode_samples(
+        samples.into_iter(),
+        SAMPLE_RATE,
+        Some(count),
+        &DecoderOptions::default(),
+        |_, _| (true, 10),
+    )
+    .expect("Decoding failed")
+    .expect("Decoding was canceled");
+
+    assert_eq!(decoded.lines.len(), LINES);
+    assert_eq!(decoded.image.height() as usize, LINES);
+    assert_eq!(decoded.image.width(), PIXELS_PER_LINE);
+
+    let synced = decoded.sync_quality.iter().filter(|q| **q > 0.7).count();
+    assert!(
+        synced >= LINES - 2,
+        "Only {} of {} lines synced",
+        synced,
+        LINES
+    );
+
+    assert!(!decoded.telemetry.frames.is_empty());
+    assert_eq!(decoded.telemetry.channel_a, Some(AVHRRChannel::Ch2));
+    assert_eq!(decoded.telemetry.channel_b, Some(AVHRRChannel::Ch4));
+}